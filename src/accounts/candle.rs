@@ -1,6 +1,10 @@
 use crate::shared::candle_data::CandleData;
 use crate::shared::candle_index::CandleIndex;
 use crate::shared::candle_interval::CandleInterval;
+use crate::shared::gap_fill::FlatCandle;
+use crate::shared::ohlc::Ohlc;
+use crate::shared::snapshot::SnapshotDatetimes;
+use crate::shared::symbols::Symbol;
 use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
 
@@ -11,11 +15,19 @@ pub struct AccountData {
     pub pnl: f64,
 }
 
+/// Account data of the ref_id at the date
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AccountSnapshot {
+    pub date: DateTime<Utc>,
+    pub ref_id: String,
+    pub data: AccountData,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct AccountCandle {
     pub interval: CandleInterval,
     pub date: DateTime<Utc>,
-    pub ref_id: String,
+    pub ref_id: Symbol,
     pub balance_data: CandleData,
     pub equity_data: CandleData,
     pub pnl_data: CandleData,
@@ -23,24 +35,80 @@ pub struct AccountCandle {
 
 impl From<&AccountCandle> for CandleIndex {
     fn from(value: &AccountCandle) -> Self {
-        Self::new(value.ref_id.to_string(), value.interval, value.date)
+        Self::new(value.ref_id, value.interval, value.date)
     }
 }
 
 impl AccountCandle {
     pub fn new(index: CandleIndex, data: &AccountData) -> Self {
+        Self::new_at(index, data, Utc::now())
+    }
+
+    /// Creates the candle with data timestamps set to the specified date instead of now
+    pub fn new_at(index: CandleIndex, data: &AccountData, timestamp: DateTime<Utc>) -> Self {
         Self {
             interval: index.candle_interval,
             date: index.interval_start_date,
-            ref_id: index.ref_id.clone(),
-            balance_data: CandleData::new(data.balance),
-            equity_data: CandleData::new(data.equity),
-            pnl_data: CandleData::new(data.pnl),
+            ref_id: index.ref_id,
+            balance_data: CandleData::new_at(data.balance, timestamp),
+            equity_data: CandleData::new_at(data.equity, timestamp),
+            pnl_data: CandleData::new_at(data.pnl, timestamp),
         }
     }
+
     pub fn update(&mut self, data: &AccountData) {
-        self.balance_data.update(data.balance);
-        self.equity_data.update(data.equity);
-        self.pnl_data.update(data.pnl);
+        self.update_at(data, Utc::now());
+    }
+
+    pub fn update_at(&mut self, data: &AccountData, timestamp: DateTime<Utc>) {
+        self.balance_data.update_at(data.balance, timestamp);
+        self.equity_data.update_at(data.equity, timestamp);
+        self.pnl_data.update_at(data.pnl, timestamp);
+    }
+
+    pub fn get_ohlc(&self, value: AccountValue) -> Ohlc {
+        match value {
+            AccountValue::Balance => Ohlc::from(&self.balance_data),
+            AccountValue::Equity => Ohlc::from(&self.equity_data),
+            AccountValue::Pnl => Ohlc::from(&self.pnl_data),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AccountValue {
+    Balance,
+    Equity,
+    Pnl,
+}
+
+impl SnapshotDatetimes for AccountCandle {
+    fn get_datetimes(&self) -> Vec<DateTime<Utc>> {
+        vec![
+            self.balance_data.timestamp,
+            self.equity_data.timestamp,
+            self.pnl_data.timestamp,
+        ]
+    }
+
+    fn set_datetimes(&mut self, datetimes: &[DateTime<Utc>]) {
+        self.balance_data.timestamp = datetimes[0];
+        self.equity_data.timestamp = datetimes[1];
+        self.pnl_data.timestamp = datetimes[2];
+    }
+}
+
+impl FlatCandle for AccountCandle {
+    fn to_flat(&self, date: DateTime<Utc>) -> Self {
+        let data = AccountData {
+            equity: self.equity_data.close,
+            balance: self.balance_data.close,
+            pnl: self.pnl_data.close,
+        };
+
+        let index = CandleIndex::new(self.ref_id, self.interval, date);
+        let start_date = index.interval_start_date;
+
+        Self::new_at(index, &data, start_date)
     }
 }
@@ -0,0 +1,126 @@
+use crate::accounts::candle::{AccountCandle, AccountSnapshot};
+use crate::accounts::candles_cache::AccountCandlesCache;
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::events::{CandleEvent, CandleEventKind};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver};
+use std::task::{Context, Poll};
+
+/// Turns a stream of account snapshots into a stream of candle events of the configured
+/// intervals. Snapshots are pulled from the source only when the consumer polls for the next
+/// event and all events of the previous snapshot are consumed
+pub struct AccountCandleStream<S> {
+    snapshots: S,
+    cache: AccountCandlesCache,
+    events: Receiver<CandleEvent<AccountCandle>>,
+    kinds: Vec<CandleEventKind>,
+}
+
+impl<S: Stream<Item = AccountSnapshot> + Unpin> AccountCandleStream<S> {
+    pub fn new(snapshots: S, candle_intervals: Vec<CandleInterval>) -> Self {
+        Self::with_cache(snapshots, AccountCandlesCache::new(candle_intervals))
+    }
+
+    /// Uses preconfigured cache (retention, budget etc.) to build candles
+    pub fn with_cache(snapshots: S, cache: AccountCandlesCache) -> Self {
+        let mut cache = cache;
+        let (sender, events) = channel();
+        cache.add_listener(Box::new(sender));
+
+        Self {
+            snapshots,
+            cache,
+            events,
+            kinds: vec![
+                CandleEventKind::Opened,
+                CandleEventKind::Updated,
+                CandleEventKind::Closed,
+            ],
+        }
+    }
+
+    /// Yields only events of closed candles
+    pub fn closed_only(self) -> Self {
+        self.with_kinds(vec![CandleEventKind::Closed])
+    }
+
+    pub fn with_kinds(mut self, kinds: Vec<CandleEventKind>) -> Self {
+        self.kinds = kinds;
+
+        self
+    }
+
+    pub fn get_cache(&self) -> &AccountCandlesCache {
+        &self.cache
+    }
+
+    pub fn into_cache(self) -> AccountCandlesCache {
+        self.cache
+    }
+}
+
+impl<S: Stream<Item = AccountSnapshot> + Unpin> Stream for AccountCandleStream<S> {
+    type Item = CandleEvent<AccountCandle>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            while let Ok(event) = this.events.try_recv() {
+                if this.kinds.contains(&event.kind) {
+                    return Poll::Ready(Some(event));
+                }
+            }
+
+            match Pin::new(&mut this.snapshots).poll_next(cx) {
+                Poll::Ready(Some(snapshot)) => this.cache.insert_snapshot(&snapshot),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::candle::AccountData;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use tokio_stream::wrappers::ReceiverStream;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn closed_candles_from_channel() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let producer = tokio::spawn(async move {
+            for i in 0..6 {
+                let snapshot = AccountSnapshot {
+                    date: date + Duration::seconds(i * 30),
+                    ref_id: "1".to_string(),
+                    data: AccountData {
+                        equity: 1000.0 + i as f64,
+                        balance: 1000.0,
+                        pnl: i as f64,
+                    },
+                };
+                sender.send(snapshot).await.unwrap();
+            }
+        });
+        let stream =
+            AccountCandleStream::new(ReceiverStream::new(receiver), vec![CandleInterval::Minute]);
+
+        let candles: Vec<_> = stream
+            .closed_only()
+            .map(|event| event.candle)
+            .collect()
+            .await;
+        producer.await.unwrap();
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].equity_data.open, 1000.0);
+        assert_eq!(candles[0].equity_data.close, 1001.0);
+        assert_eq!(candles[1].date, date + Duration::minutes(1));
+    }
+}
@@ -1,14 +1,39 @@
-use crate::accounts::candle::{AccountCandle, AccountData};
+use crate::accounts::candle::{AccountCandle, AccountData, AccountSnapshot};
+use crate::shared::batch::BatchItemOutcome;
+use crate::shared::budget::{select_evictions, CacheBudget, EvictionCandidate, EvictionStrategy};
 use crate::shared::candle_index::CandleIndex;
 use crate::shared::candle_interval::CandleInterval;
-use crate::shared::utils::calculate_candle_dates;
-use ahash::AHashMap;
+use crate::shared::changes::{ChangeSet, ChangeTracker};
+use crate::shared::events::{notify, CandleEventKind, CandleListener};
+use crate::shared::gap_fill::{get_range_filled, FlatCandle, GapFill, RangeCandle};
+use crate::shared::interval_overrides::{IntervalOverrides, KeyPattern};
+use crate::shared::replay::{ChecksumHasher, ReplayError, ReplayOrder};
+use crate::shared::retention::{RetentionIndex, RetentionPolicy};
+use crate::shared::snapshot::{
+    read_snapshot, write_snapshot, CacheSnapshot, SnapshotCandle, SnapshotDatetimes, SnapshotError,
+    SNAPSHOT_VERSION,
+};
+use crate::shared::symbols::{Symbol, SymbolRegistry};
+use ahash::{AHashMap, AHashSet};
 use chrono::{DateTime, Utc};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 pub struct AccountCandlesCache {
     candles_by_indexes: AHashMap<CandleIndex, AccountCandle>,
-    pub intervals: Vec<CandleInterval>,
+    intervals: Vec<CandleInterval>,
     pub last_update_date: Option<DateTime<Utc>>,
+    retention_policies: AHashMap<CandleInterval, RetentionPolicy>,
+    retention_index: RetentionIndex,
+    budget: Option<CacheBudget>,
+    memory_usage: usize,
+    read_clock: AtomicU64,
+    read_stamps: AHashMap<CandleIndex, AtomicU64>,
+    changes: Option<ChangeTracker<CandleIndex>>,
+    listeners: Vec<Box<dyn CandleListener<AccountCandle>>>,
+    forming_dates: AHashMap<Symbol, AHashMap<CandleInterval, DateTime<Utc>>>,
+    closed_indexes: AHashSet<CandleIndex>,
+    interval_overrides: IntervalOverrides,
 }
 
 impl AccountCandlesCache {
@@ -21,9 +46,272 @@ impl AccountCandlesCache {
             candles_by_indexes: AHashMap::new(),
             intervals: candle_intervals,
             last_update_date: None,
+            retention_policies: AHashMap::new(),
+            retention_index: RetentionIndex::default(),
+            budget: None,
+            memory_usage: 0,
+            read_clock: AtomicU64::new(0),
+            read_stamps: AHashMap::new(),
+            changes: None,
+            listeners: Vec::new(),
+            forming_dates: AHashMap::new(),
+            closed_indexes: AHashSet::new(),
+            interval_overrides: IntervalOverrides::new(),
         }
     }
 
+    /// Overrides intervals of ref_ids matching the pattern. Existing candles of intervals
+    /// which are no longer used by their ref_ids are removed
+    pub fn set_intervals_override(&mut self, pattern: KeyPattern, intervals: Vec<CandleInterval>) {
+        self.interval_overrides.set(pattern, intervals);
+        self.remove_unused_intervals();
+    }
+
+    /// Removes the override and existing candles of intervals which are no longer used
+    /// by their ref_ids
+    pub fn remove_intervals_override(
+        &mut self,
+        pattern: &KeyPattern,
+    ) -> Option<Vec<CandleInterval>> {
+        let intervals = self.interval_overrides.remove(pattern)?;
+        self.remove_unused_intervals();
+
+        Some(intervals)
+    }
+
+    /// Gets overridden intervals of the ref_id or the cache intervals
+    pub fn get_ref_id_intervals(&self, ref_id: &str) -> &[CandleInterval] {
+        self.interval_overrides
+            .resolve(ref_id)
+            .unwrap_or(&self.intervals)
+    }
+
+    /// Gets the sorted intervals of the cache. Intervals are changed only by `add_interval`
+    /// and `remove_interval`, which keep candles of the intervals consistent
+    pub fn get_intervals(&self) -> &[CandleInterval] {
+        &self.intervals
+    }
+
+    /// Adds the interval to the cache intervals. Backfill builds candles of the interval
+    /// from cached candles of the coarsest interval fitting into it, the latest backfilled
+    /// candle of every ref_id is forming. Returns backfilled candles count
+    pub fn add_interval(&mut self, interval: CandleInterval, backfill: bool) -> usize {
+        if self.intervals.contains(&interval) {
+            return 0;
+        }
+
+        self.intervals.push(interval);
+        self.intervals.sort();
+
+        if !backfill {
+            return 0;
+        }
+
+        let mut sources: AHashMap<Symbol, CandleInterval> = AHashMap::new();
+
+        for candle in self.candles_by_indexes.values() {
+            if !candle.interval.fits_into(interval)
+                || !self
+                    .get_ref_id_intervals(&candle.ref_id)
+                    .contains(&interval)
+            {
+                continue;
+            }
+
+            let source = sources.entry(candle.ref_id).or_insert(candle.interval);
+
+            if get_seconds(candle.interval) > get_seconds(*source) {
+                *source = candle.interval;
+            }
+        }
+
+        let mut source_candles: Vec<&AccountCandle> = self
+            .candles_by_indexes
+            .values()
+            .filter(|candle| sources.get(&candle.ref_id) == Some(&candle.interval))
+            .collect();
+        source_candles.sort_by_key(|candle| (candle.ref_id, candle.date));
+        let mut candles: Vec<AccountCandle> = Vec::new();
+
+        for source in source_candles {
+            let date = interval.get_start_date(source.date);
+
+            match candles.last_mut() {
+                Some(candle) if candle.ref_id == source.ref_id && candle.date == date => {
+                    candle.balance_data.merge(&source.balance_data);
+                    candle.equity_data.merge(&source.equity_data);
+                    candle.pnl_data.merge(&source.pnl_data);
+                }
+                _ => {
+                    let mut candle = source.clone();
+                    candle.interval = interval;
+                    candle.date = date;
+                    candles.push(candle);
+                }
+            }
+        }
+
+        let mut backfilled_count = 0;
+
+        for candle in candles.iter() {
+            let index = CandleIndex::from(candle);
+
+            if self.candles_by_indexes.contains_key(&index) {
+                continue;
+            }
+
+            self.update_forming_date(candle.ref_id, candle.interval, candle.date);
+            self.insert_candle(index, candle.clone());
+            backfilled_count += 1;
+        }
+
+        self.enforce_budget();
+
+        backfilled_count
+    }
+
+    /// Removes the interval from the cache intervals and purges its candles of ref_ids
+    /// which overridden intervals don't contain it. Returns removed candles count
+    pub fn remove_interval(&mut self, interval: CandleInterval) -> usize {
+        self.intervals.retain(|existing| *existing != interval);
+        let interval_overrides = self.interval_overrides.clone();
+        let intervals = self.intervals.clone();
+        let is_purged = |ref_id: &str| {
+            !interval_overrides
+                .resolve(ref_id)
+                .unwrap_or(&intervals)
+                .contains(&interval)
+        };
+
+        for (ref_id, dates) in self.forming_dates.iter_mut() {
+            if is_purged(ref_id) {
+                dates.remove(&interval);
+            }
+        }
+
+        let removed_counts = self.remove_where(|_index, candle| {
+            candle.interval == interval && is_purged(&candle.ref_id)
+        });
+
+        removed_counts.values().sum()
+    }
+
+    /// Registers listener of candle lifecycle events emitted by `update_or_create`
+    pub fn add_listener(&mut self, listener: Box<dyn CandleListener<AccountCandle>>) {
+        self.listeners.push(listener);
+    }
+
+    pub fn clear_listeners(&mut self) {
+        self.listeners.clear();
+    }
+
+    /// Sets retention policy for the interval. Expired candles are evicted when a new candle
+    /// of the interval is created or on `enforce_retention` call.
+    /// Returns false for not valid policies
+    pub fn set_retention(&mut self, interval: CandleInterval, policy: RetentionPolicy) -> bool {
+        if !policy.is_valid() {
+            return false;
+        }
+
+        if self.retention_policies.insert(interval, policy).is_none() {
+            for candle in self.candles_by_indexes.values() {
+                if candle.interval == interval {
+                    self.retention_index
+                        .insert(candle.ref_id, interval, candle.date);
+                }
+            }
+        }
+
+        true
+    }
+
+    pub fn remove_retention(&mut self, interval: CandleInterval) -> Option<RetentionPolicy> {
+        let policy = self.retention_policies.remove(&interval)?;
+        self.retention_index.remove_interval(interval);
+
+        Some(policy)
+    }
+
+    pub fn get_retention(&self, interval: CandleInterval) -> Option<&RetentionPolicy> {
+        self.retention_policies.get(&interval)
+    }
+
+    /// Sets entries count or memory budget. Candles are evicted when a new candle is created
+    /// and the budget is exceeded down to the low-water mark of the budget (90% of the limits),
+    /// the currently forming candles are never evicted
+    pub fn set_budget(&mut self, budget: Option<CacheBudget>) {
+        if let Some(CacheBudget {
+            strategy: EvictionStrategy::LeastRecentlyRead,
+            ..
+        }) = budget
+        {
+            let stamp = self.read_clock.load(Ordering::Relaxed);
+
+            for index in self.candles_by_indexes.keys() {
+                if !self.read_stamps.contains_key(index) {
+                    self.read_stamps
+                        .insert(index.to_owned(), AtomicU64::new(stamp));
+                }
+            }
+        } else {
+            self.read_stamps.clear();
+        }
+
+        self.budget = budget;
+        self.enforce_budget();
+    }
+
+    pub fn get_budget(&self) -> Option<&CacheBudget> {
+        self.budget.as_ref()
+    }
+
+    /// Enables tracking of created and modified candles. Removed candles are not tracked
+    pub fn enable_change_tracking(&mut self) {
+        if self.changes.is_none() {
+            self.changes = Some(ChangeTracker::new());
+        }
+    }
+
+    pub fn disable_change_tracking(&mut self) {
+        self.changes = None;
+    }
+
+    /// Gets version of the latest tracked change
+    pub fn get_changes_version(&self) -> u64 {
+        self.changes
+            .as_ref()
+            .map(|changes| changes.get_version())
+            .unwrap_or_default()
+    }
+
+    /// Gets candles created or modified after the specified version
+    pub fn get_changed_since(&self, version: u64) -> Vec<&AccountCandle> {
+        let Some(changes) = self.changes.as_ref() else {
+            return vec![];
+        };
+
+        changes
+            .get_changed_since(version)
+            .filter_map(|index| self.candles_by_indexes.get(index))
+            .collect()
+    }
+
+    /// Takes indexes of candles created or modified since the previous call
+    pub fn take_changes(&mut self) -> ChangeSet<CandleIndex> {
+        match self.changes.as_mut() {
+            Some(changes) => changes.take(),
+            None => ChangeSet {
+                version: 0,
+                keys: vec![],
+            },
+        }
+    }
+
+    /// Gets approximate memory used by cached candles in bytes
+    pub fn estimate_memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
     pub fn get_all(&self) -> &AHashMap<CandleIndex, AccountCandle> {
         &self.candles_by_indexes
     }
@@ -40,126 +328,747 @@ impl AccountCandlesCache {
         self.candles_by_indexes.contains_key(index)
     }
 
+    /// Checks if the candle is finalized by `close_expired`. Finalized candles are not updated
+    /// anymore
+    pub fn is_closed(&self, index: &CandleIndex) -> bool {
+        self.closed_indexes.contains(index)
+    }
+
     pub fn insert_or_replace(&mut self, candle: AccountCandle) -> Option<AccountCandle> {
-        self.candles_by_indexes.insert((&candle).into(), candle)
+        self.update_forming_date(candle.ref_id, candle.interval, candle.date);
+        let replaced = self.insert_candle((&candle).into(), candle);
+        self.enforce_budget();
+
+        replaced
     }
 
+    /// Gets candle for modification. The candle is tracked as modified if it exists
     pub fn get_mut(&mut self, index: &CandleIndex) -> Option<&mut AccountCandle> {
-        self.candles_by_indexes.get_mut(index)
+        self.mark_read(index);
+        let candle = self.candles_by_indexes.get_mut(index);
+
+        if let (Some(changes), Some(_)) = (self.changes.as_mut(), candle.as_ref()) {
+            changes.mark_changed(index);
+        }
+
+        candle
     }
 
     pub fn update_or_create(&mut self, date: DateTime<Utc>, ref_id: &str, data: AccountData) {
-        for interval in self.intervals.iter() {
-            let index = CandleIndex::new(ref_id, interval.to_owned(), date);
+        self.update_or_create_at(date, ref_id, &data, Utc::now());
+    }
+
+    /// Applies the snapshots calculating interval start dates once per run of snapshots sharing
+    /// the date. Retention and budget are enforced once after the whole batch.
+    /// Returns outcomes in the order of snapshots
+    pub fn update_or_create_batch(
+        &mut self,
+        snapshots: &[AccountSnapshot],
+    ) -> Vec<BatchItemOutcome> {
+        let update_date = Utc::now();
+        let mut outcomes = Vec::with_capacity(snapshots.len());
+        let all_intervals = self.interval_overrides.get_all_intervals(&self.intervals);
+        let mut all_start_dates = Vec::with_capacity(all_intervals.len());
+        let mut start_dates = Vec::with_capacity(all_intervals.len());
+        let mut start_date = None;
+        let mut created_intervals = Vec::new();
+        let mut created_keys: AHashSet<(&str, CandleInterval)> = AHashSet::new();
+
+        for snapshot in snapshots {
+            if start_date != Some(snapshot.date) {
+                all_start_dates.clear();
+                all_start_dates.extend(
+                    all_intervals
+                        .iter()
+                        .map(|interval| (*interval, interval.get_start_date(snapshot.date))),
+                );
+                start_date = Some(snapshot.date);
+            }
+
+            let intervals = self.get_ref_id_intervals(&snapshot.ref_id);
+            start_dates.clear();
+            start_dates.extend(
+                all_start_dates
+                    .iter()
+                    .filter(|(interval, _)| intervals.contains(interval)),
+            );
+
+            created_intervals.clear();
+            outcomes.push(self.upsert(
+                &start_dates,
+                &snapshot.ref_id,
+                &snapshot.data,
+                update_date,
+                &mut created_intervals,
+            ));
+            created_keys.extend(
+                created_intervals
+                    .iter()
+                    .map(|interval| (snapshot.ref_id.as_str(), *interval)),
+            );
+        }
+
+        for (ref_id, interval) in created_keys.iter() {
+            if self.retention_policies.contains_key(interval) {
+                self.evict_expired(Some((Symbol::new(ref_id), *interval)));
+            }
+        }
+
+        if !created_keys.is_empty() {
+            self.enforce_budget();
+        }
+
+        if !snapshots.is_empty() {
+            self.last_update_date.replace(update_date);
+        }
+
+        outcomes
+    }
+
+    fn update_or_create_at(
+        &mut self,
+        date: DateTime<Utc>,
+        ref_id: &str,
+        data: &AccountData,
+        update_date: DateTime<Utc>,
+    ) {
+        let start_dates = self.get_start_dates(ref_id, date);
+        let mut created_intervals = Vec::new();
+        self.upsert(
+            &start_dates,
+            ref_id,
+            data,
+            update_date,
+            &mut created_intervals,
+        );
+
+        for interval in created_intervals.iter() {
+            if self.retention_policies.contains_key(interval) {
+                self.evict_expired(Some((Symbol::new(ref_id), *interval)));
+            }
+        }
+
+        if !created_intervals.is_empty() {
+            self.enforce_budget();
+        }
+
+        self.last_update_date.replace(update_date);
+    }
+
+    fn get_start_dates(
+        &self,
+        ref_id: &str,
+        date: DateTime<Utc>,
+    ) -> Vec<(CandleInterval, DateTime<Utc>)> {
+        self.get_ref_id_intervals(ref_id)
+            .iter()
+            .map(|interval| (*interval, interval.get_start_date(date)))
+            .collect()
+    }
+
+    /// Updates forming candles and opens missing ones without enforcing retention and budget.
+    /// Pushes intervals of opened candles to the created intervals
+    fn upsert(
+        &mut self,
+        start_dates: &[(CandleInterval, DateTime<Utc>)],
+        ref_id: &str,
+        data: &AccountData,
+        update_date: DateTime<Utc>,
+        created_intervals: &mut Vec<CandleInterval>,
+    ) -> BatchItemOutcome {
+        let mut outcome = BatchItemOutcome::default();
+        let mut created_indexes = Vec::new();
+        let ref_id = Symbol::new(ref_id);
+
+        for (interval, start_date) in start_dates.iter() {
+            let index = CandleIndex {
+                ref_id,
+                candle_interval: *interval,
+                interval_start_date: *start_date,
+            };
             let candle = self.candles_by_indexes.get_mut(&index);
 
             if let Some(candle) = candle {
-                candle.update(&data);
+                if self.closed_indexes.contains(&index) {
+                    outcome.skipped += 1;
+                    continue;
+                }
+
+                candle.update_at(data, update_date);
+                notify(
+                    &mut self.listeners,
+                    CandleEventKind::Updated,
+                    *interval,
+                    candle,
+                );
+
+                if let Some(changes) = self.changes.as_mut() {
+                    changes.mark_changed(&index);
+                }
+
+                outcome.updated += 1;
             } else {
-                self.candles_by_indexes
-                    .insert(index.clone(), AccountCandle::new(index, &data));
+                created_indexes.push(index);
+            }
+        }
+
+        for index in created_indexes {
+            let candle = AccountCandle::new_at(index.clone(), data, update_date);
+            created_intervals.push(index.candle_interval);
+            self.open_candle(index, candle);
+            outcome.created += 1;
+        }
+
+        outcome
+    }
+
+    pub fn insert_snapshot(&mut self, snapshot: &AccountSnapshot) {
+        self.update_or_create(snapshot.date, &snapshot.ref_id, snapshot.data.clone());
+    }
+
+    /// Finalizes candles which interval ended before or at the specified date. Carry forward
+    /// opens flat candles with the close values of the finalized forming candles
+    /// for the interval containing the specified date. Returns indexes of finalized candles
+    pub fn close_expired(&mut self, now: DateTime<Utc>, carry_forward: bool) -> Vec<CandleIndex> {
+        let mut expired_indexes: Vec<CandleIndex> = self
+            .candles_by_indexes
+            .iter()
+            .filter(|(index, candle)| {
+                !self.closed_indexes.contains(*index)
+                    && candle
+                        .interval
+                        .get_nth_date(candle.date, 1)
+                        .is_some_and(|next_date| next_date <= now)
+            })
+            .map(|(index, _candle)| index.to_owned())
+            .collect();
+        expired_indexes.sort();
+        let mut carried_candles = Vec::new();
+
+        for index in expired_indexes.iter() {
+            if !self.close_candle(index) || !carry_forward {
+                continue;
+            }
+
+            let Some(candle) = self.candles_by_indexes.get(index) else {
+                continue;
+            };
+            let carried_candle = candle.to_flat(now);
+
+            if self.is_forming(candle) && carried_candle.date > candle.date {
+                carried_candles.push(carried_candle);
+            }
+        }
+
+        if !carried_candles.is_empty() {
+            for candle in carried_candles {
+                self.open_candle((&candle).into(), candle);
+            }
+
+            self.enforce_budget();
+        }
+
+        expired_indexes
+    }
+
+    /// Evicts candles expired by retention policies. Returns evicted candles count per interval
+    pub fn enforce_retention(&mut self) -> AHashMap<CandleInterval, usize> {
+        self.evict_expired(None)
+    }
+
+    /// Evicts expired candles of the ref_id and interval or of all of them
+    fn evict_expired(
+        &mut self,
+        key: Option<(Symbol, CandleInterval)>,
+    ) -> AHashMap<CandleInterval, usize> {
+        let keys = match key {
+            Some(key) => vec![key],
+            None => self.retention_index.get_keys(),
+        };
+        let mut removed_counts = AHashMap::new();
+
+        for (ref_id, interval) in keys {
+            let Some(policy) = self.retention_policies.get(&interval) else {
+                continue;
+            };
+
+            for date in self.retention_index.take_expired(ref_id, interval, policy) {
+                if self
+                    .remove_candle(&CandleIndex::new(ref_id, interval, date))
+                    .is_some()
+                {
+                    *removed_counts.entry(interval).or_insert(0) += 1;
+                }
             }
         }
 
-        self.last_update_date.replace(Utc::now());
+        removed_counts
+    }
+
+    /// Evicts candles until the budget is satisfied. Returns evicted candles count
+    pub fn enforce_budget(&mut self) -> usize {
+        let Some(budget) = self.budget.as_ref() else {
+            return 0;
+        };
+
+        if !budget.is_exceeded(self.candles_by_indexes.len(), self.memory_usage) {
+            return 0;
+        }
+
+        let candidates = self
+            .candles_by_indexes
+            .iter()
+            .map(|(index, candle)| EvictionCandidate {
+                key: index,
+                owner: candle.ref_id.as_str(),
+                interval: candle.interval,
+                date: candle.date,
+                last_read: self
+                    .read_stamps
+                    .get(index)
+                    .map(|stamp| stamp.load(Ordering::Relaxed))
+                    .unwrap_or_default(),
+                size: ENTRY_SIZE,
+            })
+            .collect();
+        let indexes: AHashSet<CandleIndex> = select_evictions(
+            budget,
+            self.candles_by_indexes.len(),
+            self.memory_usage,
+            candidates,
+        )
+        .into_iter()
+        .cloned()
+        .collect();
+
+        if indexes.is_empty() {
+            return 0;
+        }
+
+        self.remove_where(|index, _candle| indexes.contains(index))
+            .values()
+            .sum()
     }
 
     /// Gets candles with date bigger or equals specified date
     pub fn get_after(&self, date: DateTime<Utc>) -> Option<Vec<&AccountCandle>> {
-        if self.candles_by_indexes.len() == 0 {
+        if self.candles_by_indexes.is_empty() {
             return None;
         }
 
-        let candle_dates = calculate_candle_dates(&self.intervals, date, None);
-
         let candles = self
             .candles_by_indexes
             .iter()
-            .filter_map(|(_id, candle)| {
-                let current_date = candle_dates
-                    .get(&candle.interval)
-                    .expect("wrong calculate_candle_dates");
+            .filter(|(index, candle)| {
+                if !self
+                    .get_ref_id_intervals(&candle.ref_id)
+                    .contains(&candle.interval)
+                {
+                    return false;
+                }
 
-                if candle.date >= *current_date {
-                    Some(candle)
+                if candle.date >= candle.interval.get_start_date(date) {
+                    self.mark_read(index);
+                    true
                 } else {
-                    None
+                    false
                 }
             })
+            .map(|(_index, candle)| candle)
             .collect();
 
         Some(candles)
     }
 
+    /// Gets cached candles of the ref_id for every interval date in the specified range
     pub fn get_range(
         &self,
-        ref_id: impl Into<String>,
+        ref_id: impl AsRef<str>,
         interval: CandleInterval,
         start_date: DateTime<Utc>,
         end_date: DateTime<Utc>,
     ) -> Vec<&AccountCandle> {
-        if self.candles_by_indexes.len() == 0 {
-            return vec![];
-        }
+        self.get_range_filled(
+            ref_id.as_ref(),
+            interval,
+            start_date,
+            end_date,
+            GapFill::Leave,
+        )
+        .into_iter()
+        .filter_map(|candle| match candle {
+            RangeCandle::Cached(candle) => Some(candle),
+            _ => None,
+        })
+        .collect()
+    }
 
-        let mut candles = Vec::new();
-        let ref_id = ref_id.into();
-        let mut cursor_date = start_date;
+    /// Gets candles of the ref_id for every interval date in the specified range
+    /// with missing candles handled by the specified gap fill
+    pub fn get_range_filled(
+        &self,
+        ref_id: &str,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        fill: GapFill,
+    ) -> Vec<RangeCandle<'_, AccountCandle>> {
+        let ref_id = SymbolRegistry::get(ref_id);
 
-        loop {
-            let index = CandleIndex::new(ref_id.clone(), interval, cursor_date);
-            cursor_date = index.interval_start_date + interval.get_duration(cursor_date);
+        get_range_filled(interval, start_date, end_date, fill, |date| {
+            let index = CandleIndex::new(ref_id?, interval, date);
+            let candle = self.candles_by_indexes.get(&index);
 
-            if let Some(candle) = self.candles_by_indexes.get(&index) {
-                candles.push(candle);
+            if candle.is_some() {
+                self.mark_read(&index);
             }
 
-            if cursor_date >= interval.get_end_date(end_date) {
-                return candles;
-            }
-        }
+            candle
+        })
     }
 
     /// Removes candles with date less or equals specified date
     pub fn remove_before(&mut self, date: DateTime<Utc>, interval: Option<CandleInterval>) -> i32 {
-        let mut removed_count = 0;
+        let removed_counts = if let Some(interval) = interval {
+            let current_date = interval.get_start_date(date);
 
-        if let Some(interval) = interval {
-            self.candles_by_indexes.retain(|_id, candle| {
-                let current_date = interval.get_start_date(date);
+            self.remove_where(|_index, candle| {
+                candle.date <= current_date && candle.interval == interval
+            })
+        } else {
+            let interval_overrides = self.interval_overrides.clone();
+            let intervals = self.intervals.clone();
 
-                if candle.date <= current_date && candle.interval == interval {
-                    removed_count += 1;
-                    false
-                } else {
-                    true
+            self.remove_where(|_index, candle| {
+                let key_intervals = interval_overrides
+                    .resolve(&candle.ref_id)
+                    .unwrap_or(&intervals);
+
+                if !key_intervals.contains(&candle.interval) {
+                    return false;
                 }
-            });
-        } else {
-            let dates = calculate_candle_dates(&self.intervals, date, None);
 
-            self.candles_by_indexes.retain(|_id, candle| {
-                let current_date = dates
-                    .get(&candle.interval)
-                    .expect("Wrong calculate_candle_dates");
+                candle.date <= candle.interval.get_start_date(date)
+            })
+        };
+
+        removed_counts.values().sum::<usize>() as i32
+    }
 
-                if candle.date <= *current_date {
-                    removed_count += 1;
-                    false
-                } else {
-                    true
+    pub fn get(&self, index: &CandleIndex) -> Option<&AccountCandle> {
+        let candle = self.candles_by_indexes.get(index);
+
+        if candle.is_some() {
+            self.mark_read(index);
+        }
+
+        candle
+    }
+
+    /// Writes intervals, last update date and all candles to the writer
+    pub fn save_snapshot(&self, writer: impl Write) -> Result<(), SnapshotError> {
+        let mut candles: Vec<_> = self.candles_by_indexes.iter().collect();
+        candles.sort_by_key(|(index, _)| *index);
+        let candles = candles
+            .into_iter()
+            .map(|(index, candle)| SnapshotCandle {
+                closed: self.closed_indexes.contains(index),
+                datetimes: candle.get_datetimes(),
+                candle,
+            })
+            .collect();
+
+        write_snapshot(
+            writer,
+            &CacheSnapshot {
+                version: SNAPSHOT_VERSION,
+                intervals: self.intervals.clone(),
+                last_update_date: self.last_update_date,
+                candles,
+            },
+        )
+    }
+
+    /// Replaces intervals, last update date and all candles with the ones of the snapshot.
+    /// Retention, budget and listeners are kept, listeners are not notified
+    pub fn restore_snapshot(&mut self, reader: impl Read) -> Result<(), SnapshotError> {
+        let snapshot: CacheSnapshot<AccountCandle> = read_snapshot(reader)?;
+        self.remove_where(|_index, _candle| true);
+        self.forming_dates.clear();
+
+        let mut intervals = snapshot.intervals;
+        intervals.sort();
+        intervals.dedup();
+        self.intervals = intervals;
+        self.last_update_date = snapshot.last_update_date;
+
+        for snapshot_candle in snapshot.candles {
+            let (candle, closed) = snapshot_candle.into_candle();
+            let index: CandleIndex = (&candle).into();
+            self.update_forming_date(candle.ref_id, candle.interval, candle.date);
+
+            if closed {
+                self.closed_indexes.insert(index.clone());
+            }
+
+            self.insert_candle(index, candle);
+        }
+
+        self.enforce_budget();
+
+        Ok(())
+    }
+
+    /// Applies snapshots of the time-ordered log as if they were received at their date.
+    /// Stops at the first snapshot earlier than the previous one. Returns replayed snapshots count
+    pub fn replay(
+        &mut self,
+        snapshots: impl IntoIterator<Item = AccountSnapshot>,
+    ) -> Result<usize, ReplayError> {
+        let mut order = ReplayOrder::default();
+
+        for snapshot in snapshots {
+            order.check(snapshot.date)?;
+            self.update_or_create_at(
+                snapshot.date,
+                &snapshot.ref_id,
+                &snapshot.data,
+                snapshot.date,
+            );
+        }
+
+        Ok(order.get_count())
+    }
+
+    /// Stable checksum of all candles and their closed state which doesn't depend
+    /// on insertion order. Equal for caches with equal candles
+    pub fn get_checksum(&self) -> u64 {
+        let mut indexes: Vec<&CandleIndex> = self.candles_by_indexes.keys().collect();
+        indexes.sort();
+        let mut hasher = ChecksumHasher::default();
+
+        for index in indexes {
+            let candle = &self.candles_by_indexes[index];
+            hasher.write_str(&candle.ref_id);
+            hasher.write(&(candle.interval as i32).to_le_bytes());
+            hasher.write_date(candle.date);
+            hasher.write(&[self.closed_indexes.contains(index) as u8]);
+
+            for data in [&candle.balance_data, &candle.equity_data, &candle.pnl_data] {
+                hasher.write_f64(data.open);
+                hasher.write_f64(data.close);
+                hasher.write_f64(data.high);
+                hasher.write_f64(data.low);
+                hasher.write_f64(data.low_after_high);
+                hasher.write_date(data.timestamp);
+            }
+        }
+
+        hasher.finish()
+    }
+
+    fn mark_read(&self, index: &CandleIndex) {
+        if let Some(stamp) = self.read_stamps.get(index) {
+            let clock = self.read_clock.fetch_add(1, Ordering::Relaxed);
+            stamp.store(clock, Ordering::Relaxed);
+        }
+    }
+
+    /// Inserts a new candle and notifies listeners. The forming candle superseded by it is
+    /// reported as closed but is still updated by late snapshots until `close_expired`
+    fn open_candle(&mut self, index: CandleIndex, candle: AccountCandle) {
+        let superseded_index = self
+            .update_forming_date(candle.ref_id, candle.interval, candle.date)
+            .map(|date| CandleIndex::new(candle.ref_id, candle.interval, date));
+        self.insert_candle(index.clone(), candle);
+
+        if let Some(superseded_index) = superseded_index {
+            if let Some(superseded) = self.candles_by_indexes.get(&superseded_index) {
+                if !self.closed_indexes.contains(&superseded_index) {
+                    notify(
+                        &mut self.listeners,
+                        CandleEventKind::Closed,
+                        superseded.interval,
+                        superseded,
+                    );
                 }
-            });
+            }
         }
 
-        removed_count
+        if let Some(candle) = self.candles_by_indexes.get(&index) {
+            notify(
+                &mut self.listeners,
+                CandleEventKind::Opened,
+                candle.interval,
+                candle,
+            );
+        }
     }
 
-    pub fn get(&self, index: &CandleIndex) -> Option<&AccountCandle> {
-        self.candles_by_indexes.get(index)
+    /// Finalizes the candle and notifies listeners if it is still forming. Returns false
+    /// if the candle is not found or is already finalized
+    fn close_candle(&mut self, index: &CandleIndex) -> bool {
+        if self.closed_indexes.contains(index) {
+            return false;
+        }
+
+        let Some(candle) = self.candles_by_indexes.get(index) else {
+            return false;
+        };
+
+        self.closed_indexes.insert(index.to_owned());
+
+        if self.is_forming(candle) {
+            notify(
+                &mut self.listeners,
+                CandleEventKind::Closed,
+                candle.interval,
+                candle,
+            );
+        }
+
+        true
+    }
+
+    fn is_forming(&self, candle: &AccountCandle) -> bool {
+        self.forming_dates
+            .get(&candle.ref_id)
+            .and_then(|dates| dates.get(&candle.interval))
+            == Some(&candle.date)
+    }
+
+    fn insert_candle(
+        &mut self,
+        index: CandleIndex,
+        candle: AccountCandle,
+    ) -> Option<AccountCandle> {
+        if let Some(changes) = self.changes.as_mut() {
+            changes.mark_changed(&index);
+        }
+
+        if self.retention_policies.contains_key(&candle.interval) {
+            self.retention_index
+                .insert(candle.ref_id, candle.interval, candle.date);
+        }
+
+        self.memory_usage += ENTRY_SIZE;
+
+        if let Some(CacheBudget {
+            strategy: EvictionStrategy::LeastRecentlyRead,
+            ..
+        }) = self.budget
+        {
+            let stamp = self.read_clock.fetch_add(1, Ordering::Relaxed);
+            self.read_stamps
+                .insert(index.clone(), AtomicU64::new(stamp));
+        }
+
+        let replaced = self.candles_by_indexes.insert(index, candle);
+
+        if replaced.is_some() {
+            self.memory_usage = self.memory_usage.saturating_sub(ENTRY_SIZE);
+        }
+
+        replaced
+    }
+
+    /// Sets date of the forming candle and returns date of the superseded one
+    fn update_forming_date(
+        &mut self,
+        ref_id: Symbol,
+        interval: CandleInterval,
+        date: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let dates = self.forming_dates.entry(ref_id).or_default();
+
+        match dates.get_mut(&interval) {
+            Some(forming_date) if *forming_date < date => {
+                Some(std::mem::replace(forming_date, date))
+            }
+            Some(_) => None,
+            None => {
+                dates.insert(interval, date);
+                None
+            }
+        }
+    }
+
+    /// Removes candles and forming dates of intervals not used by their ref_ids
+    fn remove_unused_intervals(&mut self) {
+        let interval_overrides = self.interval_overrides.clone();
+        let intervals = self.intervals.clone();
+        let is_used = |ref_id: &str, interval: &CandleInterval| {
+            interval_overrides
+                .resolve(ref_id)
+                .unwrap_or(&intervals)
+                .contains(interval)
+        };
+
+        for (ref_id, dates) in self.forming_dates.iter_mut() {
+            dates.retain(|interval, _| is_used(ref_id, interval));
+        }
+
+        self.remove_where(|_index, candle| !is_used(&candle.ref_id, &candle.interval));
+    }
+
+    fn remove_candle(&mut self, index: &CandleIndex) -> Option<AccountCandle> {
+        let candle = self.candles_by_indexes.remove(index)?;
+        self.memory_usage = self.memory_usage.saturating_sub(ENTRY_SIZE);
+        self.read_stamps.remove(index);
+        self.closed_indexes.remove(index);
+        self.retention_index
+            .remove(candle.ref_id, candle.interval, candle.date);
+
+        if let Some(changes) = self.changes.as_mut() {
+            changes.remove(index);
+        }
+
+        Some(candle)
+    }
+
+    /// Removes candles matching the predicate. Returns removed candles count per interval
+    fn remove_where(
+        &mut self,
+        mut predicate: impl FnMut(&CandleIndex, &AccountCandle) -> bool,
+    ) -> AHashMap<CandleInterval, usize> {
+        let mut removed_counts = AHashMap::new();
+        let mut memory_usage = self.memory_usage;
+        let read_stamps = &mut self.read_stamps;
+        let closed_indexes = &mut self.closed_indexes;
+        let retention_index = &mut self.retention_index;
+        let mut changes = self.changes.as_mut();
+
+        self.candles_by_indexes.retain(|index, candle| {
+            if predicate(index, candle) {
+                *removed_counts.entry(candle.interval).or_insert(0) += 1;
+                memory_usage = memory_usage.saturating_sub(ENTRY_SIZE);
+                read_stamps.remove(index);
+                closed_indexes.remove(index);
+                retention_index.remove(candle.ref_id, candle.interval, candle.date);
+
+                if let Some(changes) = changes.as_mut() {
+                    changes.remove(index);
+                }
+
+                false
+            } else {
+                true
+            }
+        });
+
+        self.memory_usage = memory_usage;
+
+        removed_counts
     }
 }
 
+fn get_seconds(interval: CandleInterval) -> i64 {
+    interval.get_duration(DateTime::UNIX_EPOCH).num_seconds()
+}
+
+// one control byte per hash map entry, ref_id names are stored by the symbol registry
+const ENTRY_SIZE: usize = std::mem::size_of::<(CandleIndex, AccountCandle)>() + 1;
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -281,9 +1190,576 @@ mod test {
 
         cache.insert_or_replace(candle);
         cache.insert_or_replace(candle_2);
-        let range = cache.get_range(id, intervals[0], date+Duration::days(1000), date+Duration::days(1000));
+        let range = cache.get_range(
+            id,
+            intervals[0],
+            date + Duration::days(1000),
+            date + Duration::days(1000),
+        );
 
         assert_eq!(cache.len(), 2);
         assert_eq!(range.len(), 0);
     }
+
+    #[test]
+    pub fn update_or_create_evicts_by_max_count() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 12, 3, 23, 34).unwrap();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Day];
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let id = "1";
+        let mut cache = AccountCandlesCache::new(intervals);
+        cache.set_retention(CandleInterval::Minute, RetentionPolicy::MaxCount(5));
+
+        for i in 0..20 {
+            cache.update_or_create(date + Duration::minutes(i), id, data.clone());
+        }
+
+        assert_eq!(cache.len(), 6);
+        assert!(cache.contains(&CandleIndex::new(
+            id,
+            CandleInterval::Minute,
+            date + Duration::minutes(19)
+        )));
+        assert!(!cache.contains(&CandleIndex::new(
+            id,
+            CandleInterval::Minute,
+            date + Duration::minutes(14)
+        )));
+    }
+
+    #[test]
+    pub fn enforce_retention_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 12, 3, 23, 34).unwrap();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Hour];
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let mut cache = AccountCandlesCache::new(intervals);
+
+        for i in 0..10 {
+            cache.update_or_create(date + Duration::minutes(i), "1", data.clone());
+            cache.update_or_create(date + Duration::minutes(i), "2", data.clone());
+        }
+
+        cache.set_retention(CandleInterval::Minute, RetentionPolicy::MaxCount(2));
+        let evicted = cache.enforce_retention();
+
+        assert_eq!(evicted.get(&CandleInterval::Minute), Some(&16));
+        assert_eq!(evicted.get(&CandleInterval::Hour), None);
+        assert_eq!(cache.len(), 6);
+    }
+
+    #[test]
+    pub fn update_or_create_evicts_by_budget() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 12, 3, 23, 34).unwrap();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Day];
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let mut cache = AccountCandlesCache::new(intervals);
+        cache.set_budget(Some(CacheBudget {
+            max_entries: Some(4),
+            max_memory_bytes: None,
+            strategy: EvictionStrategy::OldestFirst,
+        }));
+
+        for i in 0..10 {
+            cache.update_or_create(date + Duration::minutes(i), "1", data.clone());
+            cache.update_or_create(date + Duration::minutes(i), "2", data.clone());
+        }
+
+        assert_eq!(cache.len(), 4);
+        assert!(cache.estimate_memory_usage() > 0);
+        assert!(cache.contains(&CandleIndex::new(
+            "1",
+            CandleInterval::Minute,
+            date + Duration::minutes(9)
+        )));
+        assert!(cache.contains(&CandleIndex::new("2", CandleInterval::Day, date)));
+    }
+
+    #[test]
+    pub fn get_changed_since_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 12, 3, 23, 34).unwrap();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Month];
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let mut cache = AccountCandlesCache::new(intervals);
+        cache.enable_change_tracking();
+
+        cache.update_or_create(date, "1", data.clone());
+        cache.update_or_create(date, "2", data.clone());
+        let version = cache.get_changes_version();
+        cache.update_or_create(date + Duration::days(1), "1", data.clone());
+        let changed = cache.get_changed_since(version);
+
+        assert_eq!(changed.len(), 2);
+        assert!(changed.iter().all(|candle| candle.ref_id == "1"));
+
+        let changes = cache.take_changes();
+
+        assert_eq!(changes.version, 6);
+        assert_eq!(changes.keys.len(), 5);
+        assert!(cache.take_changes().keys.is_empty());
+    }
+
+    #[test]
+    pub fn update_or_create_emits_events() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 12, 3, 23, 0).unwrap();
+        let intervals = vec![CandleInterval::Minute];
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let mut cache = AccountCandlesCache::new(intervals);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        cache.add_listener(Box::new(sender));
+
+        cache.update_or_create(date, "1", data.clone());
+        cache.update_or_create(date + Duration::seconds(1), "1", data.clone());
+        cache.update_or_create(date + Duration::minutes(1), "1", data.clone());
+
+        let events: Vec<_> = receiver
+            .try_iter()
+            .map(|event| (event.kind, event.candle.date))
+            .collect();
+
+        assert_eq!(
+            events,
+            vec![
+                (CandleEventKind::Opened, date),
+                (CandleEventKind::Updated, date),
+                (CandleEventKind::Closed, date),
+                (CandleEventKind::Opened, date + Duration::minutes(1)),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn close_expired_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 12, 3, 23, 0).unwrap();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Day];
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let late_data = AccountData {
+            equity: 900.0,
+            balance: 1000.0,
+            pnl: -100.0,
+        };
+        let mut cache = AccountCandlesCache::new(intervals);
+        cache.update_or_create(date, "1", data.clone());
+        let index = CandleIndex::new("1", CandleInterval::Minute, date);
+
+        let closed_indexes = cache.close_expired(date + Duration::minutes(2), false);
+        cache.update_or_create(date + Duration::seconds(30), "1", late_data);
+
+        assert_eq!(closed_indexes, vec![index.clone()]);
+        assert!(cache.is_closed(&index));
+        assert_eq!(cache.get(&index).unwrap().equity_data.low, 1000.0);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    pub fn update_or_create_late_snapshot() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Minute]);
+        let index = CandleIndex::new("1", CandleInterval::Minute, date);
+        let data = |equity: f64| AccountData {
+            equity,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+
+        cache.update_or_create(date, "1", data(1000.0));
+        cache.update_or_create(date + Duration::minutes(1), "1", data(1000.0));
+        cache.update_or_create(date + Duration::seconds(30), "1", data(1100.0));
+
+        assert!(!cache.is_closed(&index));
+        assert_eq!(cache.get(&index).unwrap().equity_data.high, 1100.0);
+
+        cache.close_expired(date + Duration::minutes(1), false);
+        cache.update_or_create(date + Duration::seconds(40), "1", data(1200.0));
+
+        assert!(cache.is_closed(&index));
+        assert_eq!(cache.get(&index).unwrap().equity_data.high, 1100.0);
+    }
+
+    #[test]
+    pub fn close_expired_three_and_seven_days() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 10, 0, 0, 0).unwrap();
+        let data = AccountData {
+            equity: 1010.0,
+            balance: 1000.0,
+            pnl: 10.0,
+        };
+
+        for interval in [CandleInterval::ThreeDays, CandleInterval::SevenDays] {
+            let mut cache = AccountCandlesCache::new(vec![interval]);
+            let start_date = interval.get_start_date(date);
+            let next_date = interval.get_nth_date(start_date, 1).unwrap();
+            let index = CandleIndex::new("1", interval, start_date);
+            cache.update_or_create(start_date, "1", data.clone());
+
+            assert!(cache
+                .close_expired(next_date - Duration::seconds(1), true)
+                .is_empty());
+
+            cache.update_or_create(next_date - Duration::seconds(1), "1", data.clone());
+
+            assert!(!cache.is_closed(&index));
+            assert_eq!(cache.close_expired(next_date, true), vec![index.clone()]);
+
+            let carried_index = CandleIndex::new("1", interval, next_date);
+
+            assert_eq!(cache.len(), 2);
+            assert_eq!(cache.get(&carried_index).unwrap().equity_data.open, 1010.0);
+            assert!(!cache.is_closed(&carried_index));
+        }
+    }
+
+    #[test]
+    pub fn close_expired_carry_forward() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 12, 3, 23, 0).unwrap();
+        let intervals = vec![CandleInterval::Minute];
+        let data = AccountData {
+            equity: 1010.0,
+            balance: 1000.0,
+            pnl: 10.0,
+        };
+        let mut cache = AccountCandlesCache::new(intervals);
+        cache.update_or_create(date, "1", data.clone());
+
+        let now = date + Duration::hours(2);
+        let closed_indexes = cache.close_expired(now, true);
+        let carried_index = CandleIndex::new("1", CandleInterval::Minute, now);
+        let carried_candle = cache.get(&carried_index).unwrap();
+
+        assert_eq!(closed_indexes.len(), 1);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(carried_candle.equity_data.open, 1010.0);
+        assert_eq!(carried_candle.pnl_data.close, 10.0);
+        assert_eq!(
+            carried_candle.equity_data.timestamp,
+            carried_index.interval_start_date
+        );
+        assert!(!cache.is_closed(&carried_index));
+    }
+
+    #[test]
+    pub fn get_range_seven_days() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 10, 0, 0, 0).unwrap();
+        let interval = CandleInterval::SevenDays;
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let start_date = interval.get_start_date(date);
+        let next_date = interval.get_nth_date(start_date, 1).unwrap();
+        let mut cache = AccountCandlesCache::new(vec![interval]);
+        cache.update_or_create(start_date, "1", data.clone());
+        cache.update_or_create(next_date, "1", data);
+
+        let dates: Vec<_> = cache
+            .get_range("1", interval, start_date, next_date)
+            .iter()
+            .map(|candle| candle.date)
+            .collect();
+
+        assert_eq!(dates, vec![start_date, next_date]);
+    }
+
+    #[test]
+    pub fn get_range_filled_placeholder() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 12, 3, 23, 34).unwrap();
+        let intervals = vec![CandleInterval::Minute];
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let mut cache = AccountCandlesCache::new(intervals);
+        cache.update_or_create(date, "1", data.clone());
+        cache.update_or_create(date + Duration::minutes(3), "1", data.clone());
+
+        let range = cache.get_range_filled(
+            "1",
+            CandleInterval::Minute,
+            date,
+            date + Duration::minutes(3),
+            GapFill::Placeholder,
+        );
+
+        assert_eq!(range.len(), 4);
+        assert!(matches!(range[0], RangeCandle::Cached(_)));
+        assert!(matches!(range[1], RangeCandle::Missing(_)));
+        assert!(range[2].get_candle().is_none());
+        assert!(matches!(range[3], RangeCandle::Cached(_)));
+    }
+
+    #[test]
+    pub fn get_range_of_unknown_ref_id_does_not_register_it() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let cache = AccountCandlesCache::new(vec![CandleInterval::Minute]);
+        let ref_id = "ACCOUNTS_TEST_UNKNOWN_REF_ID";
+
+        let range = cache.get_range_filled(
+            ref_id,
+            CandleInterval::Minute,
+            date,
+            date + Duration::minutes(1),
+            GapFill::Placeholder,
+        );
+
+        assert_eq!(range.len(), 2);
+        assert!(range.iter().all(|candle| candle.get_candle().is_none()));
+        assert!(cache
+            .get_range(ref_id, CandleInterval::Minute, date, date)
+            .is_empty());
+        assert_eq!(SymbolRegistry::get(ref_id), None);
+    }
+
+    #[test]
+    pub fn restore_snapshot_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Day]);
+
+        for i in 0..4 {
+            let data = AccountData {
+                equity: 1000.0 + i as f64,
+                balance: 1000.0,
+                pnl: i as f64,
+            };
+            cache.update_or_create(date + Duration::seconds(i * 30), "1", data);
+        }
+
+        cache.close_expired(date + Duration::minutes(1), false);
+        let mut bytes = Vec::new();
+        cache.save_snapshot(&mut bytes).unwrap();
+        let mut restored_cache = AccountCandlesCache::new(vec![]);
+        restored_cache.restore_snapshot(bytes.as_slice()).unwrap();
+        let closed_index = CandleIndex::new("1", CandleInterval::Minute, date);
+
+        assert_eq!(restored_cache.intervals, cache.intervals);
+        assert_eq!(restored_cache.last_update_date, cache.last_update_date);
+        assert_eq!(restored_cache.len(), 3);
+        assert!(restored_cache.is_closed(&closed_index));
+        assert!(!restored_cache.is_closed(&CandleIndex::new("1", CandleInterval::Day, date)));
+
+        for (index, expected) in cache.get_all() {
+            let candle = restored_cache.get(index).unwrap();
+
+            assert_eq!(candle.equity_data.open, expected.equity_data.open);
+            assert_eq!(candle.equity_data.close, expected.equity_data.close);
+            assert_eq!(candle.pnl_data.high, expected.pnl_data.high);
+        }
+    }
+
+    #[test]
+    pub fn restore_snapshot_keeps_sub_second_datetimes() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let snapshots: Vec<AccountSnapshot> = [123_456_789, 987_654_321]
+            .into_iter()
+            .map(|nanos| AccountSnapshot {
+                date: date + Duration::nanoseconds(nanos),
+                ref_id: "1".to_string(),
+                data: AccountData {
+                    equity: 1000.0 + nanos as f64,
+                    balance: 1000.0,
+                    pnl: nanos as f64,
+                },
+            })
+            .collect();
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Minute]);
+        cache.replay(snapshots).unwrap();
+
+        let mut bytes = Vec::new();
+        cache.save_snapshot(&mut bytes).unwrap();
+        let mut restored_cache = AccountCandlesCache::new(vec![]);
+        restored_cache.restore_snapshot(bytes.as_slice()).unwrap();
+        let index = CandleIndex::new("1", CandleInterval::Minute, date);
+
+        assert_eq!(restored_cache.get(&index), cache.get(&index));
+        assert_eq!(
+            restored_cache.get(&index).unwrap().equity_data.timestamp,
+            date + Duration::nanoseconds(987_654_321)
+        );
+        assert_eq!(restored_cache.get_checksum(), cache.get_checksum());
+    }
+
+    #[test]
+    pub fn replay_is_deterministic() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let snapshots: Vec<AccountSnapshot> = (0..100)
+            .map(|i| AccountSnapshot {
+                date: date + Duration::seconds(i * 17),
+                ref_id: (i % 3).to_string(),
+                data: AccountData {
+                    equity: 1000.0 + (i % 11) as f64,
+                    balance: 1000.0,
+                    pnl: (i % 11) as f64,
+                },
+            })
+            .collect();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Day];
+        let mut cache = AccountCandlesCache::new(intervals.clone());
+        let mut replayed_cache = AccountCandlesCache::new(intervals.clone());
+
+        assert_eq!(cache.replay(snapshots.clone()), Ok(100));
+        assert_eq!(replayed_cache.replay(snapshots.clone()), Ok(100));
+        assert_eq!(cache.get_checksum(), replayed_cache.get_checksum());
+        assert_eq!(cache.last_update_date, Some(snapshots[99].date));
+
+        let mut changed_snapshots = snapshots;
+        changed_snapshots[50].data.pnl = -1.0;
+        let mut changed_cache = AccountCandlesCache::new(intervals);
+        changed_cache.replay(changed_snapshots).unwrap();
+
+        assert_ne!(cache.get_checksum(), changed_cache.get_checksum());
+    }
+
+    #[test]
+    pub fn update_or_create_batch_skips_closed_candles() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Day]);
+        let snapshots: Vec<AccountSnapshot> = ["1", "2", "1"]
+            .iter()
+            .map(|ref_id| AccountSnapshot {
+                date,
+                ref_id: ref_id.to_string(),
+                data: AccountData {
+                    equity: 1000.0,
+                    balance: 1000.0,
+                    pnl: 0.0,
+                },
+            })
+            .collect();
+
+        let outcomes = cache.update_or_create_batch(&snapshots);
+
+        assert_eq!(cache.len(), 4);
+        assert_eq!(
+            outcomes,
+            vec![
+                BatchItemOutcome {
+                    created: 2,
+                    updated: 0,
+                    skipped: 0
+                },
+                BatchItemOutcome {
+                    created: 2,
+                    updated: 0,
+                    skipped: 0
+                },
+                BatchItemOutcome {
+                    created: 0,
+                    updated: 2,
+                    skipped: 0
+                },
+            ]
+        );
+
+        cache.close_expired(date + Duration::minutes(1), false);
+        let outcomes = cache.update_or_create_batch(&snapshots[..1]);
+
+        assert_eq!(
+            outcomes,
+            vec![BatchItemOutcome {
+                created: 0,
+                updated: 1,
+                skipped: 1
+            }]
+        );
+    }
+
+    #[test]
+    pub fn update_or_create_with_intervals_override() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Hour]);
+        cache.set_intervals_override(
+            KeyPattern::Glob("funded-*".to_string()),
+            vec![CandleInterval::Minute, CandleInterval::Hour],
+        );
+        let data = AccountData {
+            equity: 1000.0,
+            balance: 1000.0,
+            pnl: 0.0,
+        };
+        let snapshots: Vec<AccountSnapshot> = ["funded-1", "demo-1"]
+            .iter()
+            .map(|ref_id| AccountSnapshot {
+                date,
+                ref_id: ref_id.to_string(),
+                data: data.clone(),
+            })
+            .collect();
+
+        cache.update_or_create_batch(&snapshots);
+        cache.update_or_create(date + Duration::minutes(1), "funded-2", data);
+
+        assert_eq!(cache.len(), 5);
+        assert_eq!(
+            cache.get_ref_id_intervals("funded-2"),
+            &[CandleInterval::Minute, CandleInterval::Hour]
+        );
+        assert_eq!(
+            cache.get_after(date + Duration::minutes(1)).unwrap().len(),
+            4
+        );
+        assert_eq!(cache.remove_before(date, None), 4);
+        assert_eq!(cache.len(), 1);
+
+        cache.set_intervals_override(
+            KeyPattern::Glob("funded-*".to_string()),
+            vec![CandleInterval::Hour],
+        );
+
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    pub fn add_interval_backfills_from_finer_candles() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let snapshots: Vec<AccountSnapshot> = (0..200)
+            .map(|i| AccountSnapshot {
+                date: date + Duration::seconds(i * 70),
+                ref_id: (i % 2).to_string(),
+                data: AccountData {
+                    equity: 1000.0 + ((i * 7) % 23) as f64,
+                    balance: 1000.0,
+                    pnl: 1.0 + ((i * 7) % 23) as f64,
+                },
+            })
+            .collect();
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Minute]);
+        let mut expected_cache =
+            AccountCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::FourHours]);
+        cache.replay(snapshots.clone()).unwrap();
+        expected_cache.replay(snapshots).unwrap();
+
+        assert_eq!(cache.add_interval(CandleInterval::FourHours, true), 2);
+        assert_eq!(cache.get_checksum(), expected_cache.get_checksum());
+        let minute_candles_count = cache.len() - 2;
+
+        assert_eq!(
+            cache.remove_interval(CandleInterval::Minute),
+            minute_candles_count
+        );
+        assert_eq!(cache.len(), 2);
+    }
 }
@@ -0,0 +1,190 @@
+use crate::accounts::candle::AccountCandle;
+use crate::shared::candle_data::CandleData;
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::columnar::{
+    get_date_column, get_f64_column, get_i32_column, get_str_column, get_timestamp_field,
+    read_parquet, to_timestamp_array, write_parquet, ColumnarError,
+};
+use crate::shared::symbols::Symbol;
+use ::arrow::array::{ArrayRef, Float64Array, Int32Array, StringArray};
+use ::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use ::arrow::record_batch::RecordBatch;
+use parquet::file::reader::ChunkReader;
+use std::io::Write;
+use std::sync::Arc;
+
+const VALUES: [&str; 3] = ["balance", "equity", "pnl"];
+const PRICES: [&str; 5] = ["open", "close", "high", "low", "low_after_high"];
+
+/// Schema of account candles batches: `interval`, `date`, `ref_id` and
+/// `open`, `close`, `high`, `low`, `low_after_high`, `timestamp` columns
+/// of `balance`, `equity` and `pnl` values
+pub fn get_account_schema() -> SchemaRef {
+    let mut fields = vec![
+        Field::new("interval", DataType::Int32, false),
+        get_timestamp_field("date"),
+        Field::new("ref_id", DataType::Utf8, false),
+    ];
+
+    for value in VALUES {
+        for price in PRICES {
+            fields.push(Field::new(
+                format!("{value}_{price}"),
+                DataType::Float64,
+                false,
+            ));
+        }
+
+        fields.push(get_timestamp_field(&format!("{value}_timestamp")));
+    }
+
+    Arc::new(Schema::new(fields))
+}
+
+pub fn account_to_record_batch(candles: &[AccountCandle]) -> Result<RecordBatch, ColumnarError> {
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(Int32Array::from_iter_values(
+            candles.iter().map(|candle| candle.interval as i32),
+        )),
+        Arc::new(to_timestamp_array(
+            "date",
+            candles.iter().map(|candle| candle.date),
+        )?),
+        Arc::new(StringArray::from_iter_values(
+            candles.iter().map(|candle| candle.ref_id.as_str()),
+        )),
+    ];
+
+    for value in VALUES {
+        let data: Vec<&CandleData> = candles
+            .iter()
+            .map(|candle| match value {
+                "balance" => &candle.balance_data,
+                "equity" => &candle.equity_data,
+                _ => &candle.pnl_data,
+            })
+            .collect();
+        columns.extend([
+            Arc::new(Float64Array::from_iter_values(data.iter().map(|d| d.open))) as ArrayRef,
+            Arc::new(Float64Array::from_iter_values(data.iter().map(|d| d.close))),
+            Arc::new(Float64Array::from_iter_values(data.iter().map(|d| d.high))),
+            Arc::new(Float64Array::from_iter_values(data.iter().map(|d| d.low))),
+            Arc::new(Float64Array::from_iter_values(
+                data.iter().map(|d| d.low_after_high),
+            )),
+            Arc::new(to_timestamp_array(
+                &format!("{value}_timestamp"),
+                data.iter().map(|d| d.timestamp),
+            )?),
+        ]);
+    }
+
+    Ok(RecordBatch::try_new(get_account_schema(), columns)?)
+}
+
+pub fn account_from_record_batch(batch: &RecordBatch) -> Result<Vec<AccountCandle>, ColumnarError> {
+    let intervals = get_i32_column(batch, "interval")?;
+    let dates = get_date_column(batch, "date")?;
+    let ref_ids = get_str_column(batch, "ref_id")?;
+    let balance_data = read_data(batch, "balance")?;
+    let equity_data = read_data(batch, "equity")?;
+    let pnl_data = read_data(batch, "pnl")?;
+
+    intervals
+        .values()
+        .iter()
+        .zip(dates)
+        .zip(ref_ids.iter())
+        .zip(balance_data.into_iter().zip(equity_data).zip(pnl_data))
+        .map(
+            |(((interval, date), ref_id), ((balance_data, equity_data), pnl_data))| {
+                Ok(AccountCandle {
+                    interval: CandleInterval::try_from(*interval).map_err(|_| {
+                        ColumnarError::Schema(format!("interval {interval} is unknown"))
+                    })?,
+                    date,
+                    ref_id: Symbol::new(ref_id.unwrap_or_default()),
+                    balance_data,
+                    equity_data,
+                    pnl_data,
+                })
+            },
+        )
+        .collect()
+}
+
+fn read_data(batch: &RecordBatch, value: &str) -> Result<Vec<CandleData>, ColumnarError> {
+    let open = get_f64_column(batch, &format!("{value}_open"))?;
+    let close = get_f64_column(batch, &format!("{value}_close"))?;
+    let high = get_f64_column(batch, &format!("{value}_high"))?;
+    let low = get_f64_column(batch, &format!("{value}_low"))?;
+    let low_after_high = get_f64_column(batch, &format!("{value}_low_after_high"))?;
+    let timestamps = get_date_column(batch, &format!("{value}_timestamp"))?;
+
+    Ok(timestamps
+        .into_iter()
+        .enumerate()
+        .map(|(i, timestamp)| CandleData {
+            open: open.value(i),
+            close: close.value(i),
+            high: high.value(i),
+            low: low.value(i),
+            low_after_high: low_after_high.value(i),
+            timestamp,
+        })
+        .collect())
+}
+
+pub fn write_account_parquet(
+    writer: impl Write + Send,
+    candles: &[AccountCandle],
+) -> Result<(), ColumnarError> {
+    write_parquet(writer, &account_to_record_batch(candles)?)
+}
+
+pub fn read_account_parquet(
+    reader: impl ChunkReader + 'static,
+) -> Result<Vec<AccountCandle>, ColumnarError> {
+    let mut candles = Vec::new();
+
+    for batch in read_parquet(reader)? {
+        candles.extend(account_from_record_batch(&batch)?);
+    }
+
+    Ok(candles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::candle::AccountData;
+    use crate::accounts::candles_cache::AccountCandlesCache;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use std::fs::File;
+
+    #[test]
+    pub fn parquet_round_trip() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Day]);
+
+        for i in 0..300 {
+            let data = AccountData {
+                equity: 1000.0 + (i % 13) as f64,
+                balance: 1000.0,
+                pnl: (i % 13) as f64,
+            };
+            cache.update_or_create(date + Duration::seconds(i * 7), "1", data.clone());
+            cache.update_or_create(date + Duration::seconds(i * 7), "2", data);
+        }
+
+        let mut candles: Vec<_> = cache.get_all().values().cloned().collect();
+        candles.sort_by_key(|candle| (candle.ref_id, candle.interval, candle.date));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("accounts.parquet");
+
+        write_account_parquet(File::create(&path).unwrap(), &candles).unwrap();
+        let restored = read_account_parquet(File::open(&path).unwrap()).unwrap();
+
+        assert_eq!(restored, candles);
+    }
+}
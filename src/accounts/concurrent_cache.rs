@@ -0,0 +1,169 @@
+use crate::accounts::candle::{AccountCandle, AccountData};
+use crate::accounts::candles_cache::AccountCandlesCache;
+use crate::shared::budget::CacheBudget;
+use crate::shared::candle_index::CandleIndex;
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::sharded::Sharded;
+use chrono::{DateTime, Utc};
+
+/// Candles cache sharded by ref_id which allows parallel updates from multiple threads
+pub struct ConcurrentAccountCandlesCache {
+    shards: Sharded<AccountCandlesCache>,
+}
+
+impl ConcurrentAccountCandlesCache {
+    pub fn new(candle_intervals: Vec<CandleInterval>, shards_count: usize) -> Self {
+        Self {
+            shards: Sharded::new(shards_count, || {
+                AccountCandlesCache::new(candle_intervals.clone())
+            }),
+        }
+    }
+
+    /// Applies configuration (retention, budget, listeners etc.) to every shard. A budget set
+    /// here limits every shard, use `set_budget` to limit the whole cache
+    pub fn configure(&self, configure: impl FnMut(&mut AccountCandlesCache)) {
+        self.shards.for_each_mut(configure);
+    }
+
+    /// Sets the budget of the whole cache divided evenly between shards
+    pub fn set_budget(&self, budget: Option<CacheBudget>) {
+        let shard_budget = budget.map(|budget| budget.divide(self.shards.len()));
+        self.shards
+            .for_each_mut(|shard| shard.set_budget(shard_budget));
+    }
+
+    pub fn update_or_create(&self, date: DateTime<Utc>, ref_id: &str, data: AccountData) {
+        self.shards
+            .write(ref_id)
+            .update_or_create(date, ref_id, data);
+    }
+
+    pub fn get(&self, index: &CandleIndex) -> Option<AccountCandle> {
+        self.shards.read(&index.ref_id).get(index).cloned()
+    }
+
+    /// Reads the shard which contains candles of the ref_id
+    pub fn read<R>(&self, ref_id: &str, read: impl FnOnce(&AccountCandlesCache) -> R) -> R {
+        read(&self.shards.read(ref_id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.read_all().iter().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.read_all().iter().all(|shard| shard.is_empty())
+    }
+
+    /// Gets copies of all candles. All shards are locked while copying
+    pub fn snapshot(&self) -> Vec<AccountCandle> {
+        let shards = self.shards.read_all();
+        let mut candles = Vec::with_capacity(shards.iter().map(|shard| shard.len()).sum());
+
+        for shard in shards.iter() {
+            candles.extend(shard.get_all().values().cloned());
+        }
+
+        candles
+    }
+
+    /// Gets copies of candles with date bigger or equals specified date.
+    /// All shards are locked while copying
+    pub fn get_after(&self, date: DateTime<Utc>) -> Vec<AccountCandle> {
+        self.shards
+            .read_all()
+            .iter()
+            .filter_map(|shard| shard.get_after(date))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Removes candles with date less or equals specified date
+    pub fn remove_before(&self, date: DateTime<Utc>, interval: Option<CandleInterval>) -> i32 {
+        let mut removed_count = 0;
+        self.shards.for_each_mut(|shard| {
+            removed_count += shard.remove_before(date, interval);
+        });
+
+        removed_count
+    }
+
+    /// Finalizes expired candles of all shards. Returns indexes of finalized candles
+    pub fn close_expired(&self, now: DateTime<Utc>, carry_forward: bool) -> Vec<CandleIndex> {
+        let mut closed_indexes = Vec::new();
+        self.shards.for_each_mut(|shard| {
+            closed_indexes.extend(shard.close_expired(now, carry_forward));
+        });
+        closed_indexes.sort();
+
+        closed_indexes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn parallel_update_or_create_matches_sequential() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Hour];
+        let ref_ids: Vec<String> = (0..16).map(|no| no.to_string()).collect();
+        let get_data = |ref_no: usize, i: i64| AccountData {
+            equity: 1000.0 + ((i * 31 + ref_no as i64 * 17) % 200) as f64,
+            balance: 1000.0,
+            pnl: ((i * 31 + ref_no as i64 * 17) % 200) as f64,
+        };
+        let concurrent_cache = ConcurrentAccountCandlesCache::new(intervals.clone(), 4);
+        let mut sequential_cache = AccountCandlesCache::new(intervals);
+
+        std::thread::scope(|scope| {
+            for thread_no in 0..4 {
+                let concurrent_cache = &concurrent_cache;
+                let ref_ids = &ref_ids;
+
+                scope.spawn(move || {
+                    // each ref_id is fed by a single thread to keep its updates order
+                    for (ref_no, ref_id) in ref_ids.iter().enumerate().skip(thread_no).step_by(4) {
+                        for i in 0..1000 {
+                            let data = get_data(ref_no, i);
+                            concurrent_cache.update_or_create(
+                                date + Duration::seconds(i * 11),
+                                ref_id,
+                                data,
+                            );
+                        }
+                    }
+                });
+            }
+        });
+
+        for (ref_no, ref_id) in ref_ids.iter().enumerate() {
+            for i in 0..1000 {
+                let data = get_data(ref_no, i);
+                sequential_cache.update_or_create(date + Duration::seconds(i * 11), ref_id, data);
+            }
+        }
+
+        let snapshot = concurrent_cache.snapshot();
+
+        assert_eq!(snapshot.len(), sequential_cache.len());
+
+        for candle in snapshot.iter() {
+            let expected = sequential_cache.get(&candle.into()).unwrap();
+
+            assert_eq!(candle.equity_data.open, expected.equity_data.open);
+            assert_eq!(candle.equity_data.close, expected.equity_data.close);
+            assert_eq!(candle.equity_data.high, expected.equity_data.high);
+            assert_eq!(candle.equity_data.low, expected.equity_data.low);
+            assert_eq!(
+                candle.equity_data.low_after_high,
+                expected.equity_data.low_after_high
+            );
+            assert_eq!(candle.pnl_data.close, expected.pnl_data.close);
+        }
+    }
+}
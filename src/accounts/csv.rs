@@ -0,0 +1,249 @@
+use crate::accounts::candle::AccountCandle;
+use crate::accounts::candles_cache::AccountCandlesCache;
+use crate::shared::candle_data::CandleData;
+use crate::shared::csv::{
+    create_writer, read_rows, CsvError, CsvImport, CsvReadOptions, CsvRow, CsvWriteOptions,
+};
+use crate::shared::symbols::Symbol;
+use chrono::{DateTime, Utc};
+use std::io::{Read, Write};
+
+/// Columns of the written csv
+pub const ACCOUNT_CSV_COLUMNS: [&str; 19] = [
+    "date",
+    "interval",
+    "ref_id",
+    "balance_open",
+    "balance_high",
+    "balance_low",
+    "balance_close",
+    "balance_low_after_high",
+    "equity_open",
+    "equity_high",
+    "equity_low",
+    "equity_close",
+    "equity_low_after_high",
+    "pnl_open",
+    "pnl_high",
+    "pnl_low",
+    "pnl_close",
+    "pnl_low_after_high",
+    "updated",
+];
+
+const REQUIRED_COLUMNS: [&str; 13] = [
+    "date",
+    "balance_open",
+    "balance_high",
+    "balance_low",
+    "balance_close",
+    "equity_open",
+    "equity_high",
+    "equity_low",
+    "equity_close",
+    "pnl_open",
+    "pnl_high",
+    "pnl_low",
+    "pnl_close",
+];
+
+pub fn write_account_csv<'a>(
+    writer: impl Write,
+    candles: impl IntoIterator<Item = &'a AccountCandle>,
+    options: &CsvWriteOptions,
+) -> Result<(), CsvError> {
+    let mut writer = create_writer(writer, options);
+
+    if options.has_headers {
+        writer.write_record(ACCOUNT_CSV_COLUMNS)?;
+    }
+
+    for candle in candles {
+        let mut record = vec![
+            options.timestamp_format.format(candle.date),
+            (candle.interval as i32).to_string(),
+            candle.ref_id.to_string(),
+        ];
+
+        for data in [&candle.balance_data, &candle.equity_data, &candle.pnl_data] {
+            record.extend([
+                data.open.to_string(),
+                data.high.to_string(),
+                data.low.to_string(),
+                data.close.to_string(),
+                data.low_after_high.to_string(),
+            ]);
+        }
+
+        let updated = candle
+            .balance_data
+            .timestamp
+            .max(candle.equity_data.timestamp)
+            .max(candle.pnl_data.timestamp);
+        record.push(options.timestamp_format.format(updated));
+        writer.write_record(record)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads candles and passes them to the action. Rows with invalid values are reported
+/// as row errors
+pub fn read_account_csv(
+    reader: impl Read,
+    options: &CsvReadOptions,
+    mut action: impl FnMut(AccountCandle),
+) -> Result<CsvImport, CsvError> {
+    read_rows(
+        reader,
+        options,
+        &ACCOUNT_CSV_COLUMNS,
+        &REQUIRED_COLUMNS,
+        |row| {
+            action(read_candle(row, options)?);
+
+            Ok(())
+        },
+    )
+}
+
+/// Reads candles into the cache. Candles of the same index are replaced
+pub fn import_account_csv(
+    reader: impl Read,
+    options: &CsvReadOptions,
+    cache: &mut AccountCandlesCache,
+) -> Result<CsvImport, CsvError> {
+    read_account_csv(reader, options, |candle| {
+        cache.insert_or_replace(candle);
+    })
+}
+
+fn read_candle(row: &CsvRow, options: &CsvReadOptions) -> Result<AccountCandle, String> {
+    let interval = match (row.get("interval"), options.interval) {
+        (Some(_), _) => row.get_interval("interval")?,
+        (None, Some(interval)) => interval,
+        (None, None) => return Err("interval is empty".to_string()),
+    };
+    let ref_id = match (row.get("ref_id"), options.owner.as_ref()) {
+        (Some(ref_id), _) => Symbol::new(ref_id),
+        (None, Some(ref_id)) => Symbol::new(ref_id),
+        (None, None) => return Err("ref_id is empty".to_string()),
+    };
+    let date = interval.get_start_date(row.get_date("date")?);
+    let updated = match row.get("updated") {
+        Some(_) => row.get_date("updated")?,
+        None => date,
+    };
+
+    Ok(AccountCandle {
+        interval,
+        date,
+        ref_id,
+        balance_data: read_data(row, "balance", updated)?,
+        equity_data: read_data(row, "equity", updated)?,
+        pnl_data: read_data(row, "pnl", updated)?,
+    })
+}
+
+fn read_data(row: &CsvRow, name: &str, timestamp: DateTime<Utc>) -> Result<CandleData, String> {
+    let low = row.get_f64(&format!("{name}_low"))?;
+
+    Ok(CandleData {
+        open: row.get_f64(&format!("{name}_open"))?,
+        close: row.get_f64(&format!("{name}_close"))?,
+        high: row.get_f64(&format!("{name}_high"))?,
+        low,
+        low_after_high: row.get_f64_or(&format!("{name}_low_after_high"), low)?,
+        timestamp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::candle::AccountData;
+    use crate::shared::candle_index::CandleIndex;
+    use crate::shared::candle_interval::CandleInterval;
+    use crate::shared::csv::{CsvColumn, CsvRowError, TimestampFormat};
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn write_and_read_round_trip() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Minute]);
+
+        for i in 0..3 {
+            let data = AccountData {
+                equity: 1000.0 - i as f64,
+                balance: 1000.0,
+                pnl: -i as f64,
+            };
+            cache.update_or_create(date + Duration::seconds(i * 40), "1", data);
+        }
+
+        let candles: Vec<_> = cache.get_all().values().cloned().collect();
+        let mut bytes = Vec::new();
+        write_account_csv(&mut bytes, &candles, &CsvWriteOptions::default()).unwrap();
+        let mut restored_cache = AccountCandlesCache::new(vec![CandleInterval::Minute]);
+
+        let import = import_account_csv(
+            bytes.as_slice(),
+            &CsvReadOptions::default(),
+            &mut restored_cache,
+        )
+        .unwrap();
+
+        assert_eq!(import.imported_count, 2);
+        assert!(import.errors.is_empty());
+
+        for candle in candles {
+            let restored = restored_cache.get(&(&candle).into()).unwrap();
+
+            assert_eq!(restored.equity_data.open, candle.equity_data.open);
+            assert_eq!(restored.equity_data.low, candle.equity_data.low);
+            assert_eq!(restored.pnl_data.close, candle.pnl_data.close);
+            assert_eq!(
+                restored.pnl_data.low_after_high,
+                candle.pnl_data.low_after_high
+            );
+        }
+    }
+
+    #[test]
+    pub fn read_fixture_with_errors() {
+        let options = CsvReadOptions {
+            timestamp_format: TimestampFormat::UnixSeconds,
+            columns: [("date".to_string(), CsvColumn::Name("timestamp".to_string()))]
+                .into_iter()
+                .collect(),
+            interval: Some(CandleInterval::Day),
+            ..Default::default()
+        };
+        let mut cache = AccountCandlesCache::new(vec![CandleInterval::Day]);
+
+        let import = import_account_csv(
+            include_str!("../../tests/fixtures/account_candles.csv").as_bytes(),
+            &options,
+            &mut cache,
+        )
+        .unwrap();
+        let date = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let candle = cache
+            .get(&CandleIndex::new("acc-1", CandleInterval::Day, date))
+            .unwrap();
+
+        assert_eq!(import.imported_count, 2);
+        assert_eq!(
+            import.errors,
+            vec![CsvRowError {
+                line: 3,
+                message: "ref_id is empty".to_string()
+            }]
+        );
+        assert_eq!(candle.equity_data.high, 1050.0);
+        assert_eq!(candle.equity_data.low_after_high, 990.0);
+        assert_eq!(candle.pnl_data.low_after_high, -20.0);
+    }
+}
@@ -1,2 +1,8 @@
 pub mod candle;
+#[cfg(feature = "async")]
+pub mod candle_stream;
 pub mod candles_cache;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod concurrent_cache;
+pub mod csv;
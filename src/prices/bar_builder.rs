@@ -0,0 +1,312 @@
+use crate::prices::candle::BidAskCandleData;
+use crate::prices::tick::BidAskTick;
+use crate::shared::symbols::{Symbol, SymbolRegistry};
+use ahash::AHashMap;
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Condition closing non-time bars. Volume of a tick is the average of bid and ask volumes
+/// and range is measured by mid prices
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BarThreshold {
+    /// Closes the bar after the tick count
+    Ticks(u64),
+    /// Closes the bar after the tick reaching the cumulative volume, the overshoot is kept
+    /// in the closed bar
+    Volume(f64),
+    /// Closes the bar before the tick which would extend the bar range beyond the range.
+    /// The tick opens the next bar
+    Range(f64),
+}
+
+impl BarThreshold {
+    /// Checks that the tick count is not zero and the volume or range is finite and positive
+    pub fn is_valid(&self) -> bool {
+        match self {
+            BarThreshold::Ticks(count) => *count > 0,
+            BarThreshold::Volume(value) | BarThreshold::Range(value) => {
+                value.is_finite() && *value > 0.0
+            }
+        }
+    }
+}
+
+impl Display for BarThreshold {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarThreshold::Ticks(count) => write!(f, "T{count}"),
+            BarThreshold::Volume(volume) => write!(f, "V{volume}"),
+            BarThreshold::Range(range) => write!(f, "R{range}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bar {
+    pub threshold: BarThreshold,
+    pub instrument: Symbol,
+    /// Number of the bar of the instrument and the threshold starting from zero
+    pub sequence: u64,
+    /// Datetime of the first tick
+    pub date: DateTime<Utc>,
+    pub bid_data: BidAskCandleData,
+    pub ask_data: BidAskCandleData,
+    pub mid_data: BidAskCandleData,
+    pub tick_count: u64,
+}
+
+impl Bar {
+    fn new(
+        threshold: BarThreshold,
+        instrument: Symbol,
+        sequence: u64,
+        datetime: DateTime<Utc>,
+        (bid, ask, bid_vol, ask_vol): (f64, f64, f64, f64),
+    ) -> Self {
+        Self {
+            threshold,
+            instrument,
+            sequence,
+            date: datetime,
+            bid_data: BidAskCandleData::new(datetime, bid, bid_vol),
+            ask_data: BidAskCandleData::new(datetime, ask, ask_vol),
+            mid_data: BidAskCandleData::new(datetime, (bid + ask) / 2.0, (bid_vol + ask_vol) / 2.0),
+            tick_count: 1,
+        }
+    }
+
+    fn update(
+        &mut self,
+        datetime: DateTime<Utc>,
+        (bid, ask, bid_vol, ask_vol): (f64, f64, f64, f64),
+    ) {
+        self.bid_data.update(datetime, bid, bid_vol);
+        self.ask_data.update(datetime, ask, ask_vol);
+        self.mid_data
+            .update(datetime, (bid + ask) / 2.0, (bid_vol + ask_vol) / 2.0);
+        self.tick_count += 1;
+    }
+
+    fn is_complete(&self) -> bool {
+        match self.threshold {
+            BarThreshold::Ticks(count) => self.tick_count >= count,
+            BarThreshold::Volume(volume) => self.mid_data.volume >= volume,
+            BarThreshold::Range(_) => false,
+        }
+    }
+
+    fn exceeds_range(&self, mid: f64) -> bool {
+        match self.threshold {
+            BarThreshold::Range(range) => {
+                self.mid_data.high.max(mid) - self.mid_data.low.min(mid) > range
+            }
+            _ => false,
+        }
+    }
+
+    /// Id which is stable for the same ticks sequence, e.g. `T500EURUSD42`
+    pub fn generate_id(instrument: &str, threshold: &BarThreshold, sequence: u64) -> String {
+        format!("{threshold}{instrument}{sequence}")
+    }
+
+    pub fn get_id(&self) -> String {
+        Bar::generate_id(&self.instrument, &self.threshold, self.sequence)
+    }
+}
+
+/// Builds tick, volume and range bars of instruments for every threshold.
+/// Closed bars are kept until they are taken
+pub struct BarBuilder {
+    thresholds: Vec<BarThreshold>,
+    /// Forming bars by instruments in the thresholds order
+    forming_bars: AHashMap<Symbol, Vec<Option<Bar>>>,
+    next_sequences: AHashMap<(Symbol, usize), u64>,
+    closed_bars: Vec<Bar>,
+}
+
+impl BarBuilder {
+    /// Creates the builder of the thresholds without duplicates in the first occurrence order.
+    /// Returns None if any threshold is not valid
+    pub fn new(thresholds: Vec<BarThreshold>) -> Option<Self> {
+        if !thresholds.iter().all(BarThreshold::is_valid) {
+            return None;
+        }
+
+        let mut seen = Vec::with_capacity(thresholds.len());
+        let mut thresholds = thresholds;
+        thresholds.retain(|threshold| {
+            if seen.contains(threshold) {
+                return false;
+            }
+
+            seen.push(*threshold);
+
+            true
+        });
+
+        Some(Self {
+            thresholds,
+            forming_bars: AHashMap::new(),
+            next_sequences: AHashMap::new(),
+            closed_bars: Vec::new(),
+        })
+    }
+
+    pub fn get_thresholds(&self) -> &[BarThreshold] {
+        &self.thresholds
+    }
+
+    pub fn insert_or_update(
+        &mut self,
+        datetime: DateTime<Utc>,
+        instrument: &str,
+        bid: f64,
+        ask: f64,
+        bid_vol: f64,
+        ask_vol: f64,
+    ) {
+        let symbol = SymbolRegistry::get(instrument).unwrap_or_else(|| Symbol::new(instrument));
+        let tick = (bid, ask, bid_vol, ask_vol);
+        let forming_bars = self
+            .forming_bars
+            .entry(symbol)
+            .or_insert_with(|| vec![None; self.thresholds.len()]);
+
+        for (no, threshold) in self.thresholds.iter().enumerate() {
+            let bar = &mut forming_bars[no];
+
+            if let Some(forming_bar) = bar.as_mut() {
+                if forming_bar.exceeds_range((bid + ask) / 2.0) {
+                    self.closed_bars.extend(bar.take());
+                } else {
+                    forming_bar.update(datetime, tick);
+                }
+            }
+
+            if bar.is_none() {
+                let sequence = self.next_sequences.entry((symbol, no)).or_default();
+                *bar = Some(Bar::new(*threshold, symbol, *sequence, datetime, tick));
+                *sequence += 1;
+            }
+
+            if bar.as_ref().is_some_and(|bar| bar.is_complete()) {
+                self.closed_bars.extend(bar.take());
+            }
+        }
+    }
+
+    pub fn insert_tick(&mut self, tick: &BidAskTick) {
+        self.insert_or_update(
+            tick.datetime,
+            &tick.instrument,
+            tick.bid,
+            tick.ask,
+            tick.bid_vol,
+            tick.ask_vol,
+        );
+    }
+
+    pub fn get_forming(&self, instrument: &str, threshold: &BarThreshold) -> Option<&Bar> {
+        let no = self.thresholds.iter().position(|item| item == threshold)?;
+
+        self.forming_bars.get(&SymbolRegistry::get(instrument)?)?[no].as_ref()
+    }
+
+    /// Gets closed bars which are not taken yet in the closing order, so bars of every
+    /// instrument and threshold are in sequence order
+    pub fn get_closed(&self) -> &[Bar] {
+        &self.closed_bars
+    }
+
+    /// Takes closed bars in the closing order
+    pub fn take_closed(&mut self) -> Vec<Bar> {
+        std::mem::take(&mut self.closed_bars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn insert_prices(builder: &mut BarBuilder, mids: &[f64]) {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for (i, mid) in mids.iter().enumerate() {
+            let datetime = date + Duration::seconds(i as i64);
+            builder.insert_or_update(datetime, "EURUSD", mid - 0.5, mid + 0.5, 1.0, 3.0);
+        }
+    }
+
+    #[test]
+    pub fn tick_and_volume_bars() {
+        let mut builder = BarBuilder::new(vec![
+            BarThreshold::Ticks(3),
+            BarThreshold::Volume(5.0),
+            BarThreshold::Ticks(3),
+        ])
+        .unwrap();
+
+        insert_prices(&mut builder, &[10.0, 11.0, 12.0, 13.0, 14.0, 15.0, 16.0]);
+        let bars = builder.take_closed();
+        let ids: Vec<String> = bars.iter().map(|bar| bar.get_id()).collect();
+
+        assert_eq!(
+            ids,
+            vec!["T3EURUSD0", "V5EURUSD0", "T3EURUSD1", "V5EURUSD1"]
+        );
+        assert_eq!(bars[0].mid_data.close, 12.0);
+        assert_eq!(bars[1].mid_data.volume, 6.0);
+        assert_eq!(bars[2].bid_data.open, 12.5);
+        assert_eq!(
+            builder
+                .get_forming("EURUSD", &BarThreshold::Ticks(3))
+                .unwrap()
+                .tick_count,
+            1
+        );
+        assert!(builder.get_closed().is_empty());
+        assert_eq!(
+            builder.get_thresholds(),
+            &[BarThreshold::Ticks(3), BarThreshold::Volume(5.0)]
+        );
+        assert!(builder
+            .get_forming("USDJPY", &BarThreshold::Ticks(3))
+            .is_none());
+    }
+
+    #[test]
+    pub fn range_bars() {
+        let mut builder = BarBuilder::new(vec![BarThreshold::Range(2.0)]).unwrap();
+
+        insert_prices(&mut builder, &[10.0, 11.0, 9.0, 8.5, 9.5, 12.0]);
+        let bars = builder.take_closed();
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!((bars[0].mid_data.low, bars[0].mid_data.high), (9.0, 11.0));
+        assert_eq!(bars[0].tick_count, 3);
+        assert_eq!(bars[1].mid_data.open, 8.5);
+        assert_eq!(bars[1].mid_data.close, 9.5);
+        assert_eq!(
+            builder
+                .get_forming("EURUSD", &BarThreshold::Range(2.0))
+                .unwrap()
+                .sequence,
+            2
+        );
+    }
+
+    #[test]
+    pub fn invalid_thresholds() {
+        for threshold in [
+            BarThreshold::Ticks(0),
+            BarThreshold::Volume(0.0),
+            BarThreshold::Volume(-1.0),
+            BarThreshold::Range(-2.0),
+            BarThreshold::Range(f64::NAN),
+        ] {
+            assert!(BarBuilder::new(vec![BarThreshold::Ticks(3), threshold]).is_none());
+        }
+    }
+}
@@ -1,19 +1,84 @@
 use crate::shared::candle_interval::CandleInterval;
+use crate::shared::candles_store::StoredCandle;
+use crate::shared::gap_fill::FlatCandle;
+use crate::shared::ohlc::Ohlc;
+use crate::shared::snapshot::SnapshotDatetimes;
+use crate::shared::symbols::Symbol;
+use crate::shared::volume_profile::VolumeProfile;
 use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
 use serde_with::{serde_as, TimestampSecondsWithFrac};
 
-#[derive(Clone)]
+/// Price candle of the instrument. Serialized as JSON object:
+/// ```json
+/// {
+///   "interval": 0,
+///   "date": "2000-01-01T00:00:00Z",
+///   "instrument": "EURUSD",
+///   "bid_data": {"open": 1.1, "close": 1.2, "high": 1.3, "low": 1.0, "datetime": 946684830.5, "volume": 2.0},
+///   "ask_data": {"open": 1.1, "close": 1.2, "high": 1.3, "low": 1.0, "datetime": 946684830.5, "volume": 2.0},
+///   "mid_data": {"open": 1.1, "close": 1.2, "high": 1.3, "low": 1.0, "datetime": 946684830.5, "volume": 2.0},
+///   "spread_data": {"open": 0.1, "close": 0.2, "high": 0.2, "low": 0.1, "sum": 0.3, "count": 2}
+/// }
+/// ```
+/// `interval` is the `CandleInterval` number, `date` is the RFC 3339 start date of the interval,
+/// `datetime` is the unix timestamp in seconds of the latest price. `mid_data` and `spread_data`
+/// are optional for candles serialized before they were added. `volume_profile` is present only
+/// for intervals with enabled volume profiles
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BidAskCandle {
+    #[serde(rename = "interval", alias = "index")]
     pub index: CandleInterval,
     pub date: DateTime<Utc>,
-    pub instrument: String,
+    pub instrument: Symbol,
     // todo: use shared::CandleData
     pub bid_data: BidAskCandleData,
     pub ask_data: BidAskCandleData,
+    /// Mid price of every tick, volume is the average of bid and ask volumes
+    #[serde(default)]
+    pub mid_data: BidAskCandleData,
+    /// Ask minus bid of every tick
+    #[serde(default)]
+    pub spread_data: SpreadCandleData,
+    /// Mid price volume profile of the ticks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_profile: Option<VolumeProfile>,
 }
 
 impl BidAskCandle {
+    pub fn new(
+        interval: CandleInterval,
+        date: DateTime<Utc>,
+        instrument: Symbol,
+        datetime: DateTime<Utc>,
+        (bid, ask, bid_vol, ask_vol): (f64, f64, f64, f64),
+    ) -> Self {
+        Self {
+            index: interval,
+            date,
+            instrument,
+            bid_data: BidAskCandleData::new(datetime, bid, bid_vol),
+            ask_data: BidAskCandleData::new(datetime, ask, ask_vol),
+            mid_data: BidAskCandleData::new(datetime, get_mid(bid, ask), get_mid(bid_vol, ask_vol)),
+            spread_data: SpreadCandleData::new(ask - bid),
+            volume_profile: None,
+        }
+    }
+
+    /// Enables the volume profile of the bucket size starting with the mid data of the candle.
+    /// The profile is not enabled for not finite or not positive bucket sizes
+    pub fn with_volume_profile(mut self, bucket_size: f64) -> Self {
+        self.volume_profile = VolumeProfile::new(bucket_size).map(|mut profile| {
+            if self.spread_data.count > 0 {
+                profile.add(self.mid_data.open, self.mid_data.volume);
+            }
+
+            profile
+        });
+
+        self
+    }
+
     pub fn update(
         &mut self,
         datetime: DateTime<Utc>,
@@ -24,28 +89,141 @@ impl BidAskCandle {
     ) {
         self.bid_data.update(datetime, bid, bid_vol);
         self.ask_data.update(datetime, ask, ask_vol);
+        self.mid_data
+            .update(datetime, get_mid(bid, ask), get_mid(bid_vol, ask_vol));
+        self.spread_data.update(ask - bid);
+
+        if let Some(profile) = self.volume_profile.as_mut() {
+            profile.add(get_mid(bid, ask), get_mid(bid_vol, ask_vol));
+        }
+    }
+
+    /// Extends the candle with the data of the next candle
+    pub fn merge(&mut self, next: &BidAskCandle) {
+        self.bid_data.merge(&next.bid_data);
+        self.ask_data.merge(&next.ask_data);
+        self.mid_data.merge(&next.mid_data);
+        self.spread_data.merge(&next.spread_data);
+
+        if let (Some(profile), Some(next_profile)) =
+            (self.volume_profile.as_mut(), next.volume_profile.as_ref())
+        {
+            profile.merge(next_profile);
+        }
     }
 
     pub fn generate_id(
         instrument: &str,
         candle_type: &CandleInterval,
         datetime: DateTime<Utc>,
+    ) -> String {
+        Self::generate_start_id(
+            instrument,
+            candle_type,
+            candle_type.get_start_date(datetime),
+        )
+    }
+
+    /// Same as `generate_id` for the already calculated start date of the interval
+    pub fn generate_start_id(
+        instrument: &str,
+        candle_type: &CandleInterval,
+        start_date: DateTime<Utc>,
     ) -> String {
         format!(
             "{}{}{}",
             candle_type.to_owned() as u8,
-            instrument.to_string(),
-            candle_type.get_start_date(datetime).timestamp(),
+            instrument,
+            start_date.timestamp(),
         )
     }
 
     pub fn get_id(&self) -> String {
         BidAskCandle::generate_id(&self.instrument, &self.index, self.date)
     }
+
+    pub fn get_ohlc(&self, side: PriceSide) -> Ohlc {
+        match side {
+            PriceSide::Bid => Ohlc::from(&self.bid_data),
+            PriceSide::Ask => Ohlc::from(&self.ask_data),
+            PriceSide::Mid => Ohlc::from(&self.mid_data),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PriceSide {
+    Bid,
+    Ask,
+    Mid,
+}
+
+impl StoredCandle for BidAskCandle {
+    fn get_owner(&self) -> Symbol {
+        self.instrument
+    }
+
+    fn get_interval(&self) -> CandleInterval {
+        self.index
+    }
+
+    fn get_date(&self) -> DateTime<Utc> {
+        self.date
+    }
+
+    fn get_id(&self) -> String {
+        BidAskCandle::get_id(self)
+    }
+}
+
+impl SnapshotDatetimes for BidAskCandle {
+    fn get_datetimes(&self) -> Vec<DateTime<Utc>> {
+        vec![
+            self.bid_data.datetime,
+            self.ask_data.datetime,
+            self.mid_data.datetime,
+        ]
+    }
+
+    fn set_datetimes(&mut self, datetimes: &[DateTime<Utc>]) {
+        self.bid_data.datetime = datetimes[0];
+        self.ask_data.datetime = datetimes[1];
+        self.mid_data.datetime = datetimes[2];
+    }
+}
+
+impl FlatCandle for BidAskCandle {
+    fn to_flat(&self, date: DateTime<Utc>) -> Self {
+        let date = self.index.get_start_date(date);
+
+        Self {
+            index: self.index,
+            date,
+            instrument: self.instrument,
+            bid_data: BidAskCandleData::new(date, self.bid_data.close, 0.0),
+            ask_data: BidAskCandleData::new(date, self.ask_data.close, 0.0),
+            mid_data: BidAskCandleData::new(date, self.mid_data.close, 0.0),
+            spread_data: SpreadCandleData {
+                count: 0,
+                ..SpreadCandleData::new(self.spread_data.close)
+            },
+            volume_profile: self.volume_profile.as_ref().map(VolumeProfile::to_empty),
+        }
+    }
+}
+
+impl From<&BidAskCandleData> for Ohlc {
+    fn from(value: &BidAskCandleData) -> Self {
+        Self::new(value.open, value.high, value.low, value.close)
+    }
+}
+
+fn get_mid(bid: f64, ask: f64) -> f64 {
+    (bid + ask) / 2.0
 }
 
 #[serde_as]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct BidAskCandleData {
     pub open: f64,
     pub close: f64,
@@ -89,4 +267,171 @@ impl BidAskCandleData {
     pub fn get_candle_date(&self, candle_type: CandleInterval) -> DateTime<Utc> {
         candle_type.get_start_date(self.datetime)
     }
+
+    /// Extends the data with the data of the next candle
+    pub fn merge(&mut self, next: &BidAskCandleData) {
+        self.close = next.close;
+        self.high = self.high.max(next.high);
+        self.low = self.low.min(next.low);
+        self.datetime = next.datetime;
+        self.volume += next.volume;
+    }
+}
+
+/// Spread statistics of the ticks. Empty data has zero count
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SpreadCandleData {
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    /// Sum of the tick spreads
+    pub sum: f64,
+    /// Ticks count
+    pub count: u64,
+}
+
+impl SpreadCandleData {
+    pub fn new(spread: f64) -> Self {
+        Self {
+            open: spread,
+            close: spread,
+            high: spread,
+            low: spread,
+            sum: spread,
+            count: 1,
+        }
+    }
+
+    pub fn update(&mut self, spread: f64) {
+        if self.count == 0 {
+            *self = Self::new(spread);
+            return;
+        }
+
+        self.close = spread;
+        self.high = self.high.max(spread);
+        self.low = self.low.min(spread);
+        self.sum += spread;
+        self.count += 1;
+    }
+
+    pub fn get_average(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        self.sum / self.count as f64
+    }
+
+    /// Extends the data with the data of the next candle
+    pub fn merge(&mut self, next: &SpreadCandleData) {
+        if next.count == 0 {
+            return;
+        }
+
+        if self.count == 0 {
+            *self = next.clone();
+            return;
+        }
+
+        self.close = next.close;
+        self.high = self.high.max(next.high);
+        self.low = self.low.min(next.low);
+        self.sum += next.sum;
+        self.count += next.count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn get_candle() -> BidAskCandle {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let datetime = date + Duration::milliseconds(30500);
+        let mut candle = BidAskCandle::new(
+            CandleInterval::Minute,
+            date,
+            Symbol::new("EURUSD"),
+            date,
+            (1.0, 1.5, 1.0, 2.0),
+        );
+        candle.update(datetime, 1.25, 1.625, 1.5, 0.5);
+
+        candle
+    }
+
+    #[test]
+    pub fn serialize_matches_golden_file() {
+        let golden: serde_json::Value =
+            serde_json::from_str(include_str!("../../tests/fixtures/bid_ask_candle_v2.json"))
+                .unwrap();
+
+        let value = serde_json::to_value(get_candle()).unwrap();
+
+        assert_eq!(value, golden);
+    }
+
+    #[test]
+    pub fn deserialize_golden_file() {
+        let candle: BidAskCandle =
+            serde_json::from_str(include_str!("../../tests/fixtures/bid_ask_candle_v2.json"))
+                .unwrap();
+
+        assert_eq!(candle, get_candle());
+    }
+
+    #[test]
+    pub fn deserialize_without_mid_and_spread() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let datetime = date + Duration::milliseconds(30500);
+        let mut bid_data = BidAskCandleData::new(date, 1.1, 1.0);
+        bid_data.update(datetime, 1.05, 1.5);
+        let mut ask_data = BidAskCandleData::new(date, 1.2, 2.0);
+        ask_data.update(datetime, 1.25, 0.5);
+
+        let candle: BidAskCandle =
+            serde_json::from_str(include_str!("../../tests/fixtures/bid_ask_candle.json")).unwrap();
+
+        assert_eq!(
+            candle,
+            BidAskCandle {
+                index: CandleInterval::Minute,
+                date,
+                instrument: Symbol::new("EURUSD"),
+                bid_data,
+                ask_data,
+                mid_data: BidAskCandleData::default(),
+                spread_data: SpreadCandleData::default(),
+                volume_profile: None,
+            }
+        );
+    }
+
+    #[test]
+    pub fn spread_uses_tick_spreads() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut candle = BidAskCandle::new(
+            CandleInterval::Minute,
+            date,
+            Symbol::new("EURUSD"),
+            date,
+            (1.0, 1.5, 1.0, 3.0),
+        );
+        // bid high and ask low come from ticks with wide spreads
+        candle.update(date, 2.0, 2.25, 1.0, 1.0);
+        candle.update(date, 0.5, 0.75, 1.0, 1.0);
+
+        assert_eq!(candle.spread_data.high, 0.5);
+        assert_eq!(candle.spread_data.low, 0.25);
+        assert_eq!(candle.spread_data.close, 0.25);
+        assert_eq!(candle.spread_data.get_average(), 1.0 / 3.0);
+        assert_eq!(candle.ask_data.low - candle.bid_data.high, -1.25);
+        assert_eq!(candle.mid_data.open, 1.25);
+        assert_eq!(candle.mid_data.high, 2.125);
+        assert_eq!(candle.mid_data.low, 0.625);
+        assert_eq!(candle.mid_data.volume, 4.0);
+    }
 }
@@ -292,7 +292,7 @@ mod tests {
         let ids = pager.get_page_candle_ids();
         let mut count = 0;
 
-        while let Some(_) = pager.move_candle_id() {
+        while pager.move_candle_id().is_some() {
             count += 1;
         }
 
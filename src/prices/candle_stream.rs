@@ -0,0 +1,158 @@
+use crate::prices::candle::BidAskCandle;
+use crate::prices::candles_cache::BidAskCandlesCache;
+use crate::prices::tick::BidAskTick;
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::events::{CandleEvent, CandleEventKind};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver};
+use std::task::{Context, Poll};
+
+/// Turns a stream of ticks into a stream of candle events of the configured intervals.
+/// Ticks are pulled from the source only when the consumer polls for the next event
+/// and all events of the previous tick are consumed
+pub struct BidAskCandleStream<S> {
+    ticks: S,
+    cache: BidAskCandlesCache,
+    events: Receiver<CandleEvent<BidAskCandle>>,
+    kinds: Vec<CandleEventKind>,
+}
+
+impl<S: Stream<Item = BidAskTick> + Unpin> BidAskCandleStream<S> {
+    pub fn new(ticks: S, candle_intervals: Vec<CandleInterval>) -> Self {
+        Self::with_cache(ticks, BidAskCandlesCache::new(candle_intervals))
+    }
+
+    /// Uses preconfigured cache (retention, budget etc.) to build candles
+    pub fn with_cache(ticks: S, cache: BidAskCandlesCache) -> Self {
+        let mut cache = cache;
+        let (sender, events) = channel();
+        cache.add_listener(Box::new(sender));
+
+        Self {
+            ticks,
+            cache,
+            events,
+            kinds: vec![
+                CandleEventKind::Opened,
+                CandleEventKind::Updated,
+                CandleEventKind::Closed,
+            ],
+        }
+    }
+
+    /// Yields only events of closed candles
+    pub fn closed_only(self) -> Self {
+        self.with_kinds(vec![CandleEventKind::Closed])
+    }
+
+    pub fn with_kinds(mut self, kinds: Vec<CandleEventKind>) -> Self {
+        self.kinds = kinds;
+
+        self
+    }
+
+    pub fn get_cache(&self) -> &BidAskCandlesCache {
+        &self.cache
+    }
+
+    pub fn into_cache(self) -> BidAskCandlesCache {
+        self.cache
+    }
+}
+
+impl<S: Stream<Item = BidAskTick> + Unpin> Stream for BidAskCandleStream<S> {
+    type Item = CandleEvent<BidAskCandle>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            while let Ok(event) = this.events.try_recv() {
+                if this.kinds.contains(&event.kind) {
+                    return Poll::Ready(Some(event));
+                }
+            }
+
+            match Pin::new(&mut this.ticks).poll_next(cx) {
+                Poll::Ready(Some(tick)) => this.cache.insert_tick(&tick),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use tokio_stream::wrappers::ReceiverStream;
+    use tokio_stream::StreamExt;
+
+    fn get_tick(datetime: DateTime<Utc>, bid: f64) -> BidAskTick {
+        BidAskTick {
+            datetime,
+            instrument: "EURUSD".to_string(),
+            bid,
+            ask: bid + 0.0002,
+            bid_vol: 1.0,
+            ask_vol: 1.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn closed_candles_from_channel() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let producer = tokio::spawn(async move {
+            for i in 0..10 {
+                let tick = get_tick(date + Duration::seconds(i * 20), 1.0 + i as f64 / 100.0);
+                sender.send(tick).await.unwrap();
+            }
+        });
+        let stream =
+            BidAskCandleStream::new(ReceiverStream::new(receiver), vec![CandleInterval::Minute]);
+
+        let candles: Vec<_> = stream
+            .closed_only()
+            .map(|event| event.candle)
+            .collect()
+            .await;
+        producer.await.unwrap();
+
+        assert_eq!(candles.len(), 3);
+        assert_eq!(candles[0].date, date);
+        assert_eq!(candles[0].bid_data.open, 1.0);
+        assert_eq!(candles[0].bid_data.close, 1.02);
+        assert_eq!(candles[2].date, date + Duration::minutes(2));
+    }
+
+    #[tokio::test]
+    async fn all_events_from_channel() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let (sender, receiver) = tokio::sync::mpsc::channel(4);
+        sender.send(get_tick(date, 1.0)).await.unwrap();
+        sender
+            .send(get_tick(date + Duration::seconds(1), 1.1))
+            .await
+            .unwrap();
+        drop(sender);
+        let stream = BidAskCandleStream::new(
+            ReceiverStream::new(receiver),
+            vec![CandleInterval::Minute, CandleInterval::Hour],
+        );
+
+        let kinds: Vec<_> = stream.map(|event| event.kind).collect().await;
+
+        assert_eq!(
+            kinds,
+            vec![
+                CandleEventKind::Opened,
+                CandleEventKind::Opened,
+                CandleEventKind::Updated,
+                CandleEventKind::Updated,
+            ]
+        );
+    }
+}
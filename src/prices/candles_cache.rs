@@ -1,13 +1,32 @@
-use crate::prices::candle::{BidAskCandle, BidAskCandleData};
+use crate::prices::candle::BidAskCandle;
+use crate::prices::tick::BidAskTick;
+use crate::shared::batch::BatchItemOutcome;
+use crate::shared::budget::CacheBudget;
 use crate::shared::candle_interval::CandleInterval;
-use crate::shared::utils::calculate_candle_dates;
-use ahash::AHashMap;
+use crate::shared::candles_store::CandlesStore;
+use crate::shared::changes::ChangeSet;
+use crate::shared::events::CandleListener;
+use crate::shared::gap_fill::{GapFill, RangeCandle};
+use crate::shared::interval_overrides::{IntervalOverrides, KeyPattern};
+use crate::shared::replay::{ReplayError, ReplayOrder};
+use crate::shared::retention::RetentionPolicy;
+use crate::shared::snapshot::{
+    read_snapshot, write_snapshot, CacheSnapshot, SnapshotCandle, SnapshotDatetimes, SnapshotError,
+    SNAPSHOT_VERSION,
+};
+use crate::shared::symbols::Symbol;
+use crate::shared::volume_profile::VolumeProfile;
+use ahash::{AHashMap, AHashSet};
 use chrono::{DateTime, Utc};
+use std::io::{Read, Write};
 
 pub struct BidAskCandlesCache {
-    candles_by_ids: AHashMap<String, BidAskCandle>,
-    pub intervals: Vec<CandleInterval>,
+    store: CandlesStore<BidAskCandle>,
+    intervals: Vec<CandleInterval>,
     pub last_update_date: Option<DateTime<Utc>>,
+    start_dates: Vec<(CandleInterval, DateTime<Utc>)>,
+    interval_overrides: IntervalOverrides,
+    volume_profile_sizes: AHashMap<CandleInterval, f64>,
 }
 
 impl BidAskCandlesCache {
@@ -17,26 +36,255 @@ impl BidAskCandlesCache {
         candle_intervals.sort();
 
         Self {
-            candles_by_ids: AHashMap::new(),
+            store: CandlesStore::new(),
             intervals: candle_intervals,
             last_update_date: None,
+            start_dates: Vec::new(),
+            interval_overrides: IntervalOverrides::new(),
+            volume_profile_sizes: AHashMap::new(),
         }
     }
 
+    /// Overrides intervals of instruments matching the pattern. Existing candles of intervals
+    /// which are no longer used by their instruments are removed
+    pub fn set_intervals_override(&mut self, pattern: KeyPattern, intervals: Vec<CandleInterval>) {
+        self.interval_overrides.set(pattern, intervals);
+        self.remove_unused_intervals();
+    }
+
+    /// Removes the override and existing candles of intervals which are no longer used
+    /// by their instruments
+    pub fn remove_intervals_override(
+        &mut self,
+        pattern: &KeyPattern,
+    ) -> Option<Vec<CandleInterval>> {
+        let intervals = self.interval_overrides.remove(pattern)?;
+        self.remove_unused_intervals();
+
+        Some(intervals)
+    }
+
+    /// Gets overridden intervals of the instrument or the cache intervals
+    pub fn get_instrument_intervals(&self, instrument: &str) -> &[CandleInterval] {
+        self.interval_overrides
+            .resolve(instrument)
+            .unwrap_or(&self.intervals)
+    }
+
+    /// Gets the sorted intervals of the cache. Intervals are changed only by `add_interval`
+    /// and `remove_interval`, which keep candles of the intervals consistent
+    pub fn get_intervals(&self) -> &[CandleInterval] {
+        &self.intervals
+    }
+
+    /// Enables volume profiles of the bucket size for candles of the interval opened afterwards
+    /// Profile levels are not included in the memory usage estimate of the budget
+    /// Returns false for not finite or not positive bucket sizes
+    pub fn set_volume_profile(&mut self, interval: CandleInterval, bucket_size: f64) -> bool {
+        if VolumeProfile::new(bucket_size).is_none() {
+            return false;
+        }
+
+        self.volume_profile_sizes.insert(interval, bucket_size);
+
+        true
+    }
+
+    /// Disables volume profiles for candles of the interval opened afterwards
+    pub fn remove_volume_profile(&mut self, interval: CandleInterval) -> Option<f64> {
+        self.volume_profile_sizes.remove(&interval)
+    }
+
+    /// Gets the volume profile bucket size of the interval
+    pub fn get_volume_profile(&self, interval: CandleInterval) -> Option<f64> {
+        self.volume_profile_sizes.get(&interval).copied()
+    }
+
+    /// Adds the interval to the cache intervals. Backfill builds candles of the interval
+    /// from cached candles of the coarsest interval fitting into it, the latest backfilled
+    /// candle of every instrument is forming. Volume profiles of backfilled candles are merged
+    /// from the source candles profiles. Returns backfilled candles count
+    pub fn add_interval(&mut self, interval: CandleInterval, backfill: bool) -> usize {
+        if self.intervals.contains(&interval) {
+            return 0;
+        }
+
+        self.intervals.push(interval);
+        self.intervals.sort();
+
+        if !backfill {
+            return 0;
+        }
+
+        let mut sources: AHashMap<Symbol, CandleInterval> = AHashMap::new();
+
+        for candle in self.store.get_all().values() {
+            if !candle.index.fits_into(interval)
+                || !self
+                    .get_instrument_intervals(&candle.instrument)
+                    .contains(&interval)
+            {
+                continue;
+            }
+
+            let source = sources.entry(candle.instrument).or_insert(candle.index);
+
+            if get_seconds(candle.index) > get_seconds(*source) {
+                *source = candle.index;
+            }
+        }
+
+        let mut source_candles: Vec<&BidAskCandle> = self
+            .store
+            .get_all()
+            .values()
+            .filter(|candle| sources.get(&candle.instrument) == Some(&candle.index))
+            .collect();
+        source_candles.sort_by_key(|candle| (candle.instrument, candle.date));
+        let mut candles: Vec<BidAskCandle> = Vec::new();
+
+        for source in source_candles {
+            let date = interval.get_start_date(source.date);
+
+            match candles.last_mut() {
+                Some(candle) if candle.instrument == source.instrument && candle.date == date => {
+                    candle.merge(source);
+                }
+                _ => {
+                    let mut candle = source.clone();
+                    candle.index = interval;
+                    candle.date = date;
+                    candle.volume_profile =
+                        source.volume_profile.as_ref().and_then(|source_profile| {
+                            let mut profile =
+                                VolumeProfile::new(self.get_volume_profile(interval)?)?;
+                            profile.merge(source_profile);
+
+                            Some(profile)
+                        });
+                    candles.push(candle);
+                }
+            }
+        }
+
+        let mut backfilled_count = 0;
+
+        for candle in candles {
+            if self.store.contains(&candle.get_id()) {
+                continue;
+            }
+
+            self.store.insert(candle, false);
+            backfilled_count += 1;
+        }
+
+        self.store.enforce_budget();
+
+        backfilled_count
+    }
+
+    /// Removes the interval from the cache intervals and purges its candles of instruments
+    /// which overridden intervals don't contain it. Returns removed candles count
+    pub fn remove_interval(&mut self, interval: CandleInterval) -> usize {
+        self.intervals.retain(|existing| *existing != interval);
+        let interval_overrides = self.interval_overrides.clone();
+        let intervals = self.intervals.clone();
+
+        let removed_counts = self.store.remove_unused(|instrument, candle_interval| {
+            candle_interval != interval
+                || interval_overrides
+                    .resolve(&instrument)
+                    .unwrap_or(&intervals)
+                    .contains(&interval)
+        });
+
+        removed_counts.values().sum()
+    }
+
+    /// Registers listener of candle lifecycle events emitted by `insert_or_update`
+    pub fn add_listener(&mut self, listener: Box<dyn CandleListener<BidAskCandle>>) {
+        self.store.add_listener(listener);
+    }
+
+    pub fn clear_listeners(&mut self) {
+        self.store.clear_listeners();
+    }
+
+    /// Sets retention policy for the interval. Expired candles are evicted when a new candle
+    /// of the interval is inserted or on `enforce_retention` call.
+    /// Returns false for not valid policies
+    pub fn set_retention(&mut self, interval: CandleInterval, policy: RetentionPolicy) -> bool {
+        self.store.set_retention(interval, policy)
+    }
+
+    pub fn remove_retention(&mut self, interval: CandleInterval) -> Option<RetentionPolicy> {
+        self.store.remove_retention(interval)
+    }
+
+    pub fn get_retention(&self, interval: CandleInterval) -> Option<&RetentionPolicy> {
+        self.store.get_retention(interval)
+    }
+
+    /// Sets entries count or memory budget. Candles are evicted when a new candle is inserted
+    /// and the budget is exceeded down to the low-water mark of the budget (90% of the limits),
+    /// the currently forming candles are never evicted
+    pub fn set_budget(&mut self, budget: Option<CacheBudget>) {
+        self.store.set_budget(budget);
+    }
+
+    pub fn get_budget(&self) -> Option<&CacheBudget> {
+        self.store.get_budget()
+    }
+
+    /// Enables tracking of created and modified candles. Removed candles are not tracked
+    pub fn enable_change_tracking(&mut self) {
+        self.store.enable_change_tracking();
+    }
+
+    pub fn disable_change_tracking(&mut self) {
+        self.store.disable_change_tracking();
+    }
+
+    /// Gets version of the latest tracked change
+    pub fn get_changes_version(&self) -> u64 {
+        self.store.get_changes_version()
+    }
+
+    /// Gets candles created or modified after the specified version
+    pub fn get_changed_since(&self, version: u64) -> Vec<&BidAskCandle> {
+        self.store.get_changed_since(version)
+    }
+
+    /// Takes ids of candles created or modified since the previous call
+    pub fn take_changes(&mut self) -> ChangeSet<String> {
+        self.store.take_changes()
+    }
+
+    /// Gets approximate memory used by cached candles in bytes
+    pub fn estimate_memory_usage(&self) -> usize {
+        self.store.estimate_memory_usage()
+    }
+
     pub fn get_all(&self) -> &AHashMap<String, BidAskCandle> {
-        &self.candles_by_ids
+        self.store.get_all()
     }
 
     pub fn len(&self) -> usize {
-        self.candles_by_ids.len()
+        self.store.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.candles_by_ids.is_empty()
+        self.store.len() == 0
     }
 
     pub fn contains(&self, candle_id: &str) -> bool {
-        self.candles_by_ids.contains_key(candle_id)
+        self.store.contains(candle_id)
+    }
+
+    /// Checks if the candle is finalized by `close_expired`. Finalized candles are not updated
+    /// anymore
+    pub fn is_closed(&self, candle_id: &str) -> bool {
+        self.store.is_closed(candle_id)
     }
 
     pub fn insert(&mut self, candle: BidAskCandle) {
@@ -46,10 +294,11 @@ impl BidAskCandlesCache {
             candle.instrument,
             candle.date.to_rfc3339(),
             candle.get_id(),
-            self.candles_by_ids.len() + 1
+            self.store.len() + 1
         );
 
-        self.candles_by_ids.insert(candle.get_id(), candle);
+        self.store.insert(candle, false);
+        self.store.enforce_budget();
     }
 
     pub fn insert_or_update(
@@ -61,64 +310,189 @@ impl BidAskCandlesCache {
         bid_vol: f64,
         ask_vol: f64,
     ) {
-        for interval in self.intervals.iter() {
-            let candle_datetime = interval.get_start_date(datetime);
-            let id = BidAskCandle::generate_id(instrument, interval, candle_datetime);
-            let candle = self.candles_by_ids.get_mut(&id);
-
-            if let Some(candle) = candle {
-                candle.update(datetime, bid, ask, bid_vol, ask_vol);
-            } else {
-                #[cfg(feature = "console-log")]
-                println!(
-                    "insert candle {}: {} {}; {} total count",
-                    instrument.to_owned(),
-                    datetime.to_rfc3339(),
-                    id,
-                    self.candles_by_ids.len() + 1
-                );
+        // the buffer is reused to avoid allocations on every update
+        let mut start_dates = std::mem::take(&mut self.start_dates);
+        self.fill_start_dates(instrument, datetime, &mut start_dates);
+        let mut created_intervals = Vec::new();
+        self.upsert(
+            &start_dates,
+            datetime,
+            instrument,
+            (bid, ask, bid_vol, ask_vol),
+            &mut created_intervals,
+        );
+        self.start_dates = start_dates;
+        self.store.enforce_created(
+            created_intervals
+                .into_iter()
+                .map(|interval| (Symbol::new(instrument), interval)),
+        );
+        self.last_update_date.replace(Utc::now());
+    }
 
-                self.candles_by_ids.insert(
-                    id,
-                    BidAskCandle {
-                        ask_data: BidAskCandleData::new(datetime, ask, ask_vol),
-                        bid_data: BidAskCandleData::new(datetime, bid, bid_vol),
-                        index: interval.to_owned(),
-                        instrument: instrument.to_string(),
-                        date: candle_datetime,
-                    },
+    /// Inserts the ticks calculating interval start dates once per run of ticks sharing
+    /// the datetime. Retention and budget are enforced once after the whole batch.
+    /// Returns outcomes in the order of ticks
+    pub fn insert_or_update_batch(&mut self, ticks: &[BidAskTick]) -> Vec<BatchItemOutcome> {
+        let mut outcomes = Vec::with_capacity(ticks.len());
+        let all_intervals = self.interval_overrides.get_all_intervals(&self.intervals);
+        let mut all_start_dates = Vec::with_capacity(all_intervals.len());
+        let mut start_dates = Vec::with_capacity(all_intervals.len());
+        let mut start_datetime = None;
+        let mut created_intervals = Vec::new();
+        let mut created_keys: AHashSet<(Symbol, CandleInterval)> = AHashSet::new();
+
+        for tick in ticks {
+            if start_datetime != Some(tick.datetime) {
+                all_start_dates.clear();
+                all_start_dates.extend(
+                    all_intervals
+                        .iter()
+                        .map(|interval| (*interval, interval.get_start_date(tick.datetime))),
                 );
+                start_datetime = Some(tick.datetime);
             }
+
+            let intervals = self.get_instrument_intervals(&tick.instrument);
+            start_dates.clear();
+            start_dates.extend(
+                all_start_dates
+                    .iter()
+                    .filter(|(interval, _)| intervals.contains(interval)),
+            );
+
+            created_intervals.clear();
+            outcomes.push(self.upsert(
+                &start_dates,
+                tick.datetime,
+                &tick.instrument,
+                (tick.bid, tick.ask, tick.bid_vol, tick.ask_vol),
+                &mut created_intervals,
+            ));
+            created_keys.extend(
+                created_intervals
+                    .iter()
+                    .map(|interval| (Symbol::new(&tick.instrument), *interval)),
+            );
         }
 
-        self.last_update_date.replace(Utc::now());
-    }
+        self.store.enforce_created(created_keys);
 
-    /// Gets candles with date bigger or equals specified date
-    pub fn get_after(&self, datetime: DateTime<Utc>) -> Option<Vec<&BidAskCandle>> {
-        if self.candles_by_ids.len() == 0 {
-            return None;
+        if !ticks.is_empty() {
+            self.last_update_date.replace(Utc::now());
         }
 
-        let candle_dates = calculate_candle_dates(&self.intervals, datetime, None);
+        outcomes
+    }
 
-        let candles = self
-            .candles_by_ids
-            .iter()
-            .filter_map(|(_id, candle)| {
-                let current_date = candle_dates
-                    .get(&candle.index)
-                    .expect("wrong calculate_candle_dates");
-
-                if candle.date >= *current_date {
-                    Some(candle)
-                } else {
-                    None
+    fn fill_start_dates(
+        &self,
+        instrument: &str,
+        datetime: DateTime<Utc>,
+        start_dates: &mut Vec<(CandleInterval, DateTime<Utc>)>,
+    ) {
+        start_dates.clear();
+        start_dates.extend(
+            self.get_instrument_intervals(instrument)
+                .iter()
+                .map(|interval| (*interval, interval.get_start_date(datetime))),
+        );
+    }
+
+    /// Updates forming candles and opens missing ones without enforcing retention and budget.
+    /// Pushes intervals of opened candles to the created intervals
+    fn upsert(
+        &mut self,
+        start_dates: &[(CandleInterval, DateTime<Utc>)],
+        datetime: DateTime<Utc>,
+        instrument: &str,
+        (bid, ask, bid_vol, ask_vol): (f64, f64, f64, f64),
+        created_intervals: &mut Vec<CandleInterval>,
+    ) -> BatchItemOutcome {
+        let volume_profile_sizes = &self.volume_profile_sizes;
+
+        self.store.upsert(
+            instrument,
+            start_dates,
+            |candle| candle.update(datetime, bid, ask, bid_vol, ask_vol),
+            |instrument, interval, start_date| {
+                let candle = BidAskCandle::new(
+                    interval,
+                    start_date,
+                    instrument,
+                    datetime,
+                    (bid, ask, bid_vol, ask_vol),
+                );
+
+                match volume_profile_sizes.get(&interval) {
+                    Some(bucket_size) => candle.with_volume_profile(*bucket_size),
+                    None => candle,
                 }
-            })
-            .collect();
+            },
+            created_intervals,
+        )
+    }
+
+    pub fn insert_tick(&mut self, tick: &BidAskTick) {
+        self.insert_or_update(
+            tick.datetime,
+            &tick.instrument,
+            tick.bid,
+            tick.ask,
+            tick.bid_vol,
+            tick.ask_vol,
+        );
+    }
+
+    /// Finalizes candles which interval ended before or at the specified date. Carry forward
+    /// opens flat candles with the close price of the finalized forming candles
+    /// for the interval containing the specified date. Returns ids of finalized candles
+    pub fn close_expired(&mut self, now: DateTime<Utc>, carry_forward: bool) -> Vec<String> {
+        self.store.close_expired(now, carry_forward)
+    }
+
+    /// Evicts candles expired by retention policies. Returns evicted candles count per interval
+    pub fn enforce_retention(&mut self) -> AHashMap<CandleInterval, usize> {
+        self.store.evict_expired(None)
+    }
+
+    /// Evicts candles until the budget is satisfied. Returns evicted candles count
+    pub fn enforce_budget(&mut self) -> usize {
+        self.store.enforce_budget()
+    }
 
-        Some(candles)
+    /// Gets candles with date bigger or equals specified date
+    pub fn get_after(&self, datetime: DateTime<Utc>) -> Option<Vec<&BidAskCandle>> {
+        self.store.get_after(datetime, |candle| {
+            self.get_instrument_intervals(&candle.instrument)
+                .contains(&candle.index)
+        })
+    }
+
+    /// Gets cached candles of the instrument for every interval date in the specified range
+    pub fn get_range(
+        &self,
+        instrument: &str,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Vec<&BidAskCandle> {
+        self.store
+            .get_range(instrument, interval, start_date, end_date)
+    }
+
+    /// Gets candles of the instrument for every interval date in the specified range
+    /// with missing candles handled by the specified gap fill
+    pub fn get_range_filled(
+        &self,
+        instrument: &str,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        fill: GapFill,
+    ) -> Vec<RangeCandle<'_, BidAskCandle>> {
+        self.store
+            .get_range_filled(instrument, interval, start_date, end_date, fill)
     }
 
     /// Removes candles with date less or equals specified date
@@ -127,40 +501,849 @@ impl BidAskCandlesCache {
         datetime: DateTime<Utc>,
         candle_type: Option<CandleInterval>,
     ) -> i32 {
-        let mut removed_count = 0;
+        let interval_overrides = &self.interval_overrides;
+        let intervals = &self.intervals;
 
-        if let Some(candle_type) = candle_type {
-            self.candles_by_ids.retain(|_id, candle| {
-                let current_date = candle_type.get_start_date(datetime);
+        self.store.remove_before(datetime, candle_type, |candle| {
+            interval_overrides
+                .resolve(&candle.instrument)
+                .unwrap_or(intervals)
+                .contains(&candle.index)
+        })
+    }
 
-                if candle.date <= current_date && candle.index == candle_type {
-                    removed_count += 1;
-                    false
-                } else {
-                    true
-                }
-            });
-        } else {
-            let dates = calculate_candle_dates(&self.intervals, datetime, None);
-
-            self.candles_by_ids.retain(|_id, candle| {
-                let current_date = dates
-                    .get(&candle.index)
-                    .expect("Wrong calculate_candle_dates");
-
-                if candle.date <= *current_date {
-                    removed_count += 1;
-                    false
-                } else {
-                    true
-                }
-            });
+    pub fn get(&self, id: &str) -> Option<&BidAskCandle> {
+        self.store.get(id)
+    }
+
+    /// Writes intervals, last update date and all candles to the writer
+    pub fn save_snapshot(&self, writer: impl Write) -> Result<(), SnapshotError> {
+        let mut candles: Vec<_> = self
+            .store
+            .get_all()
+            .iter()
+            .map(|(id, candle)| SnapshotCandle {
+                closed: self.store.is_closed(id),
+                datetimes: candle.get_datetimes(),
+                candle,
+            })
+            .collect();
+        candles.sort_by_key(|candle| candle.candle.get_id());
+
+        write_snapshot(
+            writer,
+            &CacheSnapshot {
+                version: SNAPSHOT_VERSION,
+                intervals: self.intervals.clone(),
+                last_update_date: self.last_update_date,
+                candles,
+            },
+        )
+    }
+
+    /// Replaces intervals, last update date and all candles with the ones of the snapshot.
+    /// Retention, budget and listeners are kept, listeners are not notified
+    pub fn restore_snapshot(&mut self, reader: impl Read) -> Result<(), SnapshotError> {
+        let snapshot: CacheSnapshot<BidAskCandle> = read_snapshot(reader)?;
+        self.store.clear();
+
+        let mut intervals = snapshot.intervals;
+        intervals.sort();
+        intervals.dedup();
+        self.intervals = intervals;
+        self.last_update_date = snapshot.last_update_date;
+
+        for snapshot_candle in snapshot.candles {
+            let (candle, closed) = snapshot_candle.into_candle();
+            self.store.insert(candle, closed);
         }
 
-        removed_count
+        self.store.enforce_budget();
+
+        Ok(())
     }
 
-    pub fn get(&self, id: &str) -> Option<&BidAskCandle> {
-        self.candles_by_ids.get(id)
+    /// Inserts ticks of the time-ordered log as if they were received at their datetime.
+    /// Stops at the first tick earlier than the previous one. Returns replayed ticks count
+    pub fn replay(
+        &mut self,
+        ticks: impl IntoIterator<Item = BidAskTick>,
+    ) -> Result<usize, ReplayError> {
+        let mut order = ReplayOrder::default();
+
+        for tick in ticks {
+            order.check(tick.datetime)?;
+            self.insert_tick(&tick);
+            self.last_update_date.replace(tick.datetime);
+        }
+
+        Ok(order.get_count())
+    }
+
+    /// Stable checksum of all candles and their closed state which doesn't depend
+    /// on insertion order. Equal for caches with equal candles
+    pub fn get_checksum(&self) -> u64 {
+        self.store.get_checksum(|hasher, candle| {
+            for data in [&candle.bid_data, &candle.ask_data, &candle.mid_data] {
+                hasher.write_f64(data.open);
+                hasher.write_f64(data.close);
+                hasher.write_f64(data.high);
+                hasher.write_f64(data.low);
+                hasher.write_f64(data.volume);
+                hasher.write_date(data.datetime);
+            }
+
+            let spread = &candle.spread_data;
+            hasher.write_f64(spread.open);
+            hasher.write_f64(spread.close);
+            hasher.write_f64(spread.high);
+            hasher.write_f64(spread.low);
+            hasher.write_f64(spread.sum);
+            hasher.write(&spread.count.to_le_bytes());
+            hasher.write_volume_profile(candle.volume_profile.as_ref());
+        })
+    }
+
+    /// Removes candles and forming dates of intervals not used by their instruments
+    fn remove_unused_intervals(&mut self) {
+        let interval_overrides = &self.interval_overrides;
+        let intervals = &self.intervals;
+
+        self.store.remove_unused(|instrument, interval| {
+            interval_overrides
+                .resolve(&instrument)
+                .unwrap_or(intervals)
+                .contains(&interval)
+        });
+    }
+}
+
+fn get_seconds(interval: CandleInterval) -> i64 {
+    interval.get_duration(DateTime::UNIX_EPOCH).num_seconds()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::budget::EvictionStrategy;
+    use crate::shared::events::CandleEventKind;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn insert_or_update_evicts_by_max_count() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        cache.set_retention(CandleInterval::Minute, RetentionPolicy::MaxCount(3));
+
+        for i in 0..10 {
+            let datetime = date + Duration::minutes(i);
+            cache.insert_or_update(datetime, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+            cache.insert_or_update(datetime, "GBPUSD", 1.3, 1.4, 1.0, 1.0);
+        }
+
+        let minute_candles = cache
+            .get_all()
+            .values()
+            .filter(|c| c.index == CandleInterval::Minute)
+            .count();
+
+        assert_eq!(minute_candles, 6);
+        assert_eq!(cache.len(), 8);
+        assert!(cache.contains(&BidAskCandle::generate_id(
+            "EURUSD",
+            &CandleInterval::Minute,
+            date + Duration::minutes(9)
+        )));
+        assert!(!cache.contains(&BidAskCandle::generate_id(
+            "EURUSD",
+            &CandleInterval::Minute,
+            date + Duration::minutes(6)
+        )));
+
+        assert!(!cache.set_retention(CandleInterval::Minute, RetentionPolicy::MaxCount(0)));
+        assert_eq!(
+            cache.get_retention(CandleInterval::Minute),
+            Some(&RetentionPolicy::MaxCount(3))
+        );
+        assert_eq!(
+            cache.remove_retention(CandleInterval::Minute),
+            Some(RetentionPolicy::MaxCount(3))
+        );
+        assert!(cache.set_retention(CandleInterval::Minute, RetentionPolicy::MaxCount(1)));
+        assert_eq!(
+            cache.enforce_retention().get(&CandleInterval::Minute),
+            Some(&4)
+        );
+    }
+
+    #[test]
+    pub fn enforce_retention_by_max_age() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+
+        for i in 0..180 {
+            cache.insert_or_update(date + Duration::minutes(i), "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        }
+
+        cache.set_retention(
+            CandleInterval::Minute,
+            RetentionPolicy::MaxAge(Duration::minutes(30)),
+        );
+        cache.set_retention(
+            CandleInterval::Hour,
+            RetentionPolicy::MaxAge(Duration::hours(1)),
+        );
+        let evicted = cache.enforce_retention();
+
+        assert_eq!(evicted.get(&CandleInterval::Minute), Some(&149));
+        assert_eq!(evicted.get(&CandleInterval::Hour), Some(&1));
+        assert_eq!(cache.len(), 33);
+    }
+
+    #[test]
+    pub fn insert_or_update_evicts_by_budget() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        cache.set_budget(Some(CacheBudget {
+            max_entries: Some(10),
+            max_memory_bytes: None,
+            strategy: EvictionStrategy::OldestFirst,
+        }));
+
+        for i in 0..120 {
+            cache.insert_or_update(date + Duration::minutes(i), "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        }
+
+        let forming_id = BidAskCandle::generate_id(
+            "EURUSD",
+            &CandleInterval::Minute,
+            date + Duration::minutes(119),
+        );
+
+        assert_eq!(cache.len(), 10);
+        assert!(cache.contains(&forming_id));
+        assert!(cache.contains(&BidAskCandle::generate_id(
+            "EURUSD",
+            &CandleInterval::Hour,
+            date + Duration::hours(1)
+        )));
+    }
+
+    #[test]
+    pub fn enforce_budget_least_recently_read() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        cache.set_budget(Some(CacheBudget {
+            max_entries: None,
+            max_memory_bytes: None,
+            strategy: EvictionStrategy::LeastRecentlyRead,
+        }));
+
+        for i in 0..5 {
+            cache.insert_or_update(date + Duration::minutes(i), "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        }
+
+        let oldest_id = BidAskCandle::generate_id("EURUSD", &CandleInterval::Minute, date);
+        cache.get(&oldest_id);
+        let memory_usage = cache.estimate_memory_usage();
+        cache.set_budget(Some(CacheBudget {
+            max_entries: None,
+            max_memory_bytes: Some(memory_usage / 2),
+            strategy: EvictionStrategy::LeastRecentlyRead,
+        }));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.estimate_memory_usage() <= memory_usage / 2);
+        assert!(cache.contains(&oldest_id));
+    }
+
+    #[test]
+    pub fn get_changed_since_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache =
+            BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Month]);
+        cache.enable_change_tracking();
+
+        cache.insert_or_update(date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        cache.insert_or_update(date, "GBPUSD", 1.3, 1.4, 1.0, 1.0);
+        let version = cache.get_changes_version();
+        cache.insert_or_update(date + Duration::days(3), "EURUSD", 1.1, 1.2, 1.0, 1.0);
+
+        let mut changed_ids: Vec<_> = cache
+            .get_changed_since(version)
+            .into_iter()
+            .map(|candle| candle.get_id())
+            .collect();
+        changed_ids.sort();
+        let mut expected_ids = vec![
+            BidAskCandle::generate_id("EURUSD", &CandleInterval::Month, date),
+            BidAskCandle::generate_id("EURUSD", &CandleInterval::Minute, date + Duration::days(3)),
+        ];
+        expected_ids.sort();
+
+        assert_eq!(version, 4);
+        assert_eq!(changed_ids, expected_ids);
+    }
+
+    #[test]
+    pub fn take_changes_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        cache.enable_change_tracking();
+
+        cache.insert_or_update(date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        cache.insert_or_update(date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        let changes = cache.take_changes();
+        cache.remove_before(date, None);
+
+        assert_eq!(changes.version, 2);
+        assert_eq!(changes.keys.len(), 1);
+        assert_eq!(cache.take_changes().keys.len(), 0);
+    }
+
+    #[test]
+    pub fn insert_or_update_emits_events() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        cache.add_listener(Box::new(sender));
+
+        cache.insert_or_update(date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        cache.insert_or_update(date + Duration::seconds(30), "EURUSD", 1.3, 1.4, 1.0, 1.0);
+        cache.insert_or_update(date + Duration::seconds(60), "EURUSD", 1.0, 1.1, 1.0, 1.0);
+
+        let events: Vec<_> = receiver
+            .try_iter()
+            .map(|event| (event.kind, event.interval, event.candle.date))
+            .collect();
+
+        assert_eq!(
+            events,
+            vec![
+                (CandleEventKind::Opened, CandleInterval::Minute, date),
+                (CandleEventKind::Opened, CandleInterval::Hour, date),
+                (CandleEventKind::Updated, CandleInterval::Minute, date),
+                (CandleEventKind::Updated, CandleInterval::Hour, date),
+                (CandleEventKind::Updated, CandleInterval::Hour, date),
+                (CandleEventKind::Closed, CandleInterval::Minute, date),
+                (
+                    CandleEventKind::Opened,
+                    CandleInterval::Minute,
+                    date + Duration::minutes(1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn close_expired_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        cache.insert_or_update(date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        let minute_id = BidAskCandle::generate_id("EURUSD", &CandleInterval::Minute, date);
+
+        let closed_ids = cache.close_expired(date + Duration::seconds(59), false);
+        assert!(closed_ids.is_empty());
+
+        let closed_ids = cache.close_expired(date + Duration::minutes(5), false);
+        cache.insert_or_update(date + Duration::seconds(30), "EURUSD", 2.1, 2.2, 1.0, 1.0);
+
+        assert_eq!(closed_ids, vec![minute_id.clone()]);
+        assert!(cache.is_closed(&minute_id));
+        assert_eq!(cache.get(&minute_id).unwrap().bid_data.close, 1.1);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    pub fn insert_or_update_late_tick() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        let minute_id = BidAskCandle::generate_id("EURUSD", &CandleInterval::Minute, date);
+
+        cache.insert_or_update(date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        cache.insert_or_update(date + Duration::minutes(1), "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        cache.insert_or_update(date + Duration::seconds(30), "EURUSD", 1.5, 1.6, 2.0, 2.0);
+
+        assert!(!cache.is_closed(&minute_id));
+        assert_eq!(cache.get(&minute_id).unwrap().bid_data.high, 1.5);
+        assert_eq!(cache.get(&minute_id).unwrap().bid_data.volume, 3.0);
+
+        cache.close_expired(date + Duration::minutes(1), false);
+        cache.insert_or_update(date + Duration::seconds(40), "EURUSD", 1.7, 1.8, 1.0, 1.0);
+
+        assert!(cache.is_closed(&minute_id));
+        assert_eq!(cache.get(&minute_id).unwrap().bid_data.high, 1.5);
+    }
+
+    #[test]
+    pub fn close_expired_three_and_seven_days() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 10, 0, 0, 0).unwrap();
+
+        for interval in [CandleInterval::ThreeDays, CandleInterval::SevenDays] {
+            let mut cache = BidAskCandlesCache::new(vec![interval]);
+            let start_date = interval.get_start_date(date);
+            let next_date = interval.get_nth_date(start_date, 1).unwrap();
+            let id = BidAskCandle::generate_id("EURUSD", &interval, start_date);
+            cache.insert_or_update(start_date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+
+            assert!(cache
+                .close_expired(next_date - Duration::seconds(1), true)
+                .is_empty());
+
+            cache.insert_or_update(
+                next_date - Duration::seconds(1),
+                "EURUSD",
+                1.5,
+                1.6,
+                1.0,
+                1.0,
+            );
+
+            assert_eq!(cache.get(&id).unwrap().bid_data.close, 1.5);
+            assert_eq!(cache.close_expired(next_date, true), vec![id.clone()]);
+
+            let carried_id = BidAskCandle::generate_id("EURUSD", &interval, next_date);
+
+            assert_eq!(cache.len(), 2);
+            assert_eq!(cache.get(&id).unwrap().bid_data.volume, 2.0);
+            assert_eq!(cache.get(&carried_id).unwrap().bid_data.open, 1.5);
+            assert!(!cache.is_closed(&carried_id));
+        }
+    }
+
+    #[test]
+    pub fn close_expired_carry_forward() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        cache.insert_or_update(date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        cache.insert_or_update(date + Duration::seconds(10), "EURUSD", 1.3, 1.4, 1.0, 1.0);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        cache.add_listener(Box::new(sender));
+
+        let now = date + Duration::minutes(3) + Duration::seconds(5);
+        let closed_ids = cache.close_expired(now, true);
+        let carried_id = BidAskCandle::generate_id("EURUSD", &CandleInterval::Minute, now);
+        let carried_candle = cache.get(&carried_id).unwrap();
+        let events: Vec<_> = receiver.try_iter().map(|event| event.kind).collect();
+
+        assert_eq!(closed_ids.len(), 1);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_closed(&carried_id));
+        assert_eq!(carried_candle.bid_data.open, 1.3);
+        assert_eq!(carried_candle.ask_data.high, 1.4);
+        assert_eq!(carried_candle.ask_data.volume, 0.0);
+        assert_eq!(
+            events,
+            vec![CandleEventKind::Closed, CandleEventKind::Opened]
+        );
+    }
+
+    #[test]
+    pub fn get_range_three_days() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 6, 0, 0, 0).unwrap();
+        let next_date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 13, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::ThreeDays]);
+        cache.insert_or_update(date, "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        cache.insert_or_update(next_date, "EURUSD", 1.3, 1.4, 1.0, 1.0);
+
+        let dates: Vec<_> = cache
+            .get_range(
+                "EURUSD",
+                CandleInterval::ThreeDays,
+                date,
+                next_date + Duration::days(1),
+            )
+            .iter()
+            .map(|candle| candle.date)
+            .collect();
+
+        assert_eq!(dates, vec![date, next_date]);
+    }
+
+    #[test]
+    pub fn get_range_filled_carry_forward() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        cache.insert_or_update(date + Duration::minutes(1), "EURUSD", 1.1, 1.2, 1.0, 1.0);
+        cache.insert_or_update(date + Duration::minutes(4), "EURUSD", 1.3, 1.4, 1.0, 1.0);
+
+        let range = cache.get_range_filled(
+            "EURUSD",
+            CandleInterval::Minute,
+            date,
+            date + Duration::minutes(4),
+            GapFill::CarryForward,
+        );
+        let synthetic_candle = range[1].get_candle().unwrap();
+
+        assert_eq!(range.len(), 4);
+        assert!(!range[0].is_synthetic());
+        assert!(range[1].is_synthetic());
+        assert!(range[2].is_synthetic());
+        assert_eq!(synthetic_candle.date, date + Duration::minutes(2));
+        assert_eq!(synthetic_candle.bid_data.open, 1.1);
+        assert_eq!(synthetic_candle.bid_data.volume, 0.0);
+        assert_eq!(range[3].get_candle().unwrap().bid_data.open, 1.3);
+        assert_eq!(
+            cache
+                .get_range(
+                    "EURUSD",
+                    CandleInterval::Minute,
+                    date,
+                    date + Duration::minutes(4)
+                )
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    pub fn restore_snapshot_continues_forming_candles() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Month];
+        let mut cache = BidAskCandlesCache::new(intervals.clone());
+        let mut expected_cache = BidAskCandlesCache::new(intervals);
+
+        for i in 0..5 {
+            let datetime = date + Duration::seconds(i * 40);
+            cache.insert_or_update(datetime, "EURUSD", 1.0 + i as f64, 2.0, 1.0, 1.0);
+            expected_cache.insert_or_update(datetime, "EURUSD", 1.0 + i as f64, 2.0, 1.0, 1.0);
+        }
+
+        let mut bytes = Vec::new();
+        cache.save_snapshot(&mut bytes).unwrap();
+        let mut restored_cache = BidAskCandlesCache::new(vec![]);
+        restored_cache.restore_snapshot(bytes.as_slice()).unwrap();
+
+        assert_eq!(restored_cache.last_update_date, cache.last_update_date);
+
+        let datetime = date + Duration::seconds(200);
+        restored_cache.insert_or_update(datetime, "EURUSD", 0.5, 2.0, 1.0, 1.0);
+        expected_cache.insert_or_update(datetime, "EURUSD", 0.5, 2.0, 1.0, 1.0);
+
+        assert_eq!(restored_cache.intervals, expected_cache.intervals);
+        assert_eq!(restored_cache.len(), expected_cache.len());
+        assert_eq!(
+            restored_cache.estimate_memory_usage(),
+            expected_cache.estimate_memory_usage()
+        );
+
+        for (id, expected) in expected_cache.get_all() {
+            let candle = restored_cache.get(id).unwrap();
+
+            assert_eq!(restored_cache.is_closed(id), expected_cache.is_closed(id));
+            assert_eq!(candle.bid_data.open, expected.bid_data.open);
+            assert_eq!(candle.bid_data.low, expected.bid_data.low);
+            assert_eq!(candle.bid_data.close, expected.bid_data.close);
+            assert_eq!(candle.bid_data.volume, expected.bid_data.volume);
+            assert_eq!(candle.bid_data.datetime, expected.bid_data.datetime);
+        }
+    }
+
+    #[test]
+    pub fn restore_snapshot_keeps_sub_second_datetimes() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        cache.insert_or_update(
+            date + Duration::nanoseconds(123_456_789),
+            "EURUSD",
+            1.0,
+            2.0,
+            1.0,
+            1.0,
+        );
+        cache.insert_or_update(
+            date + Duration::nanoseconds(987_654_321),
+            "EURUSD",
+            1.5,
+            2.0,
+            1.0,
+            1.0,
+        );
+
+        let mut bytes = Vec::new();
+        cache.save_snapshot(&mut bytes).unwrap();
+        let mut restored_cache = BidAskCandlesCache::new(vec![]);
+        restored_cache.restore_snapshot(bytes.as_slice()).unwrap();
+
+        for (id, expected) in cache.get_all() {
+            assert_eq!(restored_cache.get(id).unwrap(), expected);
+        }
+
+        assert_eq!(restored_cache.get_checksum(), cache.get_checksum());
+    }
+
+    #[test]
+    pub fn replay_is_deterministic() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let ticks: Vec<BidAskTick> = (0..200)
+            .map(|i| BidAskTick {
+                datetime: date + Duration::seconds(i * 13),
+                instrument: if i % 2 == 0 { "EURUSD" } else { "GBPUSD" }.to_string(),
+                bid: 1.0 + (i % 7) as f64 / 100.0,
+                ask: 1.0002 + (i % 7) as f64 / 100.0,
+                bid_vol: 1.0,
+                ask_vol: 2.0,
+            })
+            .collect();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Hour];
+        let mut cache = BidAskCandlesCache::new(intervals.clone());
+        let mut replayed_cache = BidAskCandlesCache::new(intervals);
+
+        assert_eq!(cache.replay(ticks.clone()), Ok(200));
+        assert_eq!(replayed_cache.replay(ticks.clone()), Ok(200));
+        assert_eq!(cache.get_checksum(), replayed_cache.get_checksum());
+        assert_eq!(cache.last_update_date, Some(ticks[199].datetime));
+
+        let mut changed_ticks = ticks;
+        changed_ticks[100].bid += 0.5;
+        let mut changed_cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        changed_cache.replay(changed_ticks).unwrap();
+
+        assert_ne!(cache.get_checksum(), changed_cache.get_checksum());
+    }
+
+    #[test]
+    pub fn replay_unordered_ticks() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let tick = BidAskTick {
+            datetime: date,
+            instrument: "EURUSD".to_string(),
+            bid: 1.0,
+            ask: 1.1,
+            bid_vol: 1.0,
+            ask_vol: 1.0,
+        };
+        let mut earlier_tick = tick.clone();
+        earlier_tick.datetime = date - Duration::seconds(1);
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+
+        let result = cache.replay(vec![tick.clone(), tick, earlier_tick.clone()]);
+
+        assert_eq!(
+            result,
+            Err(ReplayError::Unordered {
+                position: 2,
+                date: earlier_tick.datetime
+            })
+        );
+        assert_eq!(
+            cache
+                .get(&BidAskCandle::generate_id(
+                    "EURUSD",
+                    &CandleInterval::Minute,
+                    date
+                ))
+                .unwrap()
+                .bid_data
+                .volume,
+            2.0
+        );
+    }
+
+    #[test]
+    pub fn insert_or_update_batch_matches_single_inserts() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let ticks: Vec<BidAskTick> = (0..300)
+            .map(|i| BidAskTick {
+                datetime: date + Duration::seconds(i / 30 * 20),
+                instrument: format!("INSTRUMENT{}", i % 30),
+                bid: 1.0 + (i % 7) as f64,
+                ask: 2.0 + (i % 7) as f64,
+                bid_vol: 1.0,
+                ask_vol: 2.0,
+            })
+            .collect();
+        let intervals = vec![CandleInterval::Minute, CandleInterval::Hour];
+        let mut cache = BidAskCandlesCache::new(intervals.clone());
+        let mut batch_cache = BidAskCandlesCache::new(intervals);
+
+        for tick in ticks.iter() {
+            cache.insert_tick(tick);
+        }
+
+        let outcomes = batch_cache.insert_or_update_batch(&ticks);
+
+        assert_eq!(cache.get_checksum(), batch_cache.get_checksum());
+        assert_eq!(outcomes.len(), ticks.len());
+        assert_eq!(
+            outcomes[0],
+            BatchItemOutcome {
+                created: 2,
+                updated: 0,
+                skipped: 0
+            }
+        );
+        assert_eq!(
+            outcomes[30],
+            BatchItemOutcome {
+                created: 0,
+                updated: 2,
+                skipped: 0
+            }
+        );
+        assert_eq!(
+            outcomes[90],
+            BatchItemOutcome {
+                created: 1,
+                updated: 1,
+                skipped: 0
+            }
+        );
+    }
+
+    #[test]
+    pub fn insert_or_update_with_intervals_override() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Hour, CandleInterval::Day]);
+        cache.set_intervals_override(
+            KeyPattern::Exact("EURUSD".to_string()),
+            vec![CandleInterval::Minute, CandleInterval::Hour],
+        );
+
+        for i in 0..3 {
+            let datetime = date + Duration::minutes(i);
+            cache.insert_or_update(datetime, "EURUSD", 1.0, 1.1, 1.0, 1.0);
+            cache.insert_or_update(datetime, "GBPUSD", 1.0, 1.1, 1.0, 1.0);
+        }
+
+        let candles = cache.get_after(date + Duration::minutes(2)).unwrap();
+
+        assert_eq!(cache.len(), 6);
+        assert_eq!(candles.len(), 4);
+        assert!(candles
+            .iter()
+            .all(|candle| candle.instrument == "EURUSD" || candle.index != CandleInterval::Minute));
+
+        cache.remove_intervals_override(&KeyPattern::Exact("EURUSD".to_string()));
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get_after(date).unwrap().len(), 3);
+        assert_eq!(cache.remove_before(date + Duration::days(2), None), 3);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    pub fn add_interval_backfills_from_finer_candles() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        let mut expected_cache =
+            BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+
+        for i in 0..150 {
+            let datetime = date + Duration::seconds(i * 50);
+            // binary fractions keep merged spread sums exact
+            let price = 1.0 + (i % 13) as f64 / 128.0;
+            let spread = (i % 3) as f64 / 64.0;
+
+            for instrument in ["EURUSD", "GBPUSD"] {
+                cache.insert_or_update(datetime, instrument, price, price + spread, 1.0, 2.0);
+                expected_cache.insert_or_update(
+                    datetime,
+                    instrument,
+                    price,
+                    price + spread,
+                    1.0,
+                    2.0,
+                );
+            }
+        }
+
+        assert_eq!(cache.add_interval(CandleInterval::Hour, true), 6);
+        assert_eq!(cache.get_intervals(), expected_cache.get_intervals());
+        assert_eq!(cache.get_checksum(), expected_cache.get_checksum());
+
+        let datetime = date + Duration::seconds(150 * 50);
+        cache.insert_or_update(datetime, "EURUSD", 2.0, 2.1, 1.0, 1.0);
+        expected_cache.insert_or_update(datetime, "EURUSD", 2.0, 2.1, 1.0, 1.0);
+
+        assert_eq!(cache.get_checksum(), expected_cache.get_checksum());
+    }
+
+    #[test]
+    pub fn volume_profile_of_enabled_intervals() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        cache.set_volume_profile(CandleInterval::Hour, 0.5);
+        cache.set_volume_profile(CandleInterval::Day, 1.0);
+
+        for i in 0..120 {
+            let bid = 1.0 + (i % 4) as f64 * 0.25;
+            cache.insert_or_update(date + Duration::seconds(i), "EURUSD", bid, bid, 1.0, 3.0);
+        }
+
+        let hour_candle = cache
+            .get(&BidAskCandle::generate_id(
+                "EURUSD",
+                &CandleInterval::Hour,
+                date,
+            ))
+            .unwrap();
+        let profile = hour_candle.volume_profile.as_ref().unwrap();
+
+        assert_eq!(profile.get_total_volume(), hour_candle.mid_data.volume);
+        assert_eq!(
+            profile.get_levels().collect::<Vec<_>>(),
+            vec![(1.0, 120.0), (1.5, 120.0)]
+        );
+        assert_eq!(profile.get_point_of_control(), Some(1.0));
+        assert!(cache
+            .get(&BidAskCandle::generate_id(
+                "EURUSD",
+                &CandleInterval::Minute,
+                date
+            ))
+            .unwrap()
+            .volume_profile
+            .is_none());
+
+        assert_eq!(cache.add_interval(CandleInterval::Day, true), 1);
+
+        let day_profile = cache
+            .get(&BidAskCandle::generate_id(
+                "EURUSD",
+                &CandleInterval::Day,
+                date,
+            ))
+            .unwrap()
+            .volume_profile
+            .as_ref()
+            .unwrap();
+
+        assert_eq!(
+            day_profile.get_levels().collect::<Vec<_>>(),
+            vec![(1.0, 240.0)]
+        );
+
+        assert!(cache.set_volume_profile(CandleInterval::FiveMinutes, 1.0));
+        assert!(!cache.set_volume_profile(CandleInterval::FiveMinutes, 0.0));
+        assert_eq!(cache.add_interval(CandleInterval::FiveMinutes, true), 1);
+        assert!(cache
+            .get(&BidAskCandle::generate_id(
+                "EURUSD",
+                &CandleInterval::FiveMinutes,
+                date
+            ))
+            .unwrap()
+            .volume_profile
+            .is_none());
+    }
+
+    #[test]
+    pub fn remove_interval_purges_candles() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        cache.set_intervals_override(
+            KeyPattern::Exact("EURUSD".to_string()),
+            vec![CandleInterval::Minute],
+        );
+        cache.insert_or_update(date, "EURUSD", 1.0, 1.1, 1.0, 1.0);
+        cache.insert_or_update(date, "GBPUSD", 1.0, 1.1, 1.0, 1.0);
+
+        assert_eq!(cache.remove_interval(CandleInterval::Minute), 1);
+        assert_eq!(cache.get_intervals(), &[CandleInterval::Hour]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_after(date).unwrap().len(), 2);
+        assert_eq!(cache.remove_before(date, None), 2);
     }
 }
@@ -0,0 +1,824 @@
+use crate::prices::candle::{BidAskCandle, BidAskCandleData, SpreadCandleData};
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::symbols::Symbol;
+use crate::shared::volume_profile::VolumeProfile;
+use chrono::{DateTime, Utc};
+use std::fmt::{Display, Formatter};
+
+const MAGIC: &[u8; 4] = b"CNDL";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 22;
+const SIDE_SIZE: usize = 5 * 8 + 8 + 4;
+const SPREAD_SIZE: usize = 5 * 8 + 8;
+pub const RECORD_SIZE: usize = 4 + 3 * SIDE_SIZE + SPREAD_SIZE + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SeriesCompression {
+    None = 0,
+    Xor = 1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    UnexpectedEnd,
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    /// Candle of another instrument or interval
+    ForeignCandle(String),
+    /// Candle date is not a start date of the interval after the first candle date
+    InvalidDate(DateTime<Utc>),
+    DuplicateDate(DateTime<Utc>),
+    /// Volume profile bucket size is not finite or not positive
+    InvalidVolumeProfile,
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::UnexpectedEnd => write!(f, "unexpected end of series"),
+            CodecError::InvalidHeader => write!(f, "invalid series header"),
+            CodecError::UnsupportedVersion(version) => {
+                write!(f, "series version {version} is not supported")
+            }
+            CodecError::ForeignCandle(id) => {
+                write!(f, "candle {id} has another instrument or interval")
+            }
+            CodecError::InvalidDate(date) => write!(f, "candle date {date} is not a bucket date"),
+            CodecError::DuplicateDate(date) => write!(f, "candle date {date} is duplicated"),
+            CodecError::InvalidVolumeProfile => write!(f, "invalid volume profile bucket size"),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Encodes candles of the instrument and interval in date order.
+///
+/// Header: magic `CNDL`, version u8, compression u8, interval u8, reserved u8,
+/// base date seconds i64, records count u32, instrument length u16 and utf-8 instrument.
+/// All numbers are little-endian.
+///
+/// Not compressed records are fixed-width [`RECORD_SIZE`] bytes: interval index u32 of the
+/// candle date after the base date, then bid, ask and mid data as open, close, high, low, volume
+/// f64 bits and datetime as seconds i64 and nanoseconds u32, then spread data as open, close,
+/// high, low, sum f64 bits and count u64, then offset u32 of the volume profile in the profiles
+/// which follow the records.
+///
+/// Xor compressed records are varints: index delta to the previous record, prices xor-ed with
+/// the open and close prices of the record or the previous record and datetime as zigzag
+/// seconds offset to the candle date and nanoseconds, then spread prices xor-ed the same way,
+/// count and the volume profile. Compressed records can be read only sequentially.
+///
+/// Volume profile is varint levels count plus one or zero without the profile, then bucket size
+/// f64 bits and levels as zigzag varint bucket delta to the previous level and volume f64 bits
+pub fn encode_bid_ask_series(
+    instrument: &str,
+    interval: CandleInterval,
+    candles: &[BidAskCandle],
+    compression: SeriesCompression,
+) -> Result<Vec<u8>, CodecError> {
+    let mut candles: Vec<&BidAskCandle> = candles.iter().collect();
+    candles.sort_by_key(|candle| candle.date);
+    let base_date = candles
+        .first()
+        .map(|candle| candle.date)
+        .unwrap_or_default();
+    let instrument_len = u16::try_from(instrument.len()).map_err(|_| CodecError::InvalidHeader)?;
+    let count = u32::try_from(candles.len()).map_err(|_| CodecError::InvalidHeader)?;
+
+    let mut bytes = Vec::with_capacity(
+        HEADER_SIZE
+            + instrument.len()
+            + match compression {
+                SeriesCompression::None => candles.len() * RECORD_SIZE,
+                SeriesCompression::Xor => candles.len() * RECORD_SIZE / 2,
+            },
+    );
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[VERSION, compression as u8, interval as u8, 0]);
+    bytes.extend_from_slice(&base_date.timestamp().to_le_bytes());
+    bytes.extend_from_slice(&count.to_le_bytes());
+    bytes.extend_from_slice(&instrument_len.to_le_bytes());
+    bytes.extend_from_slice(instrument.as_bytes());
+
+    let mut previous: Option<(u32, [u64; 20])> = None;
+    let mut profiles = Vec::new();
+
+    for candle in candles {
+        if candle.instrument != instrument || candle.index != interval {
+            return Err(CodecError::ForeignCandle(candle.get_id()));
+        }
+
+        let index = interval
+            .get_date_index(base_date, candle.date)
+            .ok_or(CodecError::InvalidDate(candle.date))?;
+        let prices = get_price_bits(candle);
+
+        if matches!(previous, Some((previous_index, _)) if previous_index == index) {
+            return Err(CodecError::DuplicateDate(candle.date));
+        }
+
+        match compression {
+            SeriesCompression::None => {
+                bytes.extend_from_slice(&index.to_le_bytes());
+
+                for (side, data) in get_sides(candle).into_iter().enumerate() {
+                    for price in &prices[side * 5..side * 5 + 5] {
+                        bytes.extend_from_slice(&price.to_le_bytes());
+                    }
+
+                    bytes.extend_from_slice(&data.datetime.timestamp().to_le_bytes());
+                    bytes.extend_from_slice(&data.datetime.timestamp_subsec_nanos().to_le_bytes());
+                }
+
+                for price in &prices[15..] {
+                    bytes.extend_from_slice(&price.to_le_bytes());
+                }
+
+                bytes.extend_from_slice(&candle.spread_data.count.to_le_bytes());
+                let profile_offset =
+                    u32::try_from(profiles.len()).map_err(|_| CodecError::InvalidHeader)?;
+                bytes.extend_from_slice(&profile_offset.to_le_bytes());
+                write_profile(&mut profiles, candle.volume_profile.as_ref());
+            }
+            SeriesCompression::Xor => {
+                let (previous_index, previous_prices) = previous.unwrap_or_default();
+                write_varint(&mut bytes, (index - previous_index) as u64);
+
+                for (side, data) in get_sides(candle).into_iter().enumerate() {
+                    write_xor_prices(&mut bytes, &prices, &previous_prices, side);
+                    let offset = data.datetime.timestamp() - candle.date.timestamp();
+                    write_varint(&mut bytes, ((offset << 1) ^ (offset >> 63)) as u64);
+                    write_varint(&mut bytes, data.datetime.timestamp_subsec_nanos() as u64);
+                }
+
+                write_xor_prices(&mut bytes, &prices, &previous_prices, 3);
+                write_varint(&mut bytes, candle.spread_data.count);
+                write_profile(&mut bytes, candle.volume_profile.as_ref());
+            }
+        }
+
+        previous = Some((index, prices));
+    }
+
+    bytes.extend_from_slice(&profiles);
+
+    Ok(bytes)
+}
+
+pub fn decode_bid_ask_series(bytes: &[u8]) -> Result<Vec<BidAskCandle>, CodecError> {
+    BidAskSeriesView::new(bytes)?
+        .iter()
+        .map(|record| record.map(|record| record.to_candle()))
+        .collect()
+}
+
+/// Candle of the series which borrows the instrument from the encoded bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidAskSeriesRecord<'a> {
+    pub instrument: &'a str,
+    pub interval: CandleInterval,
+    pub date: DateTime<Utc>,
+    pub bid_data: BidAskCandleData,
+    pub ask_data: BidAskCandleData,
+    pub mid_data: BidAskCandleData,
+    pub spread_data: SpreadCandleData,
+    pub volume_profile: Option<VolumeProfile>,
+}
+
+impl BidAskSeriesRecord<'_> {
+    pub fn to_candle(&self) -> BidAskCandle {
+        BidAskCandle {
+            index: self.interval,
+            date: self.date,
+            instrument: Symbol::new(self.instrument),
+            bid_data: self.bid_data.clone(),
+            ask_data: self.ask_data.clone(),
+            mid_data: self.mid_data.clone(),
+            spread_data: self.spread_data.clone(),
+            volume_profile: self.volume_profile.clone(),
+        }
+    }
+}
+
+/// Reads records directly from the encoded bytes without copying them
+#[derive(Debug, Clone)]
+pub struct BidAskSeriesView<'a> {
+    instrument: &'a str,
+    interval: CandleInterval,
+    base_date: DateTime<Utc>,
+    count: usize,
+    compression: SeriesCompression,
+    records: &'a [u8],
+    /// Volume profiles of not compressed records
+    profiles: &'a [u8],
+}
+
+impl<'a> BidAskSeriesView<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, CodecError> {
+        let header = bytes.get(..HEADER_SIZE).ok_or(CodecError::UnexpectedEnd)?;
+
+        if &header[..4] != MAGIC {
+            return Err(CodecError::InvalidHeader);
+        }
+
+        let version = header[4];
+
+        if version != VERSION {
+            return Err(CodecError::UnsupportedVersion(version));
+        }
+
+        let compression = match header[5] {
+            0 => SeriesCompression::None,
+            1 => SeriesCompression::Xor,
+            _ => return Err(CodecError::InvalidHeader),
+        };
+        let interval =
+            CandleInterval::try_from(header[6] as i32).map_err(|_| CodecError::InvalidHeader)?;
+        let base_date =
+            DateTime::from_timestamp(read_i64(&header[8..]), 0).ok_or(CodecError::InvalidHeader)?;
+        let count = read_u32(&header[16..]) as usize;
+        let instrument_len = u16::from_le_bytes([header[20], header[21]]) as usize;
+        let instrument = bytes
+            .get(HEADER_SIZE..HEADER_SIZE + instrument_len)
+            .ok_or(CodecError::UnexpectedEnd)?;
+        let instrument = std::str::from_utf8(instrument).map_err(|_| CodecError::InvalidHeader)?;
+        let mut records = &bytes[HEADER_SIZE + instrument_len..];
+        let mut profiles: &[u8] = &[];
+
+        if compression == SeriesCompression::None {
+            let records_size = count * RECORD_SIZE;
+
+            if records.len() < records_size {
+                return Err(CodecError::UnexpectedEnd);
+            }
+
+            (records, profiles) = records.split_at(records_size);
+        }
+
+        Ok(Self {
+            instrument,
+            interval,
+            base_date,
+            count,
+            compression,
+            records,
+            profiles,
+        })
+    }
+
+    pub fn get_instrument(&self) -> &'a str {
+        self.instrument
+    }
+
+    pub fn get_interval(&self) -> CandleInterval {
+        self.interval
+    }
+
+    pub fn get_compression(&self) -> SeriesCompression {
+        self.compression
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Reads the record by its number. Returns None for compressed series
+    pub fn get(&self, no: usize) -> Option<Result<BidAskSeriesRecord<'a>, CodecError>> {
+        if self.compression != SeriesCompression::None || no >= self.count {
+            return None;
+        }
+
+        let record = &self.records[no * RECORD_SIZE..(no + 1) * RECORD_SIZE];
+        let index = read_u32(record);
+        let date = match self.interval.get_nth_date(self.base_date, index) {
+            Some(date) => date,
+            None => return Some(Err(CodecError::InvalidHeader)),
+        };
+        let read_data = |offset: usize| -> Option<BidAskCandleData> {
+            let price = |no: usize| f64::from_bits(read_u64(&record[offset + no * 8..]));
+
+            Some(BidAskCandleData {
+                open: price(0),
+                close: price(1),
+                high: price(2),
+                low: price(3),
+                volume: price(4),
+                datetime: DateTime::from_timestamp(
+                    read_i64(&record[offset + 40..]),
+                    read_u32(&record[offset + 48..]),
+                )?,
+            })
+        };
+
+        let spread_offset = 4 + 3 * SIDE_SIZE;
+        let spread_price = |no: usize| f64::from_bits(read_u64(&record[spread_offset + no * 8..]));
+        let spread_data = SpreadCandleData {
+            open: spread_price(0),
+            close: spread_price(1),
+            high: spread_price(2),
+            low: spread_price(3),
+            sum: spread_price(4),
+            count: read_u64(&record[spread_offset + 40..]),
+        };
+        let mut profile_position = read_u32(&record[RECORD_SIZE - 4..]) as usize;
+        let volume_profile = match read_profile(self.profiles, &mut profile_position) {
+            Ok(volume_profile) => volume_profile,
+            Err(error) => return Some(Err(error)),
+        };
+
+        match (
+            read_data(4),
+            read_data(4 + SIDE_SIZE),
+            read_data(4 + 2 * SIDE_SIZE),
+        ) {
+            (Some(bid_data), Some(ask_data), Some(mid_data)) => Some(Ok(BidAskSeriesRecord {
+                instrument: self.instrument,
+                interval: self.interval,
+                date,
+                bid_data,
+                ask_data,
+                mid_data,
+                spread_data,
+                volume_profile,
+            })),
+            _ => Some(Err(CodecError::InvalidHeader)),
+        }
+    }
+
+    pub fn iter(&self) -> BidAskSeriesIter<'a> {
+        BidAskSeriesIter {
+            view: self.clone(),
+            no: 0,
+            position: 0,
+            previous: (0, [0; 20]),
+        }
+    }
+}
+
+pub struct BidAskSeriesIter<'a> {
+    view: BidAskSeriesView<'a>,
+    no: usize,
+    position: usize,
+    previous: (u32, [u64; 20]),
+}
+
+impl<'a> BidAskSeriesIter<'a> {
+    fn read_compressed(&mut self) -> Result<BidAskSeriesRecord<'a>, CodecError> {
+        let records = self.view.records;
+        let position = &mut self.position;
+        let index_delta = read_varint(records, position)?;
+        let index = u32::try_from(self.previous.0 as u64 + index_delta)
+            .map_err(|_| CodecError::InvalidHeader)?;
+        let date = self
+            .view
+            .interval
+            .get_nth_date(self.view.base_date, index)
+            .ok_or(CodecError::InvalidHeader)?;
+        let mut prices = [0; 20];
+        let mut datetimes = [date; 3];
+        for (side, datetime) in datetimes.iter_mut().enumerate() {
+            read_xor_prices(records, position, &mut prices, &self.previous.1, side)?;
+            let offset = read_varint(records, position)?;
+            let offset = ((offset >> 1) as i64) ^ -((offset & 1) as i64);
+            let nanos = u32::try_from(read_varint(records, position)?)
+                .map_err(|_| CodecError::InvalidHeader)?;
+            *datetime = date
+                .timestamp()
+                .checked_add(offset)
+                .and_then(|seconds| DateTime::from_timestamp(seconds, nanos))
+                .ok_or(CodecError::InvalidHeader)?;
+        }
+
+        read_xor_prices(records, position, &mut prices, &self.previous.1, 3)?;
+        let spread_count = read_varint(records, position)?;
+        let volume_profile = read_profile(records, position)?;
+        self.previous = (index, prices);
+        let get_data = |side: usize| BidAskCandleData {
+            open: f64::from_bits(prices[side * 5]),
+            close: f64::from_bits(prices[side * 5 + 1]),
+            high: f64::from_bits(prices[side * 5 + 2]),
+            low: f64::from_bits(prices[side * 5 + 3]),
+            volume: f64::from_bits(prices[side * 5 + 4]),
+            datetime: datetimes[side],
+        };
+
+        Ok(BidAskSeriesRecord {
+            instrument: self.view.instrument,
+            interval: self.view.interval,
+            date,
+            bid_data: get_data(0),
+            ask_data: get_data(1),
+            mid_data: get_data(2),
+            spread_data: SpreadCandleData {
+                open: f64::from_bits(prices[15]),
+                close: f64::from_bits(prices[16]),
+                high: f64::from_bits(prices[17]),
+                low: f64::from_bits(prices[18]),
+                sum: f64::from_bits(prices[19]),
+                count: spread_count,
+            },
+            volume_profile,
+        })
+    }
+}
+
+impl<'a> Iterator for BidAskSeriesIter<'a> {
+    type Item = Result<BidAskSeriesRecord<'a>, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.no >= self.view.count {
+            return None;
+        }
+
+        let record = match self.view.compression {
+            SeriesCompression::None => self.view.get(self.no)?,
+            SeriesCompression::Xor => self.read_compressed(),
+        };
+
+        // stop after the first error as the next records position is unknown
+        self.no = if record.is_ok() {
+            self.no + 1
+        } else {
+            self.view.count
+        };
+
+        Some(record)
+    }
+}
+
+fn write_profile(bytes: &mut Vec<u8>, profile: Option<&VolumeProfile>) {
+    let Some(profile) = profile else {
+        write_varint(bytes, 0);
+        return;
+    };
+
+    write_varint(bytes, profile.get_buckets().count() as u64 + 1);
+    bytes.extend_from_slice(&profile.get_bucket_size().to_bits().to_le_bytes());
+    let mut previous_bucket = 0;
+
+    for (bucket, volume) in profile.get_buckets() {
+        let delta = bucket.wrapping_sub(previous_bucket);
+        write_varint(bytes, ((delta << 1) ^ (delta >> 63)) as u64);
+        bytes.extend_from_slice(&volume.to_bits().to_le_bytes());
+        previous_bucket = bucket;
+    }
+}
+
+fn read_profile(bytes: &[u8], position: &mut usize) -> Result<Option<VolumeProfile>, CodecError> {
+    let levels_count = match read_varint(bytes, position)? {
+        0 => return Ok(None),
+        count => count - 1,
+    };
+    let read_f64 = |position: &mut usize| {
+        let value = bytes
+            .get(*position..*position + 8)
+            .ok_or(CodecError::UnexpectedEnd)?;
+        *position += 8;
+
+        Ok::<f64, CodecError>(f64::from_bits(read_u64(value)))
+    };
+    let bucket_size = read_f64(position)?;
+    let mut profile = VolumeProfile::new(bucket_size).ok_or(CodecError::InvalidVolumeProfile)?;
+    let mut bucket = 0i64;
+
+    for _ in 0..levels_count {
+        let delta = read_varint(bytes, position)?;
+        bucket = bucket.wrapping_add(((delta >> 1) as i64) ^ -((delta & 1) as i64));
+        profile.add_to_bucket(bucket, read_f64(position)?);
+    }
+
+    Ok(Some(profile))
+}
+
+fn get_sides(candle: &BidAskCandle) -> [&BidAskCandleData; 3] {
+    [&candle.bid_data, &candle.ask_data, &candle.mid_data]
+}
+
+/// Gets bits of bid, ask and mid prices and volumes followed by spread prices and sum
+fn get_price_bits(candle: &BidAskCandle) -> [u64; 20] {
+    let mut prices = [0.0; 20];
+
+    for (side, data) in get_sides(candle).into_iter().enumerate() {
+        prices[side * 5..side * 5 + 5].copy_from_slice(&[
+            data.open,
+            data.close,
+            data.high,
+            data.low,
+            data.volume,
+        ]);
+    }
+
+    let spread = &candle.spread_data;
+    prices[15..].copy_from_slice(&[
+        spread.open,
+        spread.close,
+        spread.high,
+        spread.low,
+        spread.sum,
+    ]);
+
+    prices.map(f64::to_bits)
+}
+
+/// Writes five prices of the group number xor-ed with their references
+fn write_xor_prices(bytes: &mut Vec<u8>, prices: &[u64], previous_prices: &[u64], group: usize) {
+    let prices = &prices[group * 5..group * 5 + 5];
+    let previous_prices = &previous_prices[group * 5..group * 5 + 5];
+
+    for (no, price) in prices.iter().enumerate() {
+        write_varint(
+            bytes,
+            price ^ get_xor_reference(prices, previous_prices, no),
+        );
+    }
+}
+
+fn read_xor_prices(
+    bytes: &[u8],
+    position: &mut usize,
+    prices: &mut [u64],
+    previous_prices: &[u64],
+    group: usize,
+) -> Result<(), CodecError> {
+    let prices = &mut prices[group * 5..group * 5 + 5];
+    let previous_prices = &previous_prices[group * 5..group * 5 + 5];
+
+    for no in 0..5 {
+        prices[no] = read_varint(bytes, position)? ^ get_xor_reference(prices, previous_prices, no);
+    }
+
+    Ok(())
+}
+
+/// Gets bits of the price closest to the price of the number in open, close, high, low,
+/// volume order, so the xor result has more zero bits. Only prices before the number are used
+fn get_xor_reference(prices: &[u64], previous_prices: &[u64], no: usize) -> u64 {
+    let open = f64::from_bits(prices[0]);
+    let close = f64::from_bits(prices[1]);
+
+    match no {
+        0 => previous_prices[1],
+        1 => prices[0],
+        2 => open.max(close).to_bits(),
+        3 => open.min(close).to_bits(),
+        _ => previous_prices[4],
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().expect("slice of 4 bytes"))
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().expect("slice of 8 bytes"))
+}
+
+fn read_i64(bytes: &[u8]) -> i64 {
+    i64::from_le_bytes(bytes[..8].try_into().expect("slice of 8 bytes"))
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> Result<u64, CodecError> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*position).ok_or(CodecError::UnexpectedEnd)?;
+        *position += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(CodecError::InvalidHeader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prices::candles_cache::BidAskCandlesCache;
+    use chrono::{Duration, TimeZone};
+
+    fn get_candles(interval: CandleInterval) -> Vec<BidAskCandle> {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![interval]);
+
+        for i in 0..2000 {
+            // gaps every 7th minute
+            if i % 7 == 3 {
+                continue;
+            }
+
+            let price = 1.1 + ((i * 7919) % 100) as f64 / 1e4;
+            let datetime = date + Duration::nanoseconds(i * 17_123_456_789);
+            cache.insert_or_update(datetime, "EURUSD", price, price + 0.0002, 0.1, 0.3);
+        }
+
+        let mut candles: Vec<_> = cache.get_all().values().cloned().collect();
+        candles.sort_by_key(|candle| candle.date);
+
+        candles
+    }
+
+    #[test]
+    pub fn round_trip_not_compressed() {
+        let candles = get_candles(CandleInterval::Minute);
+
+        let bytes = encode_bid_ask_series(
+            "EURUSD",
+            CandleInterval::Minute,
+            &candles,
+            SeriesCompression::None,
+        )
+        .unwrap();
+        let view = BidAskSeriesView::new(&bytes).unwrap();
+
+        // every record has one byte of the empty volume profile
+        assert_eq!(
+            bytes.len(),
+            HEADER_SIZE + 6 + candles.len() * (RECORD_SIZE + 1)
+        );
+        assert_eq!(view.get_instrument(), "EURUSD");
+        assert_eq!(view.len(), candles.len());
+        assert_eq!(view.get(10).unwrap().unwrap().to_candle(), candles[10]);
+        assert_eq!(decode_bid_ask_series(&bytes).unwrap(), candles);
+    }
+
+    #[test]
+    pub fn round_trip_xor_compressed() {
+        for interval in [CandleInterval::Minute, CandleInterval::Hour] {
+            let candles = get_candles(interval);
+
+            let bytes = encode_bid_ask_series("EURUSD", interval, &candles, SeriesCompression::Xor)
+                .unwrap();
+            let view = BidAskSeriesView::new(&bytes).unwrap();
+
+            assert!(view.get(0).is_none());
+            assert_eq!(decode_bid_ask_series(&bytes).unwrap(), candles);
+
+            if interval == CandleInterval::Minute {
+                assert!(bytes.len() < candles.len() * RECORD_SIZE * 2 / 3);
+            }
+        }
+    }
+
+    #[test]
+    pub fn round_trip_days_intervals() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for interval in [CandleInterval::ThreeDays, CandleInterval::SevenDays] {
+            let mut cache = BidAskCandlesCache::new(vec![interval]);
+
+            for i in 0..60 {
+                let price = 1.1 + i as f64 / 1e4;
+                let datetime = date + Duration::days(i);
+                cache.insert_or_update(datetime, "EURUSD", price, price + 0.0002, 0.1, 0.3);
+            }
+
+            let mut candles: Vec<_> = cache.get_all().values().cloned().collect();
+            candles.sort_by_key(|candle| candle.date);
+            let bytes =
+                encode_bid_ask_series("EURUSD", interval, &candles, SeriesCompression::None)
+                    .unwrap();
+
+            assert!(candles.len() > 1);
+            assert_eq!(decode_bid_ask_series(&bytes).unwrap(), candles);
+        }
+    }
+
+    #[test]
+    pub fn decode_unsupported_version() {
+        let candles = get_candles(CandleInterval::Minute);
+        let mut bytes = encode_bid_ask_series(
+            "EURUSD",
+            CandleInterval::Minute,
+            &candles,
+            SeriesCompression::None,
+        )
+        .unwrap();
+        bytes[4] = VERSION + 1;
+
+        assert_eq!(
+            BidAskSeriesView::new(&bytes).unwrap_err(),
+            CodecError::UnsupportedVersion(VERSION + 1)
+        );
+    }
+
+    #[test]
+    pub fn decode_overflowing_datetime_offset() {
+        let mut bytes = encode_bid_ask_series(
+            "EURUSD",
+            CandleInterval::Minute,
+            &[],
+            SeriesCompression::Xor,
+        )
+        .unwrap();
+        bytes[16..20].copy_from_slice(&1u32.to_le_bytes());
+        // index delta and prices of the bid side
+        bytes.extend_from_slice(&[0; 6]);
+        // zigzag offset of i64::MAX seconds
+        write_varint(&mut bytes, u64::MAX - 1);
+        write_varint(&mut bytes, 0);
+
+        let result = decode_bid_ask_series(&bytes);
+
+        assert_eq!(result, Err(CodecError::InvalidHeader));
+    }
+
+    #[test]
+    pub fn round_trip_volume_profiles() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        cache.set_volume_profile(CandleInterval::Minute, 0.0005);
+
+        for i in 0..600 {
+            let price = 1.1 + ((i * 7919) % 100) as f64 / 1e4;
+            let datetime = date + Duration::seconds(i);
+            cache.insert_or_update(datetime, "EURUSD", price, price + 0.0002, 0.1, 0.3);
+        }
+
+        let mut candles: Vec<_> = cache.get_all().values().cloned().collect();
+        candles.sort_by_key(|candle| candle.date);
+        candles[3].volume_profile = None;
+
+        for compression in [SeriesCompression::None, SeriesCompression::Xor] {
+            let bytes =
+                encode_bid_ask_series("EURUSD", CandleInterval::Minute, &candles, compression)
+                    .unwrap();
+
+            assert!(candles[0].volume_profile.is_some());
+            assert_eq!(decode_bid_ask_series(&bytes).unwrap(), candles);
+        }
+
+        let bytes = encode_bid_ask_series(
+            "EURUSD",
+            CandleInterval::Minute,
+            &candles,
+            SeriesCompression::None,
+        )
+        .unwrap();
+        let view = BidAskSeriesView::new(&bytes).unwrap();
+
+        assert_eq!(
+            view.get(5).unwrap().unwrap().volume_profile,
+            candles[5].volume_profile
+        );
+    }
+
+    #[test]
+    pub fn encode_foreign_and_invalid_candles() {
+        let mut candles = get_candles(CandleInterval::Minute);
+
+        let foreign_result = encode_bid_ask_series(
+            "GBPUSD",
+            CandleInterval::Minute,
+            &candles,
+            SeriesCompression::None,
+        );
+        candles[1].date += Duration::seconds(1);
+        let invalid_result = encode_bid_ask_series(
+            "EURUSD",
+            CandleInterval::Minute,
+            &candles,
+            SeriesCompression::None,
+        );
+
+        assert!(matches!(foreign_result, Err(CodecError::ForeignCandle(_))));
+        assert!(matches!(invalid_result, Err(CodecError::InvalidDate(_))));
+    }
+
+    #[test]
+    pub fn decode_truncated_series() {
+        let candles = get_candles(CandleInterval::Minute);
+        let bytes = encode_bid_ask_series(
+            "EURUSD",
+            CandleInterval::Minute,
+            &candles,
+            SeriesCompression::Xor,
+        )
+        .unwrap();
+
+        let result = decode_bid_ask_series(&bytes[..bytes.len() - 3]);
+
+        assert_eq!(result, Err(CodecError::UnexpectedEnd));
+        assert_eq!(
+            BidAskSeriesView::new(&bytes[..10]).unwrap_err(),
+            CodecError::UnexpectedEnd
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::prices::candle::{BidAskCandle, BidAskCandleData, SpreadCandleData};
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::columnar::{
+    get_date_column, get_f64_column, get_i32_column, get_str_column, get_timestamp_field,
+    get_u64_column, read_parquet, to_timestamp_array, write_parquet, ColumnarError,
+};
+use crate::shared::symbols::Symbol;
+use ::arrow::array::{ArrayRef, Float64Array, Int32Array, StringArray, UInt64Array};
+use ::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use ::arrow::record_batch::RecordBatch;
+use parquet::file::reader::ChunkReader;
+use std::io::Write;
+use std::sync::Arc;
+
+const SIDES: [&str; 3] = ["bid", "ask", "mid"];
+const PRICES: [&str; 5] = ["open", "close", "high", "low", "volume"];
+const SPREADS: [&str; 5] = ["open", "close", "high", "low", "sum"];
+
+/// Schema of price candles batches: `interval`, `date`, `instrument`,
+/// `open`, `close`, `high`, `low`, `volume`, `datetime` columns of `bid`, `ask` and `mid`
+/// sides and `open`, `close`, `high`, `low`, `sum`, `count` columns of `spread`.
+/// Mid and spread columns are optional on read
+pub fn get_bid_ask_schema() -> SchemaRef {
+    let mut fields = vec![
+        Field::new("interval", DataType::Int32, false),
+        get_timestamp_field("date"),
+        Field::new("instrument", DataType::Utf8, false),
+    ];
+
+    for side in SIDES {
+        for price in PRICES {
+            fields.push(Field::new(
+                format!("{side}_{price}"),
+                DataType::Float64,
+                false,
+            ));
+        }
+
+        fields.push(get_timestamp_field(&format!("{side}_datetime")));
+    }
+
+    for spread in SPREADS {
+        fields.push(Field::new(
+            format!("spread_{spread}"),
+            DataType::Float64,
+            false,
+        ));
+    }
+
+    fields.push(Field::new("spread_count", DataType::UInt64, false));
+
+    Arc::new(Schema::new(fields))
+}
+
+pub fn bid_ask_to_record_batch(candles: &[BidAskCandle]) -> Result<RecordBatch, ColumnarError> {
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(Int32Array::from_iter_values(
+            candles.iter().map(|candle| candle.index as i32),
+        )),
+        Arc::new(to_timestamp_array(
+            "date",
+            candles.iter().map(|candle| candle.date),
+        )?),
+        Arc::new(StringArray::from_iter_values(
+            candles.iter().map(|candle| candle.instrument.as_str()),
+        )),
+    ];
+
+    for side in SIDES {
+        let data: Vec<&BidAskCandleData> = candles
+            .iter()
+            .map(|candle| match side {
+                "bid" => &candle.bid_data,
+                "ask" => &candle.ask_data,
+                _ => &candle.mid_data,
+            })
+            .collect();
+        columns.extend([
+            Arc::new(Float64Array::from_iter_values(data.iter().map(|d| d.open))) as ArrayRef,
+            Arc::new(Float64Array::from_iter_values(data.iter().map(|d| d.close))),
+            Arc::new(Float64Array::from_iter_values(data.iter().map(|d| d.high))),
+            Arc::new(Float64Array::from_iter_values(data.iter().map(|d| d.low))),
+            Arc::new(Float64Array::from_iter_values(
+                data.iter().map(|d| d.volume),
+            )),
+            Arc::new(to_timestamp_array(
+                &format!("{side}_datetime"),
+                data.iter().map(|d| d.datetime),
+            )?),
+        ]);
+    }
+
+    let spreads: Vec<&SpreadCandleData> =
+        candles.iter().map(|candle| &candle.spread_data).collect();
+    columns.extend([
+        Arc::new(Float64Array::from_iter_values(
+            spreads.iter().map(|d| d.open),
+        )) as ArrayRef,
+        Arc::new(Float64Array::from_iter_values(
+            spreads.iter().map(|d| d.close),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            spreads.iter().map(|d| d.high),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            spreads.iter().map(|d| d.low),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            spreads.iter().map(|d| d.sum),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            spreads.iter().map(|d| d.count),
+        )),
+    ]);
+
+    Ok(RecordBatch::try_new(get_bid_ask_schema(), columns)?)
+}
+
+pub fn bid_ask_from_record_batch(batch: &RecordBatch) -> Result<Vec<BidAskCandle>, ColumnarError> {
+    let intervals = get_i32_column(batch, "interval")?;
+    let dates = get_date_column(batch, "date")?;
+    let instruments = get_str_column(batch, "instrument")?;
+    let bid_data = read_data(batch, "bid")?;
+    let ask_data = read_data(batch, "ask")?;
+    let (mid_data, spread_data) = match batch.column_by_name("mid_open") {
+        Some(_) => (read_data(batch, "mid")?, read_spread_data(batch)?),
+        None => (
+            vec![BidAskCandleData::default(); batch.num_rows()],
+            vec![SpreadCandleData::default(); batch.num_rows()],
+        ),
+    };
+
+    intervals
+        .values()
+        .iter()
+        .zip(dates)
+        .zip(instruments.iter())
+        .zip(bid_data.into_iter().zip(ask_data))
+        .zip(mid_data.into_iter().zip(spread_data))
+        .map(
+            |((((interval, date), instrument), (bid_data, ask_data)), (mid_data, spread_data))| {
+                Ok(BidAskCandle {
+                    index: CandleInterval::try_from(*interval).map_err(|_| {
+                        ColumnarError::Schema(format!("interval {interval} is unknown"))
+                    })?,
+                    date,
+                    instrument: Symbol::new(instrument.unwrap_or_default()),
+                    bid_data,
+                    ask_data,
+                    mid_data,
+                    spread_data,
+                    volume_profile: None,
+                })
+            },
+        )
+        .collect()
+}
+
+fn read_data(batch: &RecordBatch, side: &str) -> Result<Vec<BidAskCandleData>, ColumnarError> {
+    let open = get_f64_column(batch, &format!("{side}_open"))?;
+    let close = get_f64_column(batch, &format!("{side}_close"))?;
+    let high = get_f64_column(batch, &format!("{side}_high"))?;
+    let low = get_f64_column(batch, &format!("{side}_low"))?;
+    let volume = get_f64_column(batch, &format!("{side}_volume"))?;
+    let datetimes = get_date_column(batch, &format!("{side}_datetime"))?;
+
+    Ok(datetimes
+        .into_iter()
+        .enumerate()
+        .map(|(i, datetime)| BidAskCandleData {
+            open: open.value(i),
+            close: close.value(i),
+            high: high.value(i),
+            low: low.value(i),
+            datetime,
+            volume: volume.value(i),
+        })
+        .collect())
+}
+
+fn read_spread_data(batch: &RecordBatch) -> Result<Vec<SpreadCandleData>, ColumnarError> {
+    let open = get_f64_column(batch, "spread_open")?;
+    let close = get_f64_column(batch, "spread_close")?;
+    let high = get_f64_column(batch, "spread_high")?;
+    let low = get_f64_column(batch, "spread_low")?;
+    let sum = get_f64_column(batch, "spread_sum")?;
+    let count = get_u64_column(batch, "spread_count")?;
+
+    Ok((0..batch.num_rows())
+        .map(|i| SpreadCandleData {
+            open: open.value(i),
+            close: close.value(i),
+            high: high.value(i),
+            low: low.value(i),
+            sum: sum.value(i),
+            count: count.value(i),
+        })
+        .collect())
+}
+
+pub fn write_bid_ask_parquet(
+    writer: impl Write + Send,
+    candles: &[BidAskCandle],
+) -> Result<(), ColumnarError> {
+    write_parquet(writer, &bid_ask_to_record_batch(candles)?)
+}
+
+pub fn read_bid_ask_parquet(
+    reader: impl ChunkReader + 'static,
+) -> Result<Vec<BidAskCandle>, ColumnarError> {
+    let mut candles = Vec::new();
+
+    for batch in read_parquet(reader)? {
+        candles.extend(bid_ask_from_record_batch(&batch)?);
+    }
+
+    Ok(candles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prices::candles_cache::BidAskCandlesCache;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use std::fs::File;
+
+    #[test]
+    pub fn parquet_round_trip() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+
+        for i in 0..500 {
+            let price = 1.0 + (i % 17) as f64 / 100.0;
+            let datetime = date + Duration::milliseconds(i * 1733);
+            cache.insert_or_update(datetime, "EURUSD", price, price + 0.0002, 1.0, 2.0);
+            cache.insert_or_update(datetime, "GBPUSD", price + 0.3, price + 0.3002, 1.0, 2.0);
+        }
+
+        let mut candles: Vec<_> = cache.get_all().values().cloned().collect();
+        candles.sort_by_key(|candle| candle.get_id());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("candles.parquet");
+
+        write_bid_ask_parquet(File::create(&path).unwrap(), &candles).unwrap();
+        let restored = read_bid_ask_parquet(File::open(&path).unwrap()).unwrap();
+
+        assert_eq!(restored, candles);
+    }
+
+    #[test]
+    pub fn from_record_batch_with_wrong_schema() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "interval",
+            DataType::Int32,
+            false,
+        )]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![0]))]).unwrap();
+
+        let result = bid_ask_from_record_batch(&batch);
+
+        assert!(matches!(result, Err(ColumnarError::Schema(_))));
+    }
+}
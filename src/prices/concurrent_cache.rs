@@ -0,0 +1,249 @@
+use crate::prices::candle::BidAskCandle;
+use crate::prices::candles_cache::BidAskCandlesCache;
+use crate::shared::budget::CacheBudget;
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::sharded::Sharded;
+use chrono::{DateTime, Utc};
+
+/// Candles cache sharded by instrument which allows parallel updates from multiple threads
+pub struct ConcurrentBidAskCandlesCache {
+    shards: Sharded<BidAskCandlesCache>,
+}
+
+impl ConcurrentBidAskCandlesCache {
+    pub fn new(candle_intervals: Vec<CandleInterval>, shards_count: usize) -> Self {
+        Self {
+            shards: Sharded::new(shards_count, || {
+                BidAskCandlesCache::new(candle_intervals.clone())
+            }),
+        }
+    }
+
+    /// Applies configuration (retention, budget, listeners etc.) to every shard. A budget set
+    /// here limits every shard, use `set_budget` to limit the whole cache
+    pub fn configure(&self, configure: impl FnMut(&mut BidAskCandlesCache)) {
+        self.shards.for_each_mut(configure);
+    }
+
+    /// Sets the budget of the whole cache divided evenly between shards
+    pub fn set_budget(&self, budget: Option<CacheBudget>) {
+        let shard_budget = budget.map(|budget| budget.divide(self.shards.len()));
+        self.shards
+            .for_each_mut(|shard| shard.set_budget(shard_budget));
+    }
+
+    pub fn insert_or_update(
+        &self,
+        datetime: DateTime<Utc>,
+        instrument: &str,
+        bid: f64,
+        ask: f64,
+        bid_vol: f64,
+        ask_vol: f64,
+    ) {
+        self.shards
+            .write(instrument)
+            .insert_or_update(datetime, instrument, bid, ask, bid_vol, ask_vol);
+    }
+
+    pub fn get(
+        &self,
+        instrument: &str,
+        interval: CandleInterval,
+        datetime: DateTime<Utc>,
+    ) -> Option<BidAskCandle> {
+        let id = BidAskCandle::generate_id(instrument, &interval, datetime);
+
+        self.shards.read(instrument).get(&id).cloned()
+    }
+
+    /// Reads the shard which contains candles of the instrument
+    pub fn read<R>(&self, instrument: &str, read: impl FnOnce(&BidAskCandlesCache) -> R) -> R {
+        read(&self.shards.read(instrument))
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.read_all().iter().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.read_all().iter().all(|shard| shard.is_empty())
+    }
+
+    /// Gets copies of all candles. All shards are locked while copying
+    pub fn snapshot(&self) -> Vec<BidAskCandle> {
+        let shards = self.shards.read_all();
+        let mut candles = Vec::with_capacity(shards.iter().map(|shard| shard.len()).sum());
+
+        for shard in shards.iter() {
+            candles.extend(shard.get_all().values().cloned());
+        }
+
+        candles
+    }
+
+    /// Gets copies of candles with date bigger or equals specified date.
+    /// All shards are locked while copying
+    pub fn get_after(&self, datetime: DateTime<Utc>) -> Vec<BidAskCandle> {
+        self.shards
+            .read_all()
+            .iter()
+            .filter_map(|shard| shard.get_after(datetime))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Removes candles with date less or equals specified date
+    pub fn remove_before(
+        &self,
+        datetime: DateTime<Utc>,
+        candle_type: Option<CandleInterval>,
+    ) -> i32 {
+        let mut removed_count = 0;
+        self.shards.for_each_mut(|shard| {
+            removed_count += shard.remove_before(datetime, candle_type);
+        });
+
+        removed_count
+    }
+
+    /// Finalizes expired candles of all shards. Returns ids of finalized candles
+    pub fn close_expired(&self, now: DateTime<Utc>, carry_forward: bool) -> Vec<String> {
+        let mut closed_ids = Vec::new();
+        self.shards.for_each_mut(|shard| {
+            closed_ids.extend(shard.close_expired(now, carry_forward));
+        });
+        closed_ids.sort();
+
+        closed_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::budget::EvictionStrategy;
+    use chrono::{Duration, TimeZone};
+
+    fn get_ticks(instrument_no: usize, count: i64) -> Vec<(DateTime<Utc>, String, f64, f64)> {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        (0..count)
+            .map(|i| {
+                let price = 1.0 + ((i * 7919 + instrument_no as i64 * 104729) % 1000) as f64 / 1e4;
+                (
+                    date + Duration::seconds(i * 7),
+                    format!("INSTRUMENT{instrument_no}"),
+                    price,
+                    price + 0.0002,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn parallel_insert_or_update_matches_sequential() {
+        let intervals = vec![
+            CandleInterval::Minute,
+            CandleInterval::FiveMinutes,
+            CandleInterval::Hour,
+            CandleInterval::Day,
+        ];
+        let ticks_by_instruments: Vec<_> = (0..32).map(|no| get_ticks(no, 2000)).collect();
+        let concurrent_cache = ConcurrentBidAskCandlesCache::new(intervals.clone(), 4);
+        let mut sequential_cache = BidAskCandlesCache::new(intervals);
+
+        std::thread::scope(|scope| {
+            for thread_no in 0..8 {
+                let concurrent_cache = &concurrent_cache;
+                let ticks_by_instruments = &ticks_by_instruments;
+
+                scope.spawn(move || {
+                    // each instrument is fed by a single thread to keep its ticks order
+                    for ticks in ticks_by_instruments.iter().skip(thread_no).step_by(8) {
+                        for (date, instrument, bid, ask) in ticks.iter() {
+                            concurrent_cache
+                                .insert_or_update(*date, instrument, *bid, *ask, 1.0, 1.0);
+                        }
+                    }
+                });
+            }
+
+            // candles are never removed, so snapshots taken while updating can only grow
+            let mut last_len = 0;
+
+            for _ in 0..10 {
+                let len = concurrent_cache.snapshot().len();
+                assert!(len >= last_len);
+                last_len = len;
+            }
+        });
+
+        for ticks in ticks_by_instruments.iter() {
+            for (date, instrument, bid, ask) in ticks.iter() {
+                sequential_cache.insert_or_update(*date, instrument, *bid, *ask, 1.0, 1.0);
+            }
+        }
+
+        let snapshot = concurrent_cache.snapshot();
+
+        assert_eq!(snapshot.len(), sequential_cache.len());
+        assert_eq!(concurrent_cache.len(), sequential_cache.len());
+
+        for candle in snapshot.iter() {
+            let expected = sequential_cache.get(&candle.get_id()).unwrap();
+
+            for (data, expected_data) in [
+                (&candle.bid_data, &expected.bid_data),
+                (&candle.ask_data, &expected.ask_data),
+            ] {
+                assert_eq!(data.open, expected_data.open);
+                assert_eq!(data.close, expected_data.close);
+                assert_eq!(data.high, expected_data.high);
+                assert_eq!(data.low, expected_data.low);
+                assert_eq!(data.volume, expected_data.volume);
+                assert_eq!(data.datetime, expected_data.datetime);
+            }
+        }
+    }
+
+    #[test]
+    pub fn budget_is_divided_between_shards() {
+        let cache = ConcurrentBidAskCandlesCache::new(vec![CandleInterval::Minute], 4);
+        cache.set_budget(Some(CacheBudget {
+            max_entries: Some(40),
+            max_memory_bytes: None,
+            strategy: EvictionStrategy::OldestFirst,
+        }));
+
+        for no in 0..8 {
+            for (date, instrument, bid, ask) in get_ticks(no, 500) {
+                cache.insert_or_update(date, &instrument, bid, ask, 1.0, 1.0);
+            }
+        }
+
+        assert!(cache.len() <= 40);
+        assert_eq!(
+            cache.read("INSTRUMENT0", |shard| shard
+                .get_budget()
+                .unwrap()
+                .max_entries),
+            Some(10)
+        );
+    }
+
+    #[test]
+    pub fn shards_are_usable_after_panic() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let cache = ConcurrentBidAskCandlesCache::new(vec![CandleInterval::Minute], 1);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cache.configure(|_shard| panic!("configuration failed"));
+        }));
+        cache.insert_or_update(date, "EURUSD", 1.0, 1.1, 1.0, 1.0);
+
+        assert!(result.is_err());
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::prices::candle::{BidAskCandle, BidAskCandleData, SpreadCandleData};
+use crate::prices::candles_cache::BidAskCandlesCache;
+use crate::shared::csv::{
+    create_writer, read_rows, CsvError, CsvImport, CsvReadOptions, CsvRow, CsvWriteOptions,
+};
+use crate::shared::symbols::Symbol;
+use std::io::{Read, Write};
+
+/// Columns of the written csv. Mid and spread columns are optional on read, mid volume
+/// is restored as the average of bid and ask volumes
+pub const BID_ASK_CSV_COLUMNS: [&str; 24] = [
+    "date",
+    "interval",
+    "instrument",
+    "bid_open",
+    "bid_high",
+    "bid_low",
+    "bid_close",
+    "bid_volume",
+    "ask_open",
+    "ask_high",
+    "ask_low",
+    "ask_close",
+    "ask_volume",
+    "mid_open",
+    "mid_high",
+    "mid_low",
+    "mid_close",
+    "spread_open",
+    "spread_high",
+    "spread_low",
+    "spread_close",
+    "spread_average",
+    "ticks",
+    "updated",
+];
+
+const REQUIRED_COLUMNS: [&str; 9] = [
+    "date",
+    "bid_open",
+    "bid_high",
+    "bid_low",
+    "bid_close",
+    "ask_open",
+    "ask_high",
+    "ask_low",
+    "ask_close",
+];
+
+pub fn write_bid_ask_csv<'a>(
+    writer: impl Write,
+    candles: impl IntoIterator<Item = &'a BidAskCandle>,
+    options: &CsvWriteOptions,
+) -> Result<(), CsvError> {
+    let mut writer = create_writer(writer, options);
+
+    if options.has_headers {
+        writer.write_record(BID_ASK_CSV_COLUMNS)?;
+    }
+
+    for candle in candles {
+        let bid = &candle.bid_data;
+        let ask = &candle.ask_data;
+        let mid = &candle.mid_data;
+        let spread = &candle.spread_data;
+        let updated = bid.datetime.max(ask.datetime);
+        writer.write_record([
+            options.timestamp_format.format(candle.date),
+            (candle.index as i32).to_string(),
+            candle.instrument.to_string(),
+            bid.open.to_string(),
+            bid.high.to_string(),
+            bid.low.to_string(),
+            bid.close.to_string(),
+            bid.volume.to_string(),
+            ask.open.to_string(),
+            ask.high.to_string(),
+            ask.low.to_string(),
+            ask.close.to_string(),
+            ask.volume.to_string(),
+            mid.open.to_string(),
+            mid.high.to_string(),
+            mid.low.to_string(),
+            mid.close.to_string(),
+            spread.open.to_string(),
+            spread.high.to_string(),
+            spread.low.to_string(),
+            spread.close.to_string(),
+            spread.get_average().to_string(),
+            spread.count.to_string(),
+            options.timestamp_format.format(updated),
+        ])?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads candles and passes them to the action. Rows with invalid values are reported
+/// as row errors
+pub fn read_bid_ask_csv(
+    reader: impl Read,
+    options: &CsvReadOptions,
+    mut action: impl FnMut(BidAskCandle),
+) -> Result<CsvImport, CsvError> {
+    read_rows(
+        reader,
+        options,
+        &BID_ASK_CSV_COLUMNS,
+        &REQUIRED_COLUMNS,
+        |row| {
+            action(read_candle(row, options)?);
+
+            Ok(())
+        },
+    )
+}
+
+/// Reads candles into the cache. Candles of the same id are replaced
+pub fn import_bid_ask_csv(
+    reader: impl Read,
+    options: &CsvReadOptions,
+    cache: &mut BidAskCandlesCache,
+) -> Result<CsvImport, CsvError> {
+    read_bid_ask_csv(reader, options, |candle| cache.insert(candle))
+}
+
+fn read_candle(row: &CsvRow, options: &CsvReadOptions) -> Result<BidAskCandle, String> {
+    let interval = match (row.get("interval"), options.interval) {
+        (Some(_), _) => row.get_interval("interval")?,
+        (None, Some(interval)) => interval,
+        (None, None) => return Err("interval is empty".to_string()),
+    };
+    let instrument = match (row.get("instrument"), options.owner.as_ref()) {
+        (Some(instrument), _) => Symbol::new(instrument),
+        (None, Some(instrument)) => Symbol::new(instrument),
+        (None, None) => return Err("instrument is empty".to_string()),
+    };
+    let date = interval.get_start_date(row.get_date("date")?);
+    let updated = match row.get("updated") {
+        Some(_) => row.get_date("updated")?,
+        None => date,
+    };
+
+    let bid_data = read_data(row, "bid", updated)?;
+    let ask_data = read_data(row, "ask", updated)?;
+    let mid_data = match row.get("mid_open") {
+        Some(_) => BidAskCandleData {
+            volume: (bid_data.volume + ask_data.volume) / 2.0,
+            ..read_data(row, "mid", updated)?
+        },
+        None => BidAskCandleData::default(),
+    };
+
+    Ok(BidAskCandle {
+        index: interval,
+        date,
+        instrument,
+        bid_data,
+        ask_data,
+        mid_data,
+        spread_data: read_spread(row)?,
+        volume_profile: None,
+    })
+}
+
+fn read_spread(row: &CsvRow) -> Result<SpreadCandleData, String> {
+    let count: u64 = match row.get("ticks") {
+        Some(count) => count
+            .parse()
+            .map_err(|_| "ticks is not a number".to_string())?,
+        None => return Ok(SpreadCandleData::default()),
+    };
+
+    Ok(SpreadCandleData {
+        open: row.get_f64("spread_open")?,
+        close: row.get_f64("spread_close")?,
+        high: row.get_f64("spread_high")?,
+        low: row.get_f64("spread_low")?,
+        sum: row.get_f64("spread_average")? * count as f64,
+        count,
+    })
+}
+
+fn read_data(
+    row: &CsvRow,
+    side: &str,
+    datetime: chrono::DateTime<chrono::Utc>,
+) -> Result<BidAskCandleData, String> {
+    Ok(BidAskCandleData {
+        open: row.get_f64(&format!("{side}_open"))?,
+        close: row.get_f64(&format!("{side}_close"))?,
+        high: row.get_f64(&format!("{side}_high"))?,
+        low: row.get_f64(&format!("{side}_low"))?,
+        datetime,
+        volume: row.get_f64_or(&format!("{side}_volume"), 0.0)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::candle_interval::CandleInterval;
+    use crate::shared::csv::{CsvColumn, CsvRowError, TimestampFormat};
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    #[test]
+    pub fn write_and_read_round_trip() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+
+        for i in 0..3 {
+            let datetime = date + Duration::seconds(i * 40);
+            cache.insert_or_update(datetime, "EURUSD", 1.1 + i as f64, 1.2, 1.0, 2.0);
+        }
+
+        let mut candles: Vec<_> = cache.get_all().values().cloned().collect();
+        candles.sort_by_key(|candle| candle.date);
+        let options = CsvWriteOptions {
+            timestamp_format: TimestampFormat::UnixMillis,
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        write_bid_ask_csv(&mut bytes, &candles, &options).unwrap();
+        let mut restored_cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        let read_options = CsvReadOptions {
+            timestamp_format: TimestampFormat::UnixMillis,
+            ..Default::default()
+        };
+
+        let import =
+            import_bid_ask_csv(bytes.as_slice(), &read_options, &mut restored_cache).unwrap();
+
+        assert_eq!(import.imported_count, 2);
+        assert!(import.errors.is_empty());
+
+        for candle in candles {
+            assert_eq!(restored_cache.get(&candle.get_id()), Some(&candle));
+        }
+    }
+
+    #[test]
+    pub fn write_matches_fixture() {
+        let candle: BidAskCandle =
+            serde_json::from_str(include_str!("../../tests/fixtures/bid_ask_candle_v2.json"))
+                .unwrap();
+        let mut bytes = Vec::new();
+
+        write_bid_ask_csv(&mut bytes, [&candle], &CsvWriteOptions::default()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            include_str!("../../tests/fixtures/bid_ask_candles_v2.csv")
+        );
+    }
+
+    #[test]
+    pub fn read_fixture_without_spread() {
+        let expected: BidAskCandle =
+            serde_json::from_str(include_str!("../../tests/fixtures/bid_ask_candle.json")).unwrap();
+        let mut candles = Vec::new();
+
+        let import = read_bid_ask_csv(
+            include_str!("../../tests/fixtures/bid_ask_candles.csv").as_bytes(),
+            &CsvReadOptions::default(),
+            |candle| candles.push(candle),
+        )
+        .unwrap();
+
+        assert!(import.errors.is_empty());
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0].bid_data, expected.bid_data);
+        assert_eq!(candles[0].ask_data, expected.ask_data);
+        assert_eq!(candles[0].mid_data.close, 1.15);
+        assert_eq!(candles[0].spread_data, SpreadCandleData::default());
+    }
+
+    #[test]
+    pub fn read_vendor_fixture_with_errors() {
+        let options = CsvReadOptions {
+            timestamp_format: TimestampFormat::Custom("%Y-%m-%d %H:%M".to_string()),
+            delimiter: b';',
+            columns: [
+                ("date", "Time"),
+                ("bid_open", "BidOpen"),
+                ("bid_high", "BidHigh"),
+                ("bid_low", "BidLow"),
+                ("bid_close", "BidClose"),
+                ("ask_open", "AskOpen"),
+                ("ask_high", "AskHigh"),
+                ("ask_low", "AskLow"),
+                ("ask_close", "AskClose"),
+            ]
+            .into_iter()
+            .map(|(field, column)| (field.to_string(), CsvColumn::Name(column.to_string())))
+            .collect(),
+            interval: Some(CandleInterval::Hour),
+            owner: Some("EURUSD".to_string()),
+            ..Default::default()
+        };
+        let mut candles = Vec::new();
+
+        let import = read_bid_ask_csv(
+            include_str!("../../tests/fixtures/vendor_prices.csv").as_bytes(),
+            &options,
+            |candle| candles.push(candle),
+        )
+        .unwrap();
+
+        assert_eq!(import.imported_count, 2);
+        assert_eq!(
+            import.errors,
+            vec![
+                CsvRowError {
+                    line: 3,
+                    message: "bid_close is not a number".to_string()
+                },
+                CsvRowError {
+                    line: 4,
+                    message: "date: invalid timestamp '2000-01-01'".to_string()
+                }
+            ]
+        );
+        assert_eq!(candles[0].index, CandleInterval::Hour);
+        assert_eq!(candles[0].instrument, "EURUSD");
+        assert_eq!(candles[0].bid_data.close, 1.0015);
+        assert_eq!(candles[0].ask_data.volume, 0.0);
+        assert_eq!(
+            candles[1].date,
+            Utc.with_ymd_and_hms(2000, 1, 1, 3, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn read_without_header() {
+        let csv = "0,0,EURUSD,1,2,0.5,1.5,1,1.1,2.1,0.6,1.6,1\n";
+        let options = CsvReadOptions {
+            timestamp_format: TimestampFormat::UnixSeconds,
+            ..Default::default()
+        };
+        let mut candles = Vec::new();
+
+        let import =
+            read_bid_ask_csv(csv.as_bytes(), &options, |candle| candles.push(candle)).unwrap();
+
+        assert_eq!(import.imported_count, 1);
+        assert_eq!(candles[0].bid_data.close, 1.5);
+        assert_eq!(candles[0].ask_data.high, 2.1);
+        assert_eq!(candles[0].date.timestamp(), 0);
+    }
+
+    #[test]
+    pub fn read_missing_required_column() {
+        let csv = "date,bid_open\n0,1\n";
+
+        let result = read_bid_ask_csv(csv.as_bytes(), &CsvReadOptions::default(), |_| {});
+
+        assert!(matches!(result, Err(CsvError::MissingColumn(field)) if field == "bid_high"));
+    }
+}
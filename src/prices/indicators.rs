@@ -0,0 +1,235 @@
+use crate::prices::candle::{BidAskCandle, PriceSide};
+use crate::prices::candles_cache::BidAskCandlesCache;
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::events::{CandleEvent, CandleEventKind, CandleListener};
+use crate::shared::indicators::{Indicator, IndicatorSeries, IndicatorValue};
+use crate::shared::symbols::{Symbol, SymbolRegistry};
+use ahash::AHashMap;
+use chrono::{DateTime, Utc};
+
+/// Incremental indicators of price candles by instruments and intervals. Indicators are updated
+/// by candle events of `BidAskCandlesCache`, e.g. received from a channel listener
+pub struct BidAskIndicators {
+    side: PriceSide,
+    series: AHashMap<(Symbol, CandleInterval), Vec<IndicatorSeries>>,
+}
+
+impl BidAskIndicators {
+    /// Creates indicators of the price side of candles
+    pub fn new(side: PriceSide) -> Self {
+        Self {
+            side,
+            series: AHashMap::new(),
+        }
+    }
+
+    pub fn get_side(&self) -> PriceSide {
+        self.side
+    }
+
+    /// Adds the indicator of the instrument candles of the interval.
+    /// Returns false if the indicator is already added or not valid
+    pub fn add_indicator(
+        &mut self,
+        instrument: &str,
+        interval: CandleInterval,
+        indicator: Indicator,
+    ) -> bool {
+        let Some(new_series) = IndicatorSeries::new(indicator) else {
+            return false;
+        };
+        let series = self
+            .series
+            .entry((Symbol::new(instrument), interval))
+            .or_default();
+
+        if series.iter().any(|item| *item.get_indicator() == indicator) {
+            return false;
+        }
+
+        series.push(new_series);
+
+        true
+    }
+
+    pub fn remove_indicator(
+        &mut self,
+        instrument: &str,
+        interval: CandleInterval,
+        indicator: &Indicator,
+    ) -> bool {
+        let Some(symbol) = SymbolRegistry::get(instrument) else {
+            return false;
+        };
+        let key = (symbol, interval);
+        let Some(series) = self.series.get_mut(&key) else {
+            return false;
+        };
+        let len = series.len();
+        series.retain(|item| item.get_indicator() != indicator);
+        let removed = series.len() < len;
+
+        if series.is_empty() {
+            self.series.remove(&key);
+        }
+
+        removed
+    }
+
+    /// Gets indicators of the instrument candles of the interval in the adding order
+    pub fn get_indicators(&self, instrument: &str, interval: CandleInterval) -> Vec<Indicator> {
+        SymbolRegistry::get(instrument)
+            .and_then(|symbol| self.series.get(&(symbol, interval)))
+            .map(|series| series.iter().map(|item| *item.get_indicator()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Updates indicators of the candle. Opened and updated candles change only the forming
+    /// values, closed candles are added to the indicators state
+    pub fn update(&mut self, kind: CandleEventKind, candle: &BidAskCandle) {
+        let Some(series) = self.series.get_mut(&(candle.instrument, candle.index)) else {
+            return;
+        };
+        let ohlc = candle.get_ohlc(self.side);
+
+        for item in series.iter_mut() {
+            match kind {
+                CandleEventKind::Opened | CandleEventKind::Updated => {
+                    item.update(candle.date, ohlc);
+                }
+                CandleEventKind::Closed => {
+                    item.close(candle.date, ohlc);
+                }
+            }
+        }
+    }
+
+    /// Updates indicators of the instrument candles of the interval by cached candles
+    /// in the date range. Finalized candles and candles followed by a newer one in the range
+    /// are closed. Candles before already closed candles are skipped
+    pub fn backfill(
+        &mut self,
+        cache: &BidAskCandlesCache,
+        instrument: &str,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) {
+        let candles = cache.get_range(instrument, interval, start_date, end_date);
+
+        for (i, candle) in candles.iter().enumerate() {
+            let kind = if i + 1 < candles.len() || cache.is_closed(&candle.get_id()) {
+                CandleEventKind::Closed
+            } else {
+                CandleEventKind::Updated
+            };
+            self.update(kind, candle);
+        }
+    }
+
+    pub fn get_series(
+        &self,
+        instrument: &str,
+        interval: CandleInterval,
+        indicator: &Indicator,
+    ) -> Option<&IndicatorSeries> {
+        self.series
+            .get(&(SymbolRegistry::get(instrument)?, interval))?
+            .iter()
+            .find(|item| item.get_indicator() == indicator)
+    }
+
+    /// Gets the indicator value of the forming candle
+    pub fn get_forming(
+        &self,
+        instrument: &str,
+        interval: CandleInterval,
+        indicator: &Indicator,
+    ) -> Option<IndicatorValue> {
+        self.get_series(instrument, interval, indicator)?
+            .get_forming()?
+            .1
+    }
+
+    /// Gets dates and indicator values of closed candles
+    pub fn get_closed(
+        &self,
+        instrument: &str,
+        interval: CandleInterval,
+        indicator: &Indicator,
+    ) -> &[(DateTime<Utc>, IndicatorValue)] {
+        self.get_series(instrument, interval, indicator)
+            .map(|series| series.get_closed())
+            .unwrap_or_default()
+    }
+}
+
+impl CandleListener<BidAskCandle> for BidAskIndicators {
+    fn on_event(&mut self, event: &CandleEvent<BidAskCandle>) {
+        self.update(event.kind, &event.candle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn update_by_cache_events() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        cache.add_listener(Box::new(sender));
+        let mut indicators = BidAskIndicators::new(PriceSide::Mid);
+        let sma = Indicator::Sma(2);
+        assert!(indicators.add_indicator("EURUSD", CandleInterval::Minute, sma));
+        assert!(!indicators.add_indicator("EURUSD", CandleInterval::Minute, sma));
+        assert!(!indicators.add_indicator("EURUSD", CandleInterval::Minute, Indicator::Ema(0)));
+
+        for (minute, mid) in [(0, 1.0), (1, 2.0), (1, 3.0), (2, 5.0)] {
+            let datetime = date + Duration::minutes(minute);
+            cache.insert_or_update(datetime, "EURUSD", mid - 0.5, mid + 0.5, 1.0, 1.0);
+        }
+
+        for event in receiver.try_iter() {
+            indicators.on_event(&event);
+        }
+
+        assert_eq!(
+            indicators.get_closed("EURUSD", CandleInterval::Minute, &sma),
+            &[(date + Duration::minutes(1), IndicatorValue::Single(2.0))]
+        );
+        assert_eq!(
+            indicators.get_forming("EURUSD", CandleInterval::Minute, &sma),
+            Some(IndicatorValue::Single(4.0))
+        );
+
+        let mut backfilled = BidAskIndicators::new(PriceSide::Mid);
+        backfilled.add_indicator("EURUSD", CandleInterval::Minute, sma);
+        backfilled.backfill(
+            &cache,
+            "EURUSD",
+            CandleInterval::Minute,
+            date,
+            date + Duration::minutes(2),
+        );
+
+        assert_eq!(
+            backfilled.get_closed("EURUSD", CandleInterval::Minute, &sma),
+            indicators.get_closed("EURUSD", CandleInterval::Minute, &sma)
+        );
+        assert_eq!(
+            backfilled.get_forming("EURUSD", CandleInterval::Minute, &sma),
+            Some(IndicatorValue::Single(4.0))
+        );
+        assert!(indicators.remove_indicator("EURUSD", CandleInterval::Minute, &sma));
+        assert!(indicators
+            .get_indicators("EURUSD", CandleInterval::Minute)
+            .is_empty());
+        assert!(indicators
+            .get_closed("indicators-unknown", CandleInterval::Minute, &sma)
+            .is_empty());
+        assert!(SymbolRegistry::get("indicators-unknown").is_none());
+    }
+}
@@ -1,3 +1,16 @@
+pub mod bar_builder;
 pub mod candle;
 pub mod candle_pager;
+#[cfg(feature = "async")]
+pub mod candle_stream;
 pub mod candles_cache;
+pub mod codec;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod concurrent_cache;
+pub mod csv;
+pub mod indicators;
+pub mod tick;
+pub mod trade;
+pub mod trade_candle;
+pub mod trade_candles_cache;
@@ -0,0 +1,12 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BidAskTick {
+    pub datetime: DateTime<Utc>,
+    pub instrument: String,
+    pub bid: f64,
+    pub ask: f64,
+    pub bid_vol: f64,
+    pub ask_vol: f64,
+}
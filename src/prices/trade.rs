@@ -0,0 +1,18 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+/// Side of the aggressor of the trade
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trade {
+    pub datetime: DateTime<Utc>,
+    pub instrument: String,
+    pub price: f64,
+    pub quantity: f64,
+    pub side: TradeSide,
+}
@@ -0,0 +1,241 @@
+use crate::prices::candle::BidAskCandle;
+use crate::prices::trade::TradeSide;
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::candles_store::StoredCandle;
+use crate::shared::gap_fill::FlatCandle;
+use crate::shared::symbols::Symbol;
+use crate::shared::volume_profile::VolumeProfile;
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use serde_with::{serde_as, TimestampSecondsWithFrac};
+
+/// Trade OHLCV candle of the instrument. `volume` is the traded quantity split into
+/// `buy_volume` and `sell_volume` by the aggressor side, `notional_volume` is the sum
+/// of price multiplied by quantity. `datetime` is the unix timestamp in seconds of the latest trade.
+/// `volume_profile` is present only for intervals with enabled volume profiles
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeCandle {
+    pub interval: CandleInterval,
+    pub date: DateTime<Utc>,
+    pub instrument: Symbol,
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    pub volume: f64,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    pub notional_volume: f64,
+    pub trade_count: u64,
+    #[serde_as(as = "TimestampSecondsWithFrac<f64>")]
+    pub datetime: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_profile: Option<VolumeProfile>,
+}
+
+impl TradeCandle {
+    pub fn new(
+        interval: CandleInterval,
+        date: DateTime<Utc>,
+        instrument: Symbol,
+        datetime: DateTime<Utc>,
+        (price, quantity, side): (f64, f64, TradeSide),
+    ) -> Self {
+        let mut candle = Self {
+            interval,
+            date,
+            instrument,
+            open: price,
+            close: price,
+            high: price,
+            low: price,
+            volume: 0.0,
+            buy_volume: 0.0,
+            sell_volume: 0.0,
+            notional_volume: 0.0,
+            trade_count: 0,
+            datetime,
+            volume_profile: None,
+        };
+        candle.add_volume(price, quantity, side);
+
+        candle
+    }
+
+    /// Enables the volume profile of the bucket size starting with the volume of the candle.
+    /// The profile is not enabled for not finite or not positive bucket sizes
+    pub fn with_volume_profile(mut self, bucket_size: f64) -> Self {
+        self.volume_profile = VolumeProfile::new(bucket_size).map(|mut profile| {
+            if self.trade_count > 0 {
+                profile.add(self.open, self.volume);
+            }
+
+            profile
+        });
+
+        self
+    }
+
+    pub fn update(&mut self, datetime: DateTime<Utc>, price: f64, quantity: f64, side: TradeSide) {
+        if self.trade_count == 0 {
+            self.open = price;
+            self.high = price;
+            self.low = price;
+        }
+
+        self.close = price;
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.datetime = datetime;
+        self.add_volume(price, quantity, side);
+    }
+
+    fn add_volume(&mut self, price: f64, quantity: f64, side: TradeSide) {
+        self.volume += quantity;
+        self.notional_volume += price * quantity;
+        self.trade_count += 1;
+
+        if let Some(profile) = self.volume_profile.as_mut() {
+            profile.add(price, quantity);
+        }
+
+        match side {
+            TradeSide::Buy => self.buy_volume += quantity,
+            TradeSide::Sell => self.sell_volume += quantity,
+        }
+    }
+
+    /// Extends the candle with the data of the next candle
+    pub fn merge(&mut self, next: &TradeCandle) {
+        if next.trade_count == 0 {
+            return;
+        }
+
+        if self.trade_count == 0 {
+            self.open = next.open;
+            self.high = next.high;
+            self.low = next.low;
+        }
+
+        self.close = next.close;
+        self.high = self.high.max(next.high);
+        self.low = self.low.min(next.low);
+        self.volume += next.volume;
+        self.buy_volume += next.buy_volume;
+        self.sell_volume += next.sell_volume;
+        self.notional_volume += next.notional_volume;
+        self.trade_count += next.trade_count;
+        self.datetime = next.datetime;
+
+        if let (Some(profile), Some(next_profile)) =
+            (self.volume_profile.as_mut(), next.volume_profile.as_ref())
+        {
+            profile.merge(next_profile);
+        }
+    }
+
+    /// Gets volume weighted average price or None for candles without volume
+    pub fn get_vwap(&self) -> Option<f64> {
+        if self.volume == 0.0 {
+            return None;
+        }
+
+        Some(self.notional_volume / self.volume)
+    }
+
+    /// Same id scheme as `BidAskCandle::generate_id`
+    pub fn generate_id(
+        instrument: &str,
+        interval: &CandleInterval,
+        datetime: DateTime<Utc>,
+    ) -> String {
+        BidAskCandle::generate_id(instrument, interval, datetime)
+    }
+
+    /// Same as `generate_id` for the already calculated start date of the interval
+    pub fn generate_start_id(
+        instrument: &str,
+        interval: &CandleInterval,
+        start_date: DateTime<Utc>,
+    ) -> String {
+        BidAskCandle::generate_start_id(instrument, interval, start_date)
+    }
+
+    pub fn get_id(&self) -> String {
+        TradeCandle::generate_id(&self.instrument, &self.interval, self.date)
+    }
+}
+
+impl StoredCandle for TradeCandle {
+    fn get_owner(&self) -> Symbol {
+        self.instrument
+    }
+
+    fn get_interval(&self) -> CandleInterval {
+        self.interval
+    }
+
+    fn get_date(&self) -> DateTime<Utc> {
+        self.date
+    }
+
+    fn get_id(&self) -> String {
+        TradeCandle::get_id(self)
+    }
+}
+
+impl FlatCandle for TradeCandle {
+    fn to_flat(&self, date: DateTime<Utc>) -> Self {
+        let date = self.interval.get_start_date(date);
+
+        Self {
+            interval: self.interval,
+            date,
+            instrument: self.instrument,
+            open: self.close,
+            close: self.close,
+            high: self.close,
+            low: self.close,
+            volume: 0.0,
+            buy_volume: 0.0,
+            sell_volume: 0.0,
+            notional_volume: 0.0,
+            trade_count: 0,
+            datetime: date,
+            volume_profile: self.volume_profile.as_ref().map(VolumeProfile::to_empty),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn update_splits_volume_by_side() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut candle = TradeCandle::new(
+            CandleInterval::Minute,
+            date,
+            Symbol::new("BTCUSD"),
+            date,
+            (100.0, 2.0, TradeSide::Buy),
+        );
+        candle.update(date + Duration::seconds(1), 104.0, 1.0, TradeSide::Sell);
+        candle.update(date + Duration::seconds(2), 98.0, 1.0, TradeSide::Buy);
+
+        assert_eq!(candle.open, 100.0);
+        assert_eq!(candle.high, 104.0);
+        assert_eq!(candle.low, 98.0);
+        assert_eq!(candle.close, 98.0);
+        assert_eq!(candle.volume, 4.0);
+        assert_eq!(candle.buy_volume, 3.0);
+        assert_eq!(candle.sell_volume, 1.0);
+        assert_eq!(candle.notional_volume, 402.0);
+        assert_eq!(candle.trade_count, 3);
+        assert_eq!(candle.get_vwap(), Some(100.5));
+        assert_eq!(candle.datetime, date + Duration::seconds(2));
+    }
+}
@@ -0,0 +1,567 @@
+use crate::prices::trade::{Trade, TradeSide};
+use crate::prices::trade_candle::TradeCandle;
+use crate::shared::batch::BatchItemOutcome;
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::candles_store::CandlesStore;
+use crate::shared::events::CandleListener;
+use crate::shared::gap_fill::{GapFill, RangeCandle};
+use crate::shared::replay::{ReplayError, ReplayOrder};
+use crate::shared::retention::RetentionPolicy;
+use crate::shared::symbols::Symbol;
+use crate::shared::volume_profile::VolumeProfile;
+use ahash::{AHashMap, AHashSet};
+use chrono::{DateTime, Utc};
+
+/// Cache of trade candles parallel to `BidAskCandlesCache`
+pub struct TradeCandlesCache {
+    store: CandlesStore<TradeCandle>,
+    intervals: Vec<CandleInterval>,
+    pub last_update_date: Option<DateTime<Utc>>,
+    start_dates: Vec<(CandleInterval, DateTime<Utc>)>,
+    volume_profile_sizes: AHashMap<CandleInterval, f64>,
+}
+
+impl TradeCandlesCache {
+    pub fn new(candle_intervals: Vec<CandleInterval>) -> Self {
+        let mut candle_intervals = candle_intervals;
+        candle_intervals.sort();
+        candle_intervals.dedup();
+
+        Self {
+            store: CandlesStore::new(),
+            intervals: candle_intervals,
+            last_update_date: None,
+            start_dates: Vec::new(),
+            volume_profile_sizes: AHashMap::new(),
+        }
+    }
+
+    pub fn get_intervals(&self) -> &[CandleInterval] {
+        &self.intervals
+    }
+
+    /// Enables volume profiles of the bucket size for candles of the interval opened afterwards
+    /// Returns false for not finite or not positive bucket sizes
+    pub fn set_volume_profile(&mut self, interval: CandleInterval, bucket_size: f64) -> bool {
+        if VolumeProfile::new(bucket_size).is_none() {
+            return false;
+        }
+
+        self.volume_profile_sizes.insert(interval, bucket_size);
+
+        true
+    }
+
+    /// Disables volume profiles for candles of the interval opened afterwards
+    pub fn remove_volume_profile(&mut self, interval: CandleInterval) -> Option<f64> {
+        self.volume_profile_sizes.remove(&interval)
+    }
+
+    /// Gets the volume profile bucket size of the interval
+    pub fn get_volume_profile(&self, interval: CandleInterval) -> Option<f64> {
+        self.volume_profile_sizes.get(&interval).copied()
+    }
+
+    /// Adds the listener notified synchronously about opened, updated and closed candles
+    pub fn add_listener(&mut self, listener: Box<dyn CandleListener<TradeCandle>>) {
+        self.store.add_listener(listener);
+    }
+
+    pub fn clear_listeners(&mut self) {
+        self.store.clear_listeners();
+    }
+
+    /// Sets the retention policy of the interval. Policy is enforced on candle creation.
+    /// Returns false for not valid policies
+    pub fn set_retention(&mut self, interval: CandleInterval, policy: RetentionPolicy) -> bool {
+        self.store.set_retention(interval, policy)
+    }
+
+    pub fn remove_retention(&mut self, interval: CandleInterval) -> Option<RetentionPolicy> {
+        self.store.remove_retention(interval)
+    }
+
+    pub fn get_retention(&self, interval: CandleInterval) -> Option<&RetentionPolicy> {
+        self.store.get_retention(interval)
+    }
+
+    pub fn get_all(&self) -> &AHashMap<String, TradeCandle> {
+        self.store.get_all()
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.len() == 0
+    }
+
+    pub fn contains(&self, candle_id: &str) -> bool {
+        self.store.contains(candle_id)
+    }
+
+    /// Checks if the candle is finalized by `close_expired`. Finalized candles are not updated
+    /// anymore
+    pub fn is_closed(&self, candle_id: &str) -> bool {
+        self.store.is_closed(candle_id)
+    }
+
+    pub fn insert(&mut self, candle: TradeCandle) {
+        self.store.insert(candle, false);
+    }
+
+    pub fn insert_or_update(
+        &mut self,
+        datetime: DateTime<Utc>,
+        instrument: &str,
+        price: f64,
+        quantity: f64,
+        side: TradeSide,
+    ) {
+        let mut created_intervals = Vec::new();
+        self.upsert(
+            datetime,
+            instrument,
+            (price, quantity, side),
+            &mut created_intervals,
+        );
+        self.store.enforce_created(
+            created_intervals
+                .into_iter()
+                .map(|interval| (Symbol::new(instrument), interval)),
+        );
+        self.last_update_date.replace(Utc::now());
+    }
+
+    pub fn insert_trade(&mut self, trade: &Trade) {
+        self.insert_or_update(
+            trade.datetime,
+            &trade.instrument,
+            trade.price,
+            trade.quantity,
+            trade.side,
+        );
+    }
+
+    /// Inserts the trades enforcing retention once after the whole batch.
+    /// Returns outcomes in the order of trades
+    pub fn insert_or_update_batch(&mut self, trades: &[Trade]) -> Vec<BatchItemOutcome> {
+        let mut outcomes = Vec::with_capacity(trades.len());
+        let mut created_intervals = Vec::new();
+        let mut created_keys: AHashSet<(Symbol, CandleInterval)> = AHashSet::new();
+
+        for trade in trades {
+            created_intervals.clear();
+            outcomes.push(self.upsert(
+                trade.datetime,
+                &trade.instrument,
+                (trade.price, trade.quantity, trade.side),
+                &mut created_intervals,
+            ));
+            created_keys.extend(
+                created_intervals
+                    .iter()
+                    .map(|interval| (Symbol::new(&trade.instrument), *interval)),
+            );
+        }
+
+        self.store.enforce_created(created_keys);
+
+        if !trades.is_empty() {
+            self.last_update_date.replace(Utc::now());
+        }
+
+        outcomes
+    }
+
+    /// Updates forming candles and opens missing ones without enforcing retention.
+    /// Pushes intervals of opened candles to the created intervals
+    fn upsert(
+        &mut self,
+        datetime: DateTime<Utc>,
+        instrument: &str,
+        trade: (f64, f64, TradeSide),
+        created_intervals: &mut Vec<CandleInterval>,
+    ) -> BatchItemOutcome {
+        // the buffer is reused to avoid allocations on every update
+        let mut start_dates = std::mem::take(&mut self.start_dates);
+        start_dates.clear();
+        start_dates.extend(
+            self.intervals
+                .iter()
+                .map(|interval| (*interval, interval.get_start_date(datetime))),
+        );
+        let volume_profile_sizes = &self.volume_profile_sizes;
+        let (price, quantity, side) = trade;
+
+        let outcome = self.store.upsert(
+            instrument,
+            &start_dates,
+            |candle| candle.update(datetime, price, quantity, side),
+            |instrument, interval, start_date| {
+                let candle = TradeCandle::new(interval, start_date, instrument, datetime, trade);
+
+                match volume_profile_sizes.get(&interval) {
+                    Some(bucket_size) => candle.with_volume_profile(*bucket_size),
+                    None => candle,
+                }
+            },
+            created_intervals,
+        );
+        self.start_dates = start_dates;
+
+        outcome
+    }
+
+    /// Finalizes candles which interval ended before or at the specified date. Carry forward
+    /// opens flat candles with the close price of the finalized forming candles
+    /// for the interval containing the specified date. Returns ids of finalized candles
+    pub fn close_expired(&mut self, now: DateTime<Utc>, carry_forward: bool) -> Vec<String> {
+        self.store.close_expired(now, carry_forward)
+    }
+
+    /// Evicts candles expired by retention policies. Returns evicted candles count per interval
+    pub fn enforce_retention(&mut self) -> AHashMap<CandleInterval, usize> {
+        self.store.evict_expired(None)
+    }
+
+    /// Gets candles with date bigger or equals specified date
+    pub fn get_after(&self, datetime: DateTime<Utc>) -> Option<Vec<&TradeCandle>> {
+        self.store.get_after(datetime, |_candle| true)
+    }
+
+    /// Gets cached candles of the instrument for every interval date in the specified range
+    pub fn get_range(
+        &self,
+        instrument: &str,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Vec<&TradeCandle> {
+        self.store
+            .get_range(instrument, interval, start_date, end_date)
+    }
+
+    /// Gets candles of the instrument for every interval date in the specified range
+    /// with missing candles handled by the specified gap fill
+    pub fn get_range_filled(
+        &self,
+        instrument: &str,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        fill: GapFill,
+    ) -> Vec<RangeCandle<'_, TradeCandle>> {
+        self.store
+            .get_range_filled(instrument, interval, start_date, end_date, fill)
+    }
+
+    /// Removes candles with date less or equals specified date
+    pub fn remove_before(
+        &mut self,
+        datetime: DateTime<Utc>,
+        candle_type: Option<CandleInterval>,
+    ) -> i32 {
+        self.store
+            .remove_before(datetime, candle_type, |_candle| true)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&TradeCandle> {
+        self.store.get(id)
+    }
+
+    /// Inserts trades of the time-ordered log as if they were received at their datetime.
+    /// Stops at the first trade earlier than the previous one. Returns replayed trades count
+    pub fn replay(
+        &mut self,
+        trades: impl IntoIterator<Item = Trade>,
+    ) -> Result<usize, ReplayError> {
+        let mut order = ReplayOrder::default();
+
+        for trade in trades {
+            order.check(trade.datetime)?;
+            self.insert_trade(&trade);
+            self.last_update_date.replace(trade.datetime);
+        }
+
+        Ok(order.get_count())
+    }
+
+    /// Stable checksum of all candles and their closed state which doesn't depend
+    /// on insertion order. Equal for caches with equal candles
+    pub fn get_checksum(&self) -> u64 {
+        self.store.get_checksum(|hasher, candle| {
+            for value in [
+                candle.open,
+                candle.close,
+                candle.high,
+                candle.low,
+                candle.volume,
+                candle.buy_volume,
+                candle.sell_volume,
+                candle.notional_volume,
+            ] {
+                hasher.write_f64(value);
+            }
+
+            hasher.write(&candle.trade_count.to_le_bytes());
+            hasher.write_date(candle.datetime);
+            hasher.write_volume_profile(candle.volume_profile.as_ref());
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn insert_or_update_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = TradeCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+
+        cache.insert_or_update(date, "BTCUSD", 100.0, 1.0, TradeSide::Buy);
+        cache.insert_or_update(
+            date + Duration::seconds(30),
+            "BTCUSD",
+            101.0,
+            2.0,
+            TradeSide::Sell,
+        );
+        cache.insert_or_update(
+            date + Duration::seconds(60),
+            "BTCUSD",
+            99.0,
+            1.0,
+            TradeSide::Buy,
+        );
+
+        let minute_id = TradeCandle::generate_id("BTCUSD", &CandleInterval::Minute, date);
+        let minute_candle = cache.get(&minute_id).unwrap();
+        let hour_candle = cache
+            .get(&TradeCandle::generate_id(
+                "BTCUSD",
+                &CandleInterval::Hour,
+                date,
+            ))
+            .unwrap();
+
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.is_closed(&minute_id));
+        assert_eq!(minute_candle.close, 101.0);
+        assert_eq!(minute_candle.buy_volume, 1.0);
+        assert_eq!(minute_candle.sell_volume, 2.0);
+        assert_eq!(minute_candle.notional_volume, 302.0);
+        assert_eq!(hour_candle.low, 99.0);
+        assert_eq!(hour_candle.trade_count, 3);
+        assert_eq!(
+            cache
+                .get_range(
+                    "BTCUSD",
+                    CandleInterval::Minute,
+                    date,
+                    date + Duration::minutes(5)
+                )
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    pub fn insert_or_update_late_trade() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = TradeCandlesCache::new(vec![CandleInterval::Minute]);
+        let minute_id = TradeCandle::generate_id("BTCUSD", &CandleInterval::Minute, date);
+
+        cache.insert_or_update(date, "BTCUSD", 100.0, 1.0, TradeSide::Buy);
+        cache.insert_or_update(
+            date + Duration::minutes(1),
+            "BTCUSD",
+            100.0,
+            1.0,
+            TradeSide::Buy,
+        );
+        cache.insert_or_update(
+            date + Duration::seconds(30),
+            "BTCUSD",
+            105.0,
+            2.0,
+            TradeSide::Sell,
+        );
+
+        assert!(!cache.is_closed(&minute_id));
+        assert_eq!(cache.get(&minute_id).unwrap().high, 105.0);
+        assert_eq!(cache.get(&minute_id).unwrap().sell_volume, 2.0);
+
+        cache.close_expired(date + Duration::minutes(1), false);
+        cache.insert_or_update(
+            date + Duration::seconds(40),
+            "BTCUSD",
+            110.0,
+            1.0,
+            TradeSide::Buy,
+        );
+
+        assert!(cache.is_closed(&minute_id));
+        assert_eq!(cache.get(&minute_id).unwrap().high, 105.0);
+    }
+
+    #[test]
+    pub fn close_expired_three_and_seven_days() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 10, 0, 0, 0).unwrap();
+
+        for interval in [CandleInterval::ThreeDays, CandleInterval::SevenDays] {
+            let mut cache = TradeCandlesCache::new(vec![interval]);
+            let start_date = interval.get_start_date(date);
+            let next_date = interval.get_nth_date(start_date, 1).unwrap();
+            let id = TradeCandle::generate_id("BTCUSD", &interval, start_date);
+            cache.insert_or_update(start_date, "BTCUSD", 100.0, 1.0, TradeSide::Buy);
+
+            assert!(cache
+                .close_expired(next_date - Duration::seconds(1), true)
+                .is_empty());
+
+            cache.insert_or_update(
+                next_date - Duration::seconds(1),
+                "BTCUSD",
+                105.0,
+                1.0,
+                TradeSide::Buy,
+            );
+
+            assert_eq!(cache.get(&id).unwrap().close, 105.0);
+            assert_eq!(cache.close_expired(next_date, true), vec![id.clone()]);
+
+            let carried_id = TradeCandle::generate_id("BTCUSD", &interval, next_date);
+
+            assert_eq!(cache.len(), 2);
+            assert_eq!(cache.get(&id).unwrap().trade_count, 2);
+            assert_eq!(cache.get(&carried_id).unwrap().open, 105.0);
+            assert!(!cache.is_closed(&carried_id));
+        }
+    }
+
+    #[test]
+    pub fn insert_or_update_batch_matches_single_inserts() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let trades: Vec<Trade> = (0..200)
+            .map(|i| Trade {
+                datetime: date + Duration::seconds(i * 7),
+                instrument: ["BTCUSD", "ETHUSD"][i as usize % 2].to_string(),
+                price: 100.0 + (i % 11) as f64,
+                quantity: 0.5,
+                side: if i % 3 == 0 {
+                    TradeSide::Sell
+                } else {
+                    TradeSide::Buy
+                },
+            })
+            .collect();
+        let mut cache = TradeCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        let mut expected_cache =
+            TradeCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+
+        let outcomes = cache.insert_or_update_batch(&trades);
+        let replayed_count = expected_cache.replay(trades.clone()).unwrap();
+
+        assert_eq!(outcomes.len(), trades.len());
+        assert_eq!(replayed_count, trades.len());
+        assert_eq!(
+            outcomes[0],
+            BatchItemOutcome {
+                created: 2,
+                updated: 0,
+                skipped: 0
+            }
+        );
+        assert_eq!(cache.get_checksum(), expected_cache.get_checksum());
+    }
+
+    #[test]
+    pub fn volume_profile_of_enabled_intervals() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = TradeCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        cache.set_volume_profile(CandleInterval::Hour, 10.0);
+
+        for (i, price) in [100.0, 105.0, 112.0, 101.0].into_iter().enumerate() {
+            let datetime = date + Duration::minutes(i as i64);
+            cache.insert_or_update(datetime, "BTCUSD", price, 1.0, TradeSide::Buy);
+        }
+
+        let minute_candles: Vec<_> = cache
+            .get_all()
+            .values()
+            .filter(|candle| candle.interval == CandleInterval::Minute)
+            .collect();
+        let mut hour_candle = cache
+            .get(&TradeCandle::generate_id(
+                "BTCUSD",
+                &CandleInterval::Hour,
+                date,
+            ))
+            .unwrap()
+            .clone();
+
+        assert!(minute_candles
+            .iter()
+            .all(|candle| candle.volume_profile.is_none()));
+        assert_eq!(
+            hour_candle
+                .volume_profile
+                .as_ref()
+                .unwrap()
+                .get_levels()
+                .collect::<Vec<_>>(),
+            vec![(100.0, 3.0), (110.0, 1.0)]
+        );
+
+        let next_hour_date = date + Duration::hours(1);
+        let next_hour_candle = TradeCandle::new(
+            CandleInterval::Hour,
+            next_hour_date,
+            hour_candle.instrument,
+            next_hour_date,
+            (103.0, 2.0, TradeSide::Sell),
+        )
+        .with_volume_profile(10.0);
+        hour_candle.merge(&next_hour_candle);
+
+        assert_eq!(
+            hour_candle
+                .volume_profile
+                .unwrap()
+                .get_levels()
+                .collect::<Vec<_>>(),
+            vec![(100.0, 5.0), (110.0, 1.0)]
+        );
+        assert!(!cache.set_volume_profile(CandleInterval::Minute, 0.0));
+        assert!(!cache.set_volume_profile(CandleInterval::Minute, -10.0));
+        assert_eq!(cache.get_volume_profile(CandleInterval::Minute), None);
+    }
+
+    #[test]
+    pub fn enforce_retention_and_remove_before() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = TradeCandlesCache::new(vec![CandleInterval::Minute, CandleInterval::Hour]);
+        cache.set_retention(CandleInterval::Minute, RetentionPolicy::MaxCount(3));
+
+        for i in 0..10 {
+            cache.insert_or_update(
+                date + Duration::minutes(i),
+                "BTCUSD",
+                100.0,
+                1.0,
+                TradeSide::Buy,
+            );
+        }
+
+        assert_eq!(cache.len(), 4);
+        assert_eq!(
+            cache.remove_before(date + Duration::minutes(8), Some(CandleInterval::Minute)),
+            2
+        );
+        assert_eq!(cache.len(), 2);
+    }
+}
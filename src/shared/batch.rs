@@ -0,0 +1,9 @@
+/// Outcome of a single item of the batch: count of intervals which candles
+/// were opened, updated or skipped
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BatchItemOutcome {
+    pub created: usize,
+    pub updated: usize,
+    /// Intervals which candles are already closed
+    pub skipped: usize,
+}
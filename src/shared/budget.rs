@@ -0,0 +1,217 @@
+use crate::shared::candle_interval::CandleInterval;
+use ahash::AHashMap;
+use chrono::{DateTime, Utc};
+
+/// Evictions free the cache down to the limits reduced by the 1/10 share, so candidates are
+/// selected once per batch of inserts instead of on every insert at the limit
+const LOW_WATER_MARK_DIVISOR: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionStrategy {
+    /// Evicts candles with the oldest date first
+    OldestFirst,
+    /// Evicts candles which were read least recently first
+    LeastRecentlyRead,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheBudget {
+    pub max_entries: Option<usize>,
+    pub max_memory_bytes: Option<usize>,
+    pub strategy: EvictionStrategy,
+}
+
+impl CacheBudget {
+    pub fn is_exceeded(&self, entries_count: usize, memory_bytes: usize) -> bool {
+        if let Some(max_entries) = self.max_entries {
+            if entries_count > max_entries {
+                return true;
+            }
+        }
+
+        if let Some(max_memory_bytes) = self.max_memory_bytes {
+            if memory_bytes > max_memory_bytes {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Gets the budget which evictions free the cache down to
+    pub fn get_low_water_mark(&self) -> Self {
+        let get_mark = |limit: usize| limit - limit / LOW_WATER_MARK_DIVISOR;
+
+        Self {
+            max_entries: self.max_entries.map(get_mark),
+            max_memory_bytes: self.max_memory_bytes.map(get_mark),
+            strategy: self.strategy,
+        }
+    }
+
+    /// Gets the budget of one of the parts, limits are divided evenly and rounded down
+    pub fn divide(&self, parts_count: usize) -> Self {
+        let parts_count = parts_count.max(1);
+
+        Self {
+            max_entries: self
+                .max_entries
+                .map(|max_entries| max_entries / parts_count),
+            max_memory_bytes: self
+                .max_memory_bytes
+                .map(|max_memory_bytes| max_memory_bytes / parts_count),
+            strategy: self.strategy,
+        }
+    }
+}
+
+pub struct EvictionCandidate<'a, K> {
+    pub key: K,
+    /// Instrument or ref_id of the candle
+    pub owner: &'a str,
+    pub interval: CandleInterval,
+    pub date: DateTime<Utc>,
+    pub last_read: u64,
+    pub size: usize,
+}
+
+/// Selects keys to evict if the budget is exceeded until the low-water mark of the budget is
+/// satisfied. The latest (currently forming) candle of each owner and interval is never selected
+pub fn select_evictions<K>(
+    budget: &CacheBudget,
+    entries_count: usize,
+    memory_bytes: usize,
+    candidates: Vec<EvictionCandidate<'_, K>>,
+) -> Vec<K> {
+    if !budget.is_exceeded(entries_count, memory_bytes) {
+        return vec![];
+    }
+
+    let mut forming_dates: AHashMap<(&str, CandleInterval), DateTime<Utc>> = AHashMap::new();
+
+    for candidate in candidates.iter() {
+        let date = forming_dates
+            .entry((candidate.owner, candidate.interval))
+            .or_insert(candidate.date);
+
+        if candidate.date > *date {
+            *date = candidate.date;
+        }
+    }
+
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|candidate| {
+            forming_dates.get(&(candidate.owner, candidate.interval)) != Some(&candidate.date)
+        })
+        .collect();
+
+    match budget.strategy {
+        EvictionStrategy::OldestFirst => candidates.sort_unstable_by_key(|c| c.date),
+        EvictionStrategy::LeastRecentlyRead => {
+            candidates.sort_unstable_by_key(|c| (c.last_read, c.date))
+        }
+    }
+
+    let low_water_mark = budget.get_low_water_mark();
+    let mut entries_count = entries_count;
+    let mut memory_bytes = memory_bytes;
+    let mut keys = Vec::new();
+
+    for candidate in candidates {
+        if !low_water_mark.is_exceeded(entries_count, memory_bytes) {
+            break;
+        }
+
+        entries_count -= 1;
+        memory_bytes = memory_bytes.saturating_sub(candidate.size);
+        keys.push(candidate.key);
+    }
+
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shared::budget::{
+        select_evictions, CacheBudget, EvictionCandidate, EvictionStrategy,
+    };
+    use crate::shared::candle_interval::CandleInterval;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    fn get_candidates(date: DateTime<Utc>, count: usize) -> Vec<EvictionCandidate<'static, usize>> {
+        (0..count)
+            .map(|i| EvictionCandidate {
+                key: i,
+                owner: "1",
+                interval: CandleInterval::Minute,
+                date: date + Duration::minutes(i as i64),
+                last_read: (2 * count - i) as u64,
+                size: 100,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn select_evictions_oldest_first() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let budget = CacheBudget {
+            max_entries: Some(3),
+            max_memory_bytes: None,
+            strategy: EvictionStrategy::OldestFirst,
+        };
+
+        let keys = select_evictions(&budget, 5, 500, get_candidates(date, 5));
+
+        assert_eq!(keys, vec![0, 1]);
+    }
+
+    #[test]
+    fn select_evictions_least_recently_read_keeps_forming() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let budget = CacheBudget {
+            max_entries: None,
+            max_memory_bytes: Some(50),
+            strategy: EvictionStrategy::LeastRecentlyRead,
+        };
+
+        let keys = select_evictions(&budget, 5, 500, get_candidates(date, 5));
+
+        assert_eq!(keys, vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn select_evictions_down_to_low_water_mark() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let budget = CacheBudget {
+            max_entries: Some(20),
+            max_memory_bytes: None,
+            strategy: EvictionStrategy::OldestFirst,
+        };
+
+        assert!(select_evictions(&budget, 20, 2000, get_candidates(date, 20)).is_empty());
+        assert_eq!(
+            select_evictions(&budget, 21, 2100, get_candidates(date, 21)),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn divide_budget() {
+        let budget = CacheBudget {
+            max_entries: Some(10),
+            max_memory_bytes: Some(1000),
+            strategy: EvictionStrategy::LeastRecentlyRead,
+        };
+
+        assert_eq!(
+            budget.divide(3),
+            CacheBudget {
+                max_entries: Some(3),
+                max_memory_bytes: Some(333),
+                strategy: EvictionStrategy::LeastRecentlyRead,
+            }
+        );
+        assert_eq!(budget.divide(0), budget);
+    }
+}
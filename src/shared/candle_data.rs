@@ -4,7 +4,7 @@ use serde_derive::{Deserialize, Serialize};
 use serde_with::{serde_as, TimestampSecondsWithFrac};
 
 #[serde_as]
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CandleData {
     pub open: f64,
     pub close: f64,
@@ -17,19 +17,27 @@ pub struct CandleData {
 
 impl CandleData {
     pub fn new(value: f64) -> Self {
+        Self::new_at(value, Utc::now())
+    }
+
+    pub fn new_at(value: f64, timestamp: DateTime<Utc>) -> Self {
         Self {
             open: value,
             close: value,
             high: value,
             low: value,
             low_after_high: value,
-            timestamp: Utc::now(),
+            timestamp,
         }
     }
 
     pub fn update(&mut self, value: f64) {
+        self.update_at(value, Utc::now());
+    }
+
+    pub fn update_at(&mut self, value: f64, timestamp: DateTime<Utc>) {
         self.close = value;
-        self.timestamp = Utc::now();
+        self.timestamp = timestamp;
 
         if self.open == 0.0 {
             self.open = value;
@@ -52,6 +60,20 @@ impl CandleData {
     pub fn get_candle_date(&self, candle_type: CandleInterval) -> DateTime<Utc> {
         candle_type.get_start_date(self.timestamp)
     }
+
+    /// Extends the data with the data of the next candle
+    pub fn merge(&mut self, next: &CandleData) {
+        if next.high > self.high {
+            self.high = next.high;
+            self.low_after_high = next.low_after_high;
+        } else {
+            self.low_after_high = self.low_after_high.min(next.low);
+        }
+
+        self.close = next.close;
+        self.low = self.low.min(next.low);
+        self.timestamp = next.timestamp;
+    }
 }
 
 #[cfg(test)]
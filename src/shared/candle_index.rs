@@ -1,17 +1,18 @@
 use crate::shared::candle_interval::CandleInterval;
+use crate::shared::symbols::Symbol;
 use chrono::{DateTime, Utc};
 use std::fmt::Display;
 
-#[derive(Debug, Clone, Eq, Hash, PartialEq)]
+#[derive(Debug, Clone, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub struct CandleIndex {
-    pub ref_id: String,
+    pub ref_id: Symbol,
     pub candle_interval: CandleInterval,
     pub interval_start_date: DateTime<Utc>,
 }
 
 impl CandleIndex {
     pub fn new(
-        ref_id: impl Into<String>,
+        ref_id: impl Into<Symbol>,
         candle_interval: CandleInterval,
         date: DateTime<Utc>,
     ) -> Self {
@@ -29,8 +30,7 @@ impl CandleIndex {
             "{}{}{}",
             self.candle_interval as u32,
             self.ref_id,
-            self.interval_start_date
-                .timestamp(),
+            self.interval_start_date.timestamp(),
         )
     }
 }
@@ -1,6 +1,6 @@
 use ahash::AHashSet;
 use chrono::{DateTime, Datelike, Utc};
-use chrono::{Duration, TimeZone};
+use chrono::{Duration, Months, TimeZone};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
@@ -153,8 +153,49 @@ impl CandleInterval {
         }
     }
 
+    /// Gets start date of the interval which is the specified count of intervals after
+    /// the start date
+    pub fn get_nth_date(&self, start_date: DateTime<Utc>, n: u32) -> Option<DateTime<Utc>> {
+        match self {
+            CandleInterval::Month => start_date.checked_add_months(Months::new(n)),
+            CandleInterval::Endless => (n == 0).then_some(start_date),
+            _ => {
+                start_date.checked_add_signed(self.get_step().checked_mul(i32::try_from(n).ok()?)?)
+            }
+        }
+    }
+
+    /// Gets count of intervals between the start dates. Returns None if the date is not a start
+    /// date of the intervals sequence beginning with the start date
+    pub fn get_date_index(&self, start_date: DateTime<Utc>, date: DateTime<Utc>) -> Option<u32> {
+        let index = match self {
+            CandleInterval::Month => {
+                let months = (date.year() - start_date.year()) as i64 * 12 + date.month() as i64
+                    - start_date.month() as i64;
+                u32::try_from(months).ok()?
+            }
+            CandleInterval::Endless => 0,
+            _ => {
+                let seconds = (date - start_date).num_seconds();
+                u32::try_from(seconds / self.get_step().num_seconds()).ok()?
+            }
+        };
+
+        (self.get_nth_date(start_date, index) == Some(date)).then_some(index)
+    }
+
+    /// Gets the distance between consecutive start dates of intervals with fixed durations.
+    /// Start dates of `ThreeDays` and `SevenDays` are aligned to 7 and 12 days
+    fn get_step(&self) -> Duration {
+        match self {
+            CandleInterval::ThreeDays => Duration::seconds(604800),
+            CandleInterval::SevenDays => Duration::seconds(1036800),
+            _ => self.get_duration(DateTime::UNIX_EPOCH),
+        }
+    }
+
     pub fn get_duration(&self, datetime: DateTime<Utc>) -> Duration {
-        let duration = match self {
+        match self {
             CandleInterval::Minute => Duration::seconds(60),
             CandleInterval::Hour => Duration::seconds(3600),
             CandleInterval::Day => Duration::seconds(86400),
@@ -192,9 +233,32 @@ impl CandleInterval {
             CandleInterval::ThreeDays => Duration::days(3),
             CandleInterval::SevenDays => Duration::days(7),
             CandleInterval::Endless => Duration::MAX,
-        };
+        }
+    }
+
+    /// Returns true if every candle of the interval is inside a single candle
+    /// of the other interval
+    pub fn fits_into(&self, other: CandleInterval) -> bool {
+        if self == &other || self == &CandleInterval::Endless {
+            return false;
+        }
+
+        match (self.get_fixed_seconds(), other) {
+            (_, CandleInterval::Endless) => true,
+            (Some(seconds), CandleInterval::Month) => 86400 % seconds == 0,
+            (Some(seconds), other) => other
+                .get_fixed_seconds()
+                .is_some_and(|other_seconds| other_seconds % seconds == 0),
+            (None, _) => false,
+        }
+    }
 
-        duration
+    /// Gets duration of the intervals aligned to the unix epoch
+    fn get_fixed_seconds(&self) -> Option<i64> {
+        match self {
+            CandleInterval::Month | CandleInterval::Endless => None,
+            _ => Some(self.get_duration(DateTime::UNIX_EPOCH).num_seconds()),
+        }
     }
 }
 
@@ -408,4 +472,59 @@ mod tests {
             assert!(dates.contains(&date));
         }
     }
+
+    #[test]
+    fn get_date_index_for_days() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 5, 13, 0, 0).unwrap();
+
+        for interval in [CandleInterval::ThreeDays, CandleInterval::SevenDays] {
+            let start_date = interval.get_start_date(date);
+            let next_date = interval.get_nth_date(start_date, 1).unwrap();
+
+            assert!(next_date >= interval.get_end_date(date));
+            assert_eq!(interval.get_start_date(next_date), next_date);
+            assert_eq!(interval.get_date_index(start_date, next_date), Some(1));
+        }
+    }
+
+    #[test]
+    fn get_date_index_1() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 11, 1, 0, 0, 0).unwrap();
+        let month_date: DateTime<Utc> = Utc.with_ymd_and_hms(2002, 2, 1, 0, 0, 0).unwrap();
+
+        assert_eq!(
+            CandleInterval::Month.get_date_index(date, month_date),
+            Some(15)
+        );
+        assert_eq!(
+            CandleInterval::Month.get_nth_date(date, 15),
+            Some(month_date)
+        );
+        assert_eq!(
+            CandleInterval::Hour.get_date_index(date, date + Duration::hours(30)),
+            Some(30)
+        );
+        assert_eq!(
+            CandleInterval::Hour.get_date_index(date, date + Duration::minutes(30)),
+            None
+        );
+        assert_eq!(
+            CandleInterval::Hour.get_date_index(date, date - Duration::hours(1)),
+            None
+        );
+        assert_eq!(CandleInterval::Endless.get_nth_date(date, 1), None);
+        assert_eq!(CandleInterval::Minute.get_nth_date(date, u32::MAX), None);
+    }
+
+    #[test]
+    fn fits_into_1() {
+        assert!(CandleInterval::Minute.fits_into(CandleInterval::Hour));
+        assert!(CandleInterval::FifteenMinutes.fits_into(CandleInterval::Month));
+        assert!(CandleInterval::Day.fits_into(CandleInterval::SevenDays));
+        assert!(CandleInterval::Month.fits_into(CandleInterval::Endless));
+        assert!(!CandleInterval::SevenDays.fits_into(CandleInterval::Month));
+        assert!(!CandleInterval::ThreeMinutes.fits_into(CandleInterval::FiveMinutes));
+        assert!(!CandleInterval::Hour.fits_into(CandleInterval::Minute));
+        assert!(!CandleInterval::Hour.fits_into(CandleInterval::Hour));
+    }
 }
@@ -0,0 +1,26 @@
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::symbols::Symbol;
+use chrono::{DateTime, Utc};
+
+/// Composite key which allows to find candle ids without allocations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CandleKey {
+    instrument: Symbol,
+    interval: CandleInterval,
+    start_timestamp: i64,
+}
+
+impl CandleKey {
+    /// Creates the key of the candle with the start date of the interval
+    pub(crate) fn new(
+        instrument: Symbol,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            instrument,
+            interval,
+            start_timestamp: start_date.timestamp(),
+        }
+    }
+}
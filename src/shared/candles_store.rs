@@ -0,0 +1,734 @@
+use crate::shared::batch::BatchItemOutcome;
+use crate::shared::budget::{select_evictions, CacheBudget, EvictionCandidate, EvictionStrategy};
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::candle_key::CandleKey;
+use crate::shared::changes::{ChangeSet, ChangeTracker};
+use crate::shared::events::{notify, CandleEventKind, CandleListener};
+use crate::shared::gap_fill::{get_range_filled, FlatCandle, GapFill, RangeCandle};
+use crate::shared::replay::ChecksumHasher;
+use crate::shared::retention::{RetentionIndex, RetentionPolicy};
+use crate::shared::symbols::{Symbol, SymbolRegistry};
+use ahash::{AHashMap, AHashSet};
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Candle kept by `CandlesStore`
+pub(crate) trait StoredCandle: Clone + FlatCandle {
+    /// Instrument of the candle
+    fn get_owner(&self) -> Symbol;
+    fn get_interval(&self) -> CandleInterval;
+    fn get_date(&self) -> DateTime<Utc>;
+    fn get_id(&self) -> String;
+}
+
+/// Candles by ids with the state shared by candle caches: forming and finalized candles,
+/// retention, budget, change tracking and lifecycle listeners
+pub(crate) struct CandlesStore<C> {
+    candles_by_ids: AHashMap<String, C>,
+    ids_by_keys: AHashMap<CandleKey, String>,
+    forming_dates: AHashMap<Symbol, AHashMap<CandleInterval, DateTime<Utc>>>,
+    closed_ids: AHashSet<String>,
+    retention_policies: AHashMap<CandleInterval, RetentionPolicy>,
+    retention_index: RetentionIndex,
+    budget: Option<CacheBudget>,
+    memory_usage: usize,
+    read_clock: AtomicU64,
+    read_stamps: AHashMap<String, AtomicU64>,
+    changes: Option<ChangeTracker<String>>,
+    listeners: Vec<Box<dyn CandleListener<C>>>,
+}
+
+impl<C: StoredCandle> CandlesStore<C> {
+    pub fn new() -> Self {
+        Self {
+            candles_by_ids: AHashMap::new(),
+            ids_by_keys: AHashMap::new(),
+            forming_dates: AHashMap::new(),
+            closed_ids: AHashSet::new(),
+            retention_policies: AHashMap::new(),
+            retention_index: RetentionIndex::default(),
+            budget: None,
+            memory_usage: 0,
+            read_clock: AtomicU64::new(0),
+            read_stamps: AHashMap::new(),
+            changes: None,
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn add_listener(&mut self, listener: Box<dyn CandleListener<C>>) {
+        self.listeners.push(listener);
+    }
+
+    pub fn clear_listeners(&mut self) {
+        self.listeners.clear();
+    }
+
+    /// Returns false for not valid policies
+    pub fn set_retention(&mut self, interval: CandleInterval, policy: RetentionPolicy) -> bool {
+        if !policy.is_valid() {
+            return false;
+        }
+
+        if self.retention_policies.insert(interval, policy).is_none() {
+            for candle in self.candles_by_ids.values() {
+                if candle.get_interval() == interval {
+                    self.retention_index
+                        .insert(candle.get_owner(), interval, candle.get_date());
+                }
+            }
+        }
+
+        true
+    }
+
+    pub fn remove_retention(&mut self, interval: CandleInterval) -> Option<RetentionPolicy> {
+        let policy = self.retention_policies.remove(&interval)?;
+        self.retention_index.remove_interval(interval);
+
+        Some(policy)
+    }
+
+    pub fn get_retention(&self, interval: CandleInterval) -> Option<&RetentionPolicy> {
+        self.retention_policies.get(&interval)
+    }
+
+    pub fn set_budget(&mut self, budget: Option<CacheBudget>) {
+        if let Some(CacheBudget {
+            strategy: EvictionStrategy::LeastRecentlyRead,
+            ..
+        }) = budget
+        {
+            let stamp = self.read_clock.load(Ordering::Relaxed);
+
+            for id in self.candles_by_ids.keys() {
+                if !self.read_stamps.contains_key(id) {
+                    self.read_stamps
+                        .insert(id.to_owned(), AtomicU64::new(stamp));
+                }
+            }
+        } else {
+            self.read_stamps.clear();
+        }
+
+        self.budget = budget;
+        self.enforce_budget();
+    }
+
+    pub fn get_budget(&self) -> Option<&CacheBudget> {
+        self.budget.as_ref()
+    }
+
+    pub fn enable_change_tracking(&mut self) {
+        if self.changes.is_none() {
+            self.changes = Some(ChangeTracker::new());
+        }
+    }
+
+    pub fn disable_change_tracking(&mut self) {
+        self.changes = None;
+    }
+
+    pub fn get_changes_version(&self) -> u64 {
+        self.changes
+            .as_ref()
+            .map(|changes| changes.get_version())
+            .unwrap_or_default()
+    }
+
+    pub fn get_changed_since(&self, version: u64) -> Vec<&C> {
+        let Some(changes) = self.changes.as_ref() else {
+            return vec![];
+        };
+
+        changes
+            .get_changed_since(version)
+            .filter_map(|id| self.candles_by_ids.get(id))
+            .collect()
+    }
+
+    pub fn take_changes(&mut self) -> ChangeSet<String> {
+        match self.changes.as_mut() {
+            Some(changes) => changes.take(),
+            None => ChangeSet {
+                version: 0,
+                keys: vec![],
+            },
+        }
+    }
+
+    pub fn estimate_memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    pub fn get_all(&self) -> &AHashMap<String, C> {
+        &self.candles_by_ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.candles_by_ids.len()
+    }
+
+    pub fn contains(&self, candle_id: &str) -> bool {
+        self.candles_by_ids.contains_key(candle_id)
+    }
+
+    pub fn is_closed(&self, candle_id: &str) -> bool {
+        self.closed_ids.contains(candle_id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&C> {
+        let candle = self.candles_by_ids.get(id);
+
+        if candle.is_some() {
+            self.mark_read(id);
+        }
+
+        candle
+    }
+
+    /// Inserts or replaces the candle without enforcing retention and budget
+    pub fn insert(&mut self, candle: C, closed: bool) {
+        let id = candle.get_id();
+        self.update_forming_date(&candle);
+
+        if closed {
+            self.closed_ids.insert(id.clone());
+        }
+
+        self.insert_candle(id, candle);
+    }
+
+    /// Updates not finalized candles of the owner for the interval start dates and then opens
+    /// missing ones without enforcing retention and budget. Pushes intervals of opened candles
+    /// to the created intervals
+    pub fn upsert(
+        &mut self,
+        owner: &str,
+        start_dates: &[(CandleInterval, DateTime<Utc>)],
+        mut update: impl FnMut(&mut C),
+        mut create: impl FnMut(Symbol, CandleInterval, DateTime<Utc>) -> C,
+        created_intervals: &mut Vec<CandleInterval>,
+    ) -> BatchItemOutcome {
+        let mut outcome = BatchItemOutcome::default();
+        let mut created_dates = Vec::new();
+        let symbol = SymbolRegistry::get(owner);
+
+        for (interval, start_date) in start_dates.iter() {
+            let id = symbol.and_then(|symbol| {
+                self.ids_by_keys
+                    .get(&CandleKey::new(symbol, *interval, *start_date))
+            });
+            let candle = id.and_then(|id| Some((id, self.candles_by_ids.get_mut(id)?)));
+
+            let Some((id, candle)) = candle else {
+                created_dates.push((*interval, *start_date));
+                continue;
+            };
+
+            if self.closed_ids.contains(id) {
+                outcome.skipped += 1;
+                continue;
+            }
+
+            update(candle);
+            notify(
+                &mut self.listeners,
+                CandleEventKind::Updated,
+                *interval,
+                candle,
+            );
+
+            if let Some(changes) = self.changes.as_mut() {
+                changes.mark_changed(id.as_str());
+            }
+
+            outcome.updated += 1;
+        }
+
+        for (interval, start_date) in created_dates {
+            let candle = create(Symbol::new(owner), interval, start_date);
+
+            #[cfg(feature = "console-log")]
+            println!(
+                "insert candle {}: {} {}; {} total count",
+                owner,
+                start_date.to_rfc3339(),
+                candle.get_id(),
+                self.candles_by_ids.len() + 1
+            );
+
+            self.open_candle(candle.get_id(), candle);
+            created_intervals.push(interval);
+            outcome.created += 1;
+        }
+
+        outcome
+    }
+
+    /// Evicts expired candles of the created owners and intervals and enforces the budget
+    /// if any candle was created
+    pub fn enforce_created(
+        &mut self,
+        created_keys: impl IntoIterator<Item = (Symbol, CandleInterval)>,
+    ) {
+        let mut is_created = false;
+
+        for key in created_keys {
+            if self.retention_policies.contains_key(&key.1) {
+                self.evict_expired(Some(key));
+            }
+
+            is_created = true;
+        }
+
+        if is_created {
+            self.enforce_budget();
+        }
+    }
+
+    /// Finalizes candles which interval ended before or at the specified date. Carry forward
+    /// opens flat candles with the close values of the finalized forming candles
+    /// for the interval containing the specified date. Returns ids of finalized candles
+    pub fn close_expired(&mut self, now: DateTime<Utc>, carry_forward: bool) -> Vec<String> {
+        let mut expired_ids: Vec<String> = self
+            .candles_by_ids
+            .iter()
+            .filter(|(id, candle)| {
+                !self.closed_ids.contains(id.as_str())
+                    && candle
+                        .get_interval()
+                        .get_nth_date(candle.get_date(), 1)
+                        .is_some_and(|next_date| next_date <= now)
+            })
+            .map(|(id, _candle)| id.to_owned())
+            .collect();
+        expired_ids.sort();
+        let mut carried_candles = Vec::new();
+
+        for id in expired_ids.iter() {
+            if !self.close_candle(id) || !carry_forward {
+                continue;
+            }
+
+            let Some(candle) = self.candles_by_ids.get(id) else {
+                continue;
+            };
+            let carried_candle = candle.to_flat(now);
+
+            if self.is_forming(candle) && carried_candle.get_date() > candle.get_date() {
+                carried_candles.push(carried_candle);
+            }
+        }
+
+        if !carried_candles.is_empty() {
+            for candle in carried_candles {
+                self.open_candle(candle.get_id(), candle);
+            }
+
+            self.enforce_budget();
+        }
+
+        expired_ids
+    }
+
+    /// Evicts expired candles of the owner and interval or of all of them.
+    /// Returns evicted candles count per interval
+    pub fn evict_expired(
+        &mut self,
+        key: Option<(Symbol, CandleInterval)>,
+    ) -> AHashMap<CandleInterval, usize> {
+        let keys = match key {
+            Some(key) => vec![key],
+            None => self.retention_index.get_keys(),
+        };
+        let mut removed_counts = AHashMap::new();
+
+        for (owner, interval) in keys {
+            let Some(policy) = self.retention_policies.get(&interval) else {
+                continue;
+            };
+
+            for date in self.retention_index.take_expired(owner, interval, policy) {
+                let key = CandleKey::new(owner, interval, interval.get_start_date(date));
+
+                if let Some(id) = self.ids_by_keys.remove(&key) {
+                    if self.remove_candle(&id).is_some() {
+                        *removed_counts.entry(interval).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        removed_counts
+    }
+
+    /// Evicts candles until the budget is satisfied. Returns evicted candles count
+    pub fn enforce_budget(&mut self) -> usize {
+        let Some(budget) = self.budget.as_ref() else {
+            return 0;
+        };
+
+        if !budget.is_exceeded(self.candles_by_ids.len(), self.memory_usage) {
+            return 0;
+        }
+
+        let candidates = self
+            .candles_by_ids
+            .iter()
+            .map(|(id, candle)| EvictionCandidate {
+                key: id.as_str(),
+                owner: candle.get_owner().as_str(),
+                interval: candle.get_interval(),
+                date: candle.get_date(),
+                last_read: self
+                    .read_stamps
+                    .get(id)
+                    .map(|stamp| stamp.load(Ordering::Relaxed))
+                    .unwrap_or_default(),
+                size: get_entry_size::<C>(id),
+            })
+            .collect();
+        let ids: AHashSet<String> = select_evictions(
+            budget,
+            self.candles_by_ids.len(),
+            self.memory_usage,
+            candidates,
+        )
+        .into_iter()
+        .map(|id| id.to_owned())
+        .collect();
+
+        if ids.is_empty() {
+            return 0;
+        }
+
+        self.remove_where(|id, _candle| ids.contains(id))
+            .values()
+            .sum()
+    }
+
+    /// Gets candles with date bigger or equals the interval start date of the specified date
+    /// which match the predicate
+    pub fn get_after(
+        &self,
+        datetime: DateTime<Utc>,
+        predicate: impl Fn(&C) -> bool,
+    ) -> Option<Vec<&C>> {
+        if self.candles_by_ids.is_empty() {
+            return None;
+        }
+
+        let candles = self
+            .candles_by_ids
+            .iter()
+            .filter(|(id, candle)| {
+                if !predicate(candle) {
+                    return false;
+                }
+
+                if candle.get_date() >= candle.get_interval().get_start_date(datetime) {
+                    self.mark_read(id);
+                    true
+                } else {
+                    false
+                }
+            })
+            .map(|(_id, candle)| candle)
+            .collect();
+
+        Some(candles)
+    }
+
+    pub fn get_range(
+        &self,
+        owner: &str,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+    ) -> Vec<&C> {
+        self.get_range_filled(owner, interval, start_date, end_date, GapFill::Leave)
+            .into_iter()
+            .filter_map(|candle| match candle {
+                RangeCandle::Cached(candle) => Some(candle),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_range_filled(
+        &self,
+        owner: &str,
+        interval: CandleInterval,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        fill: GapFill,
+    ) -> Vec<RangeCandle<'_, C>> {
+        let symbol = SymbolRegistry::get(owner);
+
+        get_range_filled(interval, start_date, end_date, fill, |date| {
+            let id = self
+                .ids_by_keys
+                .get(&CandleKey::new(symbol?, interval, date))?;
+
+            self.get(id)
+        })
+    }
+
+    /// Removes candles with date less or equals the interval start date of the specified date.
+    /// Candles of all intervals are removed only if they match the predicate
+    pub fn remove_before(
+        &mut self,
+        datetime: DateTime<Utc>,
+        interval: Option<CandleInterval>,
+        predicate: impl Fn(&C) -> bool,
+    ) -> i32 {
+        let removed_counts = if let Some(interval) = interval {
+            let current_date = interval.get_start_date(datetime);
+
+            self.remove_where(|_id, candle| {
+                candle.get_date() <= current_date && candle.get_interval() == interval
+            })
+        } else {
+            self.remove_where(|_id, candle| {
+                predicate(candle)
+                    && candle.get_date() <= candle.get_interval().get_start_date(datetime)
+            })
+        };
+
+        removed_counts.values().sum::<usize>() as i32
+    }
+
+    /// Removes all candles and forming dates
+    pub fn clear(&mut self) {
+        self.remove_where(|_id, _candle| true);
+        self.forming_dates.clear();
+    }
+
+    /// Removes forming dates and candles of intervals not used by their owners.
+    /// Returns removed candles count per interval
+    pub fn remove_unused(
+        &mut self,
+        is_used: impl Fn(Symbol, CandleInterval) -> bool,
+    ) -> AHashMap<CandleInterval, usize> {
+        for (owner, dates) in self.forming_dates.iter_mut() {
+            dates.retain(|interval, _| is_used(*owner, *interval));
+        }
+
+        self.remove_where(|_id, candle| !is_used(candle.get_owner(), candle.get_interval()))
+    }
+
+    /// Stable checksum of all candles and their closed state which doesn't depend
+    /// on insertion order. Data of candles is written by the specified function
+    pub fn get_checksum(&self, write_candle: impl Fn(&mut ChecksumHasher, &C)) -> u64 {
+        let mut ids: Vec<&String> = self.candles_by_ids.keys().collect();
+        ids.sort();
+        let mut hasher = ChecksumHasher::default();
+
+        for id in ids {
+            let candle = &self.candles_by_ids[id];
+            hasher.write_str(&candle.get_owner());
+            hasher.write(&(candle.get_interval() as i32).to_le_bytes());
+            hasher.write_date(candle.get_date());
+            hasher.write(&[self.closed_ids.contains(id) as u8]);
+            write_candle(&mut hasher, candle);
+        }
+
+        hasher.finish()
+    }
+
+    fn mark_read(&self, id: &str) {
+        if let Some(stamp) = self.read_stamps.get(id) {
+            let clock = self.read_clock.fetch_add(1, Ordering::Relaxed);
+            stamp.store(clock, Ordering::Relaxed);
+        }
+    }
+
+    /// Inserts a new candle and notifies listeners. The forming candle superseded by it is
+    /// reported as closed but is still updated by late data until `close_expired`
+    fn open_candle(&mut self, id: String, candle: C) {
+        let superseded_key = self.update_forming_date(&candle).map(|date| {
+            let interval = candle.get_interval();
+
+            CandleKey::new(candle.get_owner(), interval, date)
+        });
+        self.insert_candle(id.clone(), candle);
+
+        if let Some(superseded_id) = superseded_key.and_then(|key| self.ids_by_keys.get(&key)) {
+            if let Some(superseded) = self.candles_by_ids.get(superseded_id) {
+                if !self.closed_ids.contains(superseded_id) {
+                    notify(
+                        &mut self.listeners,
+                        CandleEventKind::Closed,
+                        superseded.get_interval(),
+                        superseded,
+                    );
+                }
+            }
+        }
+
+        if let Some(candle) = self.candles_by_ids.get(&id) {
+            notify(
+                &mut self.listeners,
+                CandleEventKind::Opened,
+                candle.get_interval(),
+                candle,
+            );
+        }
+    }
+
+    /// Finalizes the candle and notifies listeners if it is still forming. Returns false
+    /// if the candle is not found or is already finalized
+    fn close_candle(&mut self, id: &str) -> bool {
+        if self.closed_ids.contains(id) {
+            return false;
+        }
+
+        let Some(candle) = self.candles_by_ids.get(id) else {
+            return false;
+        };
+
+        self.closed_ids.insert(id.to_owned());
+
+        if self.is_forming(candle) {
+            notify(
+                &mut self.listeners,
+                CandleEventKind::Closed,
+                candle.get_interval(),
+                candle,
+            );
+        }
+
+        true
+    }
+
+    fn is_forming(&self, candle: &C) -> bool {
+        self.forming_dates
+            .get(&candle.get_owner())
+            .and_then(|dates| dates.get(&candle.get_interval()))
+            == Some(&candle.get_date())
+    }
+
+    /// Sets date of the forming candle and returns date of the superseded one
+    fn update_forming_date(&mut self, candle: &C) -> Option<DateTime<Utc>> {
+        let dates = self.forming_dates.entry(candle.get_owner()).or_default();
+        let date = candle.get_date();
+
+        match dates.get_mut(&candle.get_interval()) {
+            Some(forming_date) if *forming_date < date => {
+                Some(std::mem::replace(forming_date, date))
+            }
+            Some(_) => None,
+            None => {
+                dates.insert(candle.get_interval(), date);
+                None
+            }
+        }
+    }
+
+    fn insert_candle(&mut self, id: String, candle: C) {
+        self.memory_usage += get_entry_size::<C>(&id);
+        self.ids_by_keys.insert(get_key(&candle), id.clone());
+
+        if self.retention_policies.contains_key(&candle.get_interval()) {
+            self.retention_index.insert(
+                candle.get_owner(),
+                candle.get_interval(),
+                candle.get_date(),
+            );
+        }
+
+        if let Some(changes) = self.changes.as_mut() {
+            changes.mark_changed(id.as_str());
+        }
+
+        if let Some(CacheBudget {
+            strategy: EvictionStrategy::LeastRecentlyRead,
+            ..
+        }) = self.budget
+        {
+            let stamp = self.read_clock.fetch_add(1, Ordering::Relaxed);
+            self.read_stamps.insert(id.clone(), AtomicU64::new(stamp));
+        }
+
+        if let Some(replaced) = self.candles_by_ids.insert(id, candle) {
+            let replaced_size = get_entry_size::<C>(&replaced.get_id());
+            self.memory_usage = self.memory_usage.saturating_sub(replaced_size);
+        }
+    }
+
+    fn remove_candle(&mut self, id: &str) -> Option<C> {
+        let candle = self.candles_by_ids.remove(id)?;
+        self.memory_usage = self.memory_usage.saturating_sub(get_entry_size::<C>(id));
+        self.read_stamps.remove(id);
+        self.closed_ids.remove(id);
+        self.ids_by_keys.remove(&get_key(&candle));
+        self.retention_index
+            .remove(candle.get_owner(), candle.get_interval(), candle.get_date());
+
+        if let Some(changes) = self.changes.as_mut() {
+            changes.remove(id);
+        }
+
+        Some(candle)
+    }
+
+    /// Removes candles matching the predicate. Returns removed candles count per interval
+    pub fn remove_where(
+        &mut self,
+        mut predicate: impl FnMut(&str, &C) -> bool,
+    ) -> AHashMap<CandleInterval, usize> {
+        let mut removed_counts = AHashMap::new();
+        let mut memory_usage = self.memory_usage;
+        let read_stamps = &mut self.read_stamps;
+        let closed_ids = &mut self.closed_ids;
+        let ids_by_keys = &mut self.ids_by_keys;
+        let retention_index = &mut self.retention_index;
+        let mut changes = self.changes.as_mut();
+
+        self.candles_by_ids.retain(|id, candle| {
+            if predicate(id, candle) {
+                *removed_counts.entry(candle.get_interval()).or_insert(0) += 1;
+                memory_usage = memory_usage.saturating_sub(get_entry_size::<C>(id));
+                read_stamps.remove(id);
+                closed_ids.remove(id);
+                ids_by_keys.remove(&get_key(candle));
+                retention_index.remove(
+                    candle.get_owner(),
+                    candle.get_interval(),
+                    candle.get_date(),
+                );
+
+                if let Some(changes) = changes.as_mut() {
+                    changes.remove(id.as_str());
+                }
+
+                false
+            } else {
+                true
+            }
+        });
+
+        self.memory_usage = memory_usage;
+
+        removed_counts
+    }
+}
+
+fn get_key<C: StoredCandle>(candle: &C) -> CandleKey {
+    let interval = candle.get_interval();
+
+    CandleKey::new(
+        candle.get_owner(),
+        interval,
+        interval.get_start_date(candle.get_date()),
+    )
+}
+
+fn get_entry_size<C>(id: &str) -> usize {
+    // one control byte per hash map entry, the id is stored by the candles and the keys maps
+    std::mem::size_of::<(String, C)>()
+        + std::mem::size_of::<(CandleKey, String)>()
+        + 2
+        + id.len() * 2
+}
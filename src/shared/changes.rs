@@ -0,0 +1,117 @@
+use ahash::AHashMap;
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeSet<K> {
+    /// Version of the latest change included into the set
+    pub version: u64,
+    pub keys: Vec<K>,
+}
+
+/// Tracks keys of created or modified candles with monotonically increasing versions
+#[derive(Debug, Clone)]
+pub struct ChangeTracker<K> {
+    version: u64,
+    versions_by_keys: AHashMap<K, u64>,
+}
+
+impl<K: Hash + Eq + Clone> Default for ChangeTracker<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone> ChangeTracker<K> {
+    pub fn new() -> Self {
+        Self {
+            version: 0,
+            versions_by_keys: AHashMap::new(),
+        }
+    }
+
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn len(&self) -> usize {
+        self.versions_by_keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions_by_keys.is_empty()
+    }
+
+    pub fn mark_changed<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.version += 1;
+
+        if let Some(version) = self.versions_by_keys.get_mut(key) {
+            *version = self.version;
+        } else {
+            self.versions_by_keys.insert(key.to_owned(), self.version);
+        }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.versions_by_keys.remove(key);
+    }
+
+    /// Gets keys changed after the specified version
+    pub fn get_changed_since(&self, version: u64) -> impl Iterator<Item = &K> {
+        self.versions_by_keys
+            .iter()
+            .filter(move |(_key, key_version)| **key_version > version)
+            .map(|(key, _version)| key)
+    }
+
+    /// Takes all tracked keys and starts tracking from the current version
+    pub fn take(&mut self) -> ChangeSet<K> {
+        ChangeSet {
+            version: self.version,
+            keys: self.versions_by_keys.drain().map(|(key, _)| key).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shared::changes::ChangeTracker;
+
+    #[test]
+    fn get_changed_since() {
+        let mut tracker: ChangeTracker<String> = ChangeTracker::new();
+        tracker.mark_changed("a");
+        tracker.mark_changed("b");
+        let version = tracker.get_version();
+        tracker.mark_changed("a");
+
+        let keys: Vec<_> = tracker.get_changed_since(version).collect();
+
+        assert_eq!(version, 2);
+        assert_eq!(tracker.get_version(), 3);
+        assert_eq!(keys, vec!["a"]);
+    }
+
+    #[test]
+    fn take() {
+        let mut tracker: ChangeTracker<String> = ChangeTracker::new();
+        tracker.mark_changed("a");
+        tracker.mark_changed("b");
+        tracker.mark_changed("a");
+
+        let changes = tracker.take();
+
+        assert_eq!(changes.version, 3);
+        assert_eq!(changes.keys.len(), 2);
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.get_version(), 3);
+    }
+}
@@ -0,0 +1,144 @@
+use ::arrow::array::{
+    Array, Float64Array, Int32Array, StringArray, TimestampNanosecondArray, UInt64Array,
+};
+use ::arrow::datatypes::{DataType, Field, TimeUnit};
+use ::arrow::error::ArrowError;
+use ::arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Utc};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use parquet::file::reader::ChunkReader;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+#[derive(Debug)]
+pub enum ColumnarError {
+    Arrow(ArrowError),
+    Parquet(ParquetError),
+    /// Batch does not match the candles schema
+    Schema(String),
+}
+
+impl Display for ColumnarError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColumnarError::Arrow(error) => write!(f, "arrow error: {error}"),
+            ColumnarError::Parquet(error) => write!(f, "parquet error: {error}"),
+            ColumnarError::Schema(message) => write!(f, "schema error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ColumnarError {}
+
+impl From<ArrowError> for ColumnarError {
+    fn from(error: ArrowError) -> Self {
+        ColumnarError::Arrow(error)
+    }
+}
+
+impl From<ParquetError> for ColumnarError {
+    fn from(error: ParquetError) -> Self {
+        ColumnarError::Parquet(error)
+    }
+}
+
+/// Writes the batch as a single parquet file
+pub fn write_parquet(writer: impl Write + Send, batch: &RecordBatch) -> Result<(), ColumnarError> {
+    let mut writer = ArrowWriter::try_new(writer, batch.schema(), None)?;
+    writer.write(batch)?;
+    writer.close()?;
+
+    Ok(())
+}
+
+pub fn read_parquet(reader: impl ChunkReader + 'static) -> Result<Vec<RecordBatch>, ColumnarError> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(reader)?.build()?;
+
+    Ok(reader.collect::<Result<Vec<_>, _>>()?)
+}
+
+pub(crate) fn get_timestamp_field(name: &str) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+        false,
+    )
+}
+
+pub(crate) fn to_timestamp_array(
+    name: &str,
+    dates: impl Iterator<Item = DateTime<Utc>>,
+) -> Result<TimestampNanosecondArray, ColumnarError> {
+    let nanos = dates
+        .map(|date| {
+            date.timestamp_nanos_opt().ok_or_else(|| {
+                ColumnarError::Schema(format!("{name} {date} is out of nanoseconds range"))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(TimestampNanosecondArray::from(nanos).with_timezone("UTC"))
+}
+
+fn get_column<'a, A: Array + 'static>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a A, ColumnarError> {
+    let column = batch
+        .column_by_name(name)
+        .ok_or_else(|| ColumnarError::Schema(format!("column {name} is missing")))?;
+
+    if column.null_count() > 0 {
+        return Err(ColumnarError::Schema(format!(
+            "column {name} contains nulls"
+        )));
+    }
+
+    column
+        .as_any()
+        .downcast_ref::<A>()
+        .ok_or_else(|| ColumnarError::Schema(format!("column {name} has wrong type")))
+}
+
+pub(crate) fn get_f64_column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a Float64Array, ColumnarError> {
+    get_column(batch, name)
+}
+
+pub(crate) fn get_i32_column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a Int32Array, ColumnarError> {
+    get_column(batch, name)
+}
+
+pub(crate) fn get_u64_column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a UInt64Array, ColumnarError> {
+    get_column(batch, name)
+}
+
+pub(crate) fn get_str_column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a StringArray, ColumnarError> {
+    get_column(batch, name)
+}
+
+pub(crate) fn get_date_column(
+    batch: &RecordBatch,
+    name: &str,
+) -> Result<Vec<DateTime<Utc>>, ColumnarError> {
+    let column: &TimestampNanosecondArray = get_column(batch, name)?;
+
+    Ok(column
+        .values()
+        .iter()
+        .map(|nanos| DateTime::from_timestamp_nanos(*nanos))
+        .collect())
+}
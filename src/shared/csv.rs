@@ -0,0 +1,330 @@
+use crate::shared::candle_interval::CandleInterval;
+use ahash::AHashMap;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// `2000-01-01T00:00:00Z`
+    Rfc3339,
+    UnixSeconds,
+    UnixMillis,
+    /// chrono format string of UTC date time or date, e.g. `%Y-%m-%d %H:%M:%S`
+    Custom(String),
+}
+
+impl TimestampFormat {
+    pub fn format(&self, date: DateTime<Utc>) -> String {
+        match self {
+            TimestampFormat::Rfc3339 => date.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            TimestampFormat::UnixSeconds => date.timestamp().to_string(),
+            TimestampFormat::UnixMillis => date.timestamp_millis().to_string(),
+            TimestampFormat::Custom(format) => date.format(format).to_string(),
+        }
+    }
+
+    pub fn parse(&self, value: &str) -> Result<DateTime<Utc>, String> {
+        let date = match self {
+            TimestampFormat::Rfc3339 => DateTime::parse_from_rfc3339(value)
+                .map(|date| date.with_timezone(&Utc))
+                .ok(),
+            TimestampFormat::UnixSeconds => value.parse::<f64>().ok().and_then(|seconds| {
+                Utc.timestamp_millis_opt((seconds * 1000.0).round() as i64)
+                    .single()
+            }),
+            TimestampFormat::UnixMillis => value
+                .parse::<i64>()
+                .ok()
+                .and_then(|millis| Utc.timestamp_millis_opt(millis).single()),
+            TimestampFormat::Custom(format) => NaiveDateTime::parse_from_str(value, format)
+                .ok()
+                .or_else(|| {
+                    NaiveDate::parse_from_str(value, format)
+                        .ok()
+                        .and_then(|date| date.and_hms_opt(0, 0, 0))
+                })
+                .map(|date| date.and_utc()),
+        };
+
+        date.ok_or_else(|| format!("invalid timestamp '{value}'"))
+    }
+}
+
+/// Source column of a field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvColumn {
+    Name(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvWriteOptions {
+    pub timestamp_format: TimestampFormat,
+    pub delimiter: u8,
+    pub has_headers: bool,
+}
+
+impl Default for CsvWriteOptions {
+    fn default() -> Self {
+        Self {
+            timestamp_format: TimestampFormat::Rfc3339,
+            delimiter: b',',
+            has_headers: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvReadOptions {
+    pub timestamp_format: TimestampFormat,
+    pub delimiter: u8,
+    /// None detects the header by field or mapped column names in the first row
+    pub has_headers: Option<bool>,
+    /// Source columns by field names. Not mapped fields are read from the columns with
+    /// the field name or, without the header, from the columns in the written order
+    pub columns: AHashMap<String, CsvColumn>,
+    /// Interval of the candles if the interval column is missing
+    pub interval: Option<CandleInterval>,
+    /// Instrument or ref_id of the candles if the column is missing
+    pub owner: Option<String>,
+}
+
+impl Default for CsvReadOptions {
+    fn default() -> Self {
+        Self {
+            timestamp_format: TimestampFormat::Rfc3339,
+            delimiter: b',',
+            has_headers: None,
+            columns: AHashMap::new(),
+            interval: None,
+            owner: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvRowError {
+    /// 1-based line number in the source
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CsvImport {
+    pub imported_count: usize,
+    pub errors: Vec<CsvRowError>,
+}
+
+#[derive(Debug)]
+pub enum CsvError {
+    Csv(::csv::Error),
+    /// Required field has no source column
+    MissingColumn(String),
+}
+
+impl Display for CsvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CsvError::Csv(error) => write!(f, "csv error: {error}"),
+            CsvError::MissingColumn(field) => write!(f, "csv column of '{field}' is missing"),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+impl From<::csv::Error> for CsvError {
+    fn from(error: ::csv::Error) -> Self {
+        CsvError::Csv(error)
+    }
+}
+
+impl From<std::io::Error> for CsvError {
+    fn from(error: std::io::Error) -> Self {
+        CsvError::Csv(error.into())
+    }
+}
+
+pub(crate) fn create_writer<W: std::io::Write>(
+    writer: W,
+    options: &CsvWriteOptions,
+) -> ::csv::Writer<W> {
+    ::csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(false)
+        .from_writer(writer)
+}
+
+/// Reads rows of the fields. Row errors are collected, the import stops only on
+/// malformed csv or missing required columns
+pub(crate) fn read_rows<R: std::io::Read>(
+    reader: R,
+    options: &CsvReadOptions,
+    fields: &[&str],
+    required_fields: &[&str],
+    mut read_row: impl FnMut(&CsvRow) -> Result<(), String>,
+) -> Result<CsvImport, CsvError> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .trim(::csv::Trim::All)
+        .from_reader(reader);
+    let mut records = reader.records();
+    let mut import = CsvImport::default();
+    let mut pending_record = None;
+
+    let first_record = match records.next() {
+        Some(record) => record?,
+        None => return Ok(import),
+    };
+    let has_headers = options
+        .has_headers
+        .unwrap_or_else(|| is_header(&first_record, options, fields));
+    let mut column_indexes = AHashMap::new();
+
+    for (field_no, field) in fields.iter().enumerate() {
+        let index = match options.columns.get(*field) {
+            Some(CsvColumn::Index(index)) => Some(*index),
+            Some(CsvColumn::Name(name)) if has_headers => {
+                first_record.iter().position(|value| value == name)
+            }
+            Some(CsvColumn::Name(_)) => None,
+            None if has_headers => first_record.iter().position(|value| value == *field),
+            None => Some(field_no),
+        };
+
+        match index {
+            Some(index) => {
+                column_indexes.insert(field.to_string(), index);
+            }
+            None if required_fields.contains(field) => {
+                return Err(CsvError::MissingColumn(field.to_string()));
+            }
+            None => {}
+        }
+    }
+
+    if !has_headers {
+        pending_record = Some(first_record);
+    }
+
+    let mut row = CsvRow {
+        record: ::csv::StringRecord::new(),
+        column_indexes,
+        timestamp_format: &options.timestamp_format,
+    };
+
+    while let Some(record) = pending_record.take().map(Ok).or_else(|| records.next()) {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => match error.kind() {
+                ::csv::ErrorKind::Utf8 { .. } => {
+                    import.errors.push(CsvRowError {
+                        line: error.position().map(|p| p.line()).unwrap_or_default(),
+                        message: error.to_string(),
+                    });
+                    continue;
+                }
+                _ => return Err(error.into()),
+            },
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+
+        if record.iter().all(|value| value.is_empty()) {
+            continue;
+        }
+
+        row.record = record;
+
+        match read_row(&row) {
+            Ok(()) => import.imported_count += 1,
+            Err(message) => import.errors.push(CsvRowError { line, message }),
+        }
+    }
+
+    Ok(import)
+}
+
+fn is_header(record: &::csv::StringRecord, options: &CsvReadOptions, fields: &[&str]) -> bool {
+    record.iter().any(|value| {
+        fields.contains(&value)
+            || options
+                .columns
+                .values()
+                .any(|column| matches!(column, CsvColumn::Name(name) if name == value))
+    })
+}
+
+pub(crate) struct CsvRow<'a> {
+    record: ::csv::StringRecord,
+    column_indexes: AHashMap<String, usize>,
+    timestamp_format: &'a TimestampFormat,
+}
+
+impl CsvRow<'_> {
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.column_indexes
+            .get(field)
+            .and_then(|index| self.record.get(*index))
+            .filter(|value| !value.is_empty())
+    }
+
+    pub fn get_str(&self, field: &str) -> Result<&str, String> {
+        self.get(field).ok_or_else(|| format!("{field} is empty"))
+    }
+
+    pub fn get_f64(&self, field: &str) -> Result<f64, String> {
+        self.get_str(field)?
+            .parse()
+            .map_err(|_| format!("{field} is not a number"))
+    }
+
+    pub fn get_f64_or(&self, field: &str, default: f64) -> Result<f64, String> {
+        match self.get(field) {
+            Some(_) => self.get_f64(field),
+            None => Ok(default),
+        }
+    }
+
+    pub fn get_date(&self, field: &str) -> Result<DateTime<Utc>, String> {
+        self.timestamp_format
+            .parse(self.get_str(field)?)
+            .map_err(|error| format!("{field}: {error}"))
+    }
+
+    pub fn get_interval(&self, field: &str) -> Result<CandleInterval, String> {
+        let value: i32 = self
+            .get_str(field)?
+            .parse()
+            .map_err(|_| format!("{field} is not a number"))?;
+
+        CandleInterval::try_from(value).map_err(|_| format!("{field} {value} is unknown"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn timestamp_format_round_trip() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 2, 3, 4, 5).unwrap();
+        let formats = [
+            TimestampFormat::Rfc3339,
+            TimestampFormat::UnixSeconds,
+            TimestampFormat::UnixMillis,
+            TimestampFormat::Custom("%Y-%m-%d %H:%M:%S".to_string()),
+        ];
+
+        for format in formats {
+            assert_eq!(format.parse(&format.format(date)), Ok(date));
+        }
+
+        assert_eq!(
+            TimestampFormat::Custom("%d.%m.%Y".to_string()).parse("02.01.2000"),
+            Ok(Utc.with_ymd_and_hms(2000, 1, 2, 0, 0, 0).unwrap())
+        );
+        assert!(TimestampFormat::Rfc3339.parse("2000-01-02").is_err());
+    }
+}
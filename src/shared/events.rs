@@ -0,0 +1,54 @@
+use crate::shared::candle_interval::CandleInterval;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CandleEventKind {
+    /// A new candle was created
+    Opened,
+    /// An existing candle was updated
+    Updated,
+    /// A newer candle of the same interval was opened or the forming candle was finalized
+    /// by `close_expired`. Candles superseded by a newer one are still updated by late data
+    /// until they are finalized
+    Closed,
+}
+
+#[derive(Debug, Clone)]
+pub struct CandleEvent<C> {
+    pub kind: CandleEventKind,
+    pub interval: CandleInterval,
+    /// Snapshot of the candle after the event
+    pub candle: C,
+}
+
+pub trait CandleListener<C>: Send + Sync {
+    fn on_event(&mut self, event: &CandleEvent<C>);
+}
+
+/// Forwards events to the channel receiver
+impl<C: Clone + Send + Sync> CandleListener<C> for std::sync::mpsc::Sender<CandleEvent<C>> {
+    fn on_event(&mut self, event: &CandleEvent<C>) {
+        // the receiver may be dropped, events are not required to be delivered in that case
+        let _ = self.send(event.clone());
+    }
+}
+
+pub(crate) fn notify<C: Clone>(
+    listeners: &mut [Box<dyn CandleListener<C>>],
+    kind: CandleEventKind,
+    interval: CandleInterval,
+    candle: &C,
+) {
+    if listeners.is_empty() {
+        return;
+    }
+
+    let event = CandleEvent {
+        kind,
+        interval,
+        candle: candle.clone(),
+    };
+
+    for listener in listeners.iter_mut() {
+        listener.on_event(&event);
+    }
+}
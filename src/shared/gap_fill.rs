@@ -0,0 +1,167 @@
+use crate::shared::candle_interval::CandleInterval;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapFill {
+    /// Missing candles are skipped
+    Leave,
+    /// Missing candles are replaced with flat synthetic candles carrying forward the previous
+    /// close. Gaps before the first cached candle of the range are skipped
+    CarryForward,
+    /// Missing candles are replaced with placeholders
+    Placeholder,
+}
+
+#[derive(Debug, Clone)]
+pub enum RangeCandle<'a, C> {
+    Cached(&'a C),
+    Synthetic(C),
+    Missing(DateTime<Utc>),
+}
+
+impl<C> RangeCandle<'_, C> {
+    pub fn get_candle(&self) -> Option<&C> {
+        match self {
+            RangeCandle::Cached(candle) => Some(candle),
+            RangeCandle::Synthetic(candle) => Some(candle),
+            RangeCandle::Missing(_) => None,
+        }
+    }
+
+    pub fn is_synthetic(&self) -> bool {
+        matches!(self, RangeCandle::Synthetic(_))
+    }
+}
+
+pub trait FlatCandle: Sized {
+    /// Creates a flat candle at the specified date with the close values of this candle
+    fn to_flat(&self, date: DateTime<Utc>) -> Self;
+}
+
+/// Collects candles of every interval date in the specified range
+pub fn get_range_filled<'a, C: FlatCandle>(
+    interval: CandleInterval,
+    start_date: DateTime<Utc>,
+    end_date: DateTime<Utc>,
+    fill: GapFill,
+    get_candle: impl Fn(DateTime<Utc>) -> Option<&'a C>,
+) -> Vec<RangeCandle<'a, C>> {
+    let mut candles = Vec::new();
+    let mut last_candle: Option<&C> = None;
+    let mut cursor_date = interval.get_start_date(start_date);
+    let end_date = interval.get_start_date(end_date);
+
+    while cursor_date <= end_date {
+        if let Some(candle) = get_candle(cursor_date) {
+            candles.push(RangeCandle::Cached(candle));
+            last_candle = Some(candle);
+        } else {
+            match fill {
+                GapFill::Leave => {}
+                GapFill::CarryForward => {
+                    if let Some(last_candle) = last_candle {
+                        candles.push(RangeCandle::Synthetic(last_candle.to_flat(cursor_date)));
+                    }
+                }
+                GapFill::Placeholder => candles.push(RangeCandle::Missing(cursor_date)),
+            }
+        }
+
+        let Some(next_date) = interval.get_nth_date(cursor_date, 1) else {
+            break;
+        };
+        cursor_date = next_date;
+    }
+
+    candles
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shared::candle_interval::CandleInterval;
+    use crate::shared::gap_fill::{get_range_filled, FlatCandle, GapFill, RangeCandle};
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    #[derive(Debug, PartialEq)]
+    struct TestCandle {
+        date: DateTime<Utc>,
+        close: f64,
+    }
+
+    impl FlatCandle for TestCandle {
+        fn to_flat(&self, date: DateTime<Utc>) -> Self {
+            Self {
+                date,
+                close: self.close,
+            }
+        }
+    }
+
+    #[test]
+    fn get_range_filled_month() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let march: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 3, 1, 0, 0, 0).unwrap();
+        let candles = [TestCandle { date, close: 1.0 }];
+
+        let range = get_range_filled(
+            CandleInterval::Month,
+            date,
+            march,
+            GapFill::CarryForward,
+            |date| candles.iter().find(|c| c.date == date),
+        );
+
+        assert_eq!(range.len(), 3);
+        assert!(range[1].is_synthetic());
+        assert_eq!(range[2].get_candle().unwrap().date, march);
+        assert_eq!(range[2].get_candle().unwrap().close, 1.0);
+    }
+
+    #[test]
+    fn get_range_filled_three_and_seven_days() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 10, 0, 0, 0).unwrap();
+
+        for interval in [CandleInterval::ThreeDays, CandleInterval::SevenDays] {
+            let start_date = interval.get_start_date(date);
+            let candles: Vec<_> = (0..3)
+                .map(|n| TestCandle {
+                    date: interval.get_nth_date(start_date, n).unwrap(),
+                    close: n as f64,
+                })
+                .collect();
+
+            let range = get_range_filled(
+                interval,
+                start_date,
+                candles[2].date,
+                GapFill::Placeholder,
+                |date| candles.iter().find(|c| c.date == date),
+            );
+            let range_candles: Vec<_> = range.iter().filter_map(|c| c.get_candle()).collect();
+
+            assert_eq!(range_candles, candles.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn get_range_filled_placeholder() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let candles = [TestCandle {
+            date: date + Duration::minutes(1),
+            close: 1.0,
+        }];
+
+        let range = get_range_filled(
+            CandleInterval::Minute,
+            date,
+            date + Duration::minutes(2),
+            GapFill::Placeholder,
+            |date| candles.iter().find(|c| c.date == date),
+        );
+
+        assert_eq!(range.len(), 3);
+        assert!(matches!(range[0], RangeCandle::Missing(missing_date) if missing_date == date));
+        assert!(matches!(range[1], RangeCandle::Cached(_)));
+        assert!(range[2].get_candle().is_none());
+    }
+}
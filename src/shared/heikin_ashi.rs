@@ -0,0 +1,175 @@
+use crate::shared::ohlc::Ohlc;
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeikinAshiCandle {
+    pub date: DateTime<Utc>,
+    pub ohlc: Ohlc,
+}
+
+/// Heikin-Ashi candles of the date-ordered series. The latest candle can be updated
+/// without recalculating the previous ones
+#[derive(Debug, Clone, Default)]
+pub struct HeikinAshi {
+    candles: Vec<HeikinAshiCandle>,
+}
+
+impl HeikinAshi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_series(series: impl IntoIterator<Item = (DateTime<Utc>, Ohlc)>) -> Self {
+        let mut heikin_ashi = Self::new();
+
+        for (date, ohlc) in series {
+            heikin_ashi.update(date, ohlc);
+        }
+
+        heikin_ashi
+    }
+
+    /// Appends the candle of the new date or recalculates the latest candle of the same date.
+    /// Returns None for dates before the latest date
+    pub fn update(&mut self, date: DateTime<Utc>, ohlc: Ohlc) -> Option<&HeikinAshiCandle> {
+        match self.candles.last() {
+            Some(last) if date < last.date => return None,
+            Some(last) if date == last.date => {
+                self.candles.pop();
+            }
+            _ => {}
+        }
+
+        let close = (ohlc.open + ohlc.high + ohlc.low + ohlc.close) / 4.0;
+        let open = match self.candles.last() {
+            Some(previous) => (previous.ohlc.open + previous.ohlc.close) / 2.0,
+            None => (ohlc.open + ohlc.close) / 2.0,
+        };
+        self.candles.push(HeikinAshiCandle {
+            date,
+            ohlc: Ohlc::new(
+                open,
+                ohlc.high.max(open).max(close),
+                ohlc.low.min(open).min(close),
+                close,
+            ),
+        });
+
+        self.candles.last()
+    }
+
+    pub fn get_candles(&self) -> &[HeikinAshiCandle] {
+        &self.candles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::candle::{AccountCandle, AccountData, AccountValue};
+    use crate::prices::candle::{BidAskCandle, PriceSide};
+    use crate::shared::candle_index::CandleIndex;
+    use crate::shared::candle_interval::CandleInterval;
+    use crate::shared::symbols::Symbol;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn update_recalculates_latest_candle() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let series = [
+            Ohlc::new(10.0, 12.0, 9.0, 11.0),
+            Ohlc::new(11.0, 14.0, 10.0, 13.0),
+        ];
+        let mut heikin_ashi = HeikinAshi::from_series(
+            series
+                .iter()
+                .enumerate()
+                .map(|(i, ohlc)| (date + Duration::minutes(i as i64), *ohlc)),
+        );
+
+        assert_eq!(
+            heikin_ashi.get_candles()[0].ohlc,
+            Ohlc::new(10.5, 12.0, 9.0, 10.5)
+        );
+        assert_eq!(
+            heikin_ashi.get_candles()[1].ohlc,
+            Ohlc::new(10.5, 14.0, 10.0, 12.0)
+        );
+
+        let latest = heikin_ashi
+            .update(
+                date + Duration::minutes(1),
+                Ohlc::new(11.0, 15.0, 10.0, 14.0),
+            )
+            .unwrap();
+
+        assert_eq!(latest.ohlc, Ohlc::new(10.5, 15.0, 10.0, 12.5));
+        assert_eq!(heikin_ashi.get_candles().len(), 2);
+        assert!(heikin_ashi
+            .update(date, Ohlc::new(1.0, 1.0, 1.0, 1.0))
+            .is_none());
+    }
+
+    #[test]
+    pub fn candles_of_price_and_account_series() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut price_candles = Vec::new();
+        let mut account_candles = Vec::new();
+
+        for minute in 0..3 {
+            let candle_date = date + Duration::minutes(minute);
+            let mut price_candle = BidAskCandle::new(
+                CandleInterval::Minute,
+                candle_date,
+                Symbol::new("EURUSD"),
+                candle_date,
+                (10.0, 10.5, 1.0, 1.0),
+            );
+            let index = CandleIndex::new("1", CandleInterval::Minute, candle_date);
+            let mut account_candle = AccountCandle::new_at(
+                index,
+                &AccountData {
+                    equity: 10.0,
+                    balance: 10.0,
+                    pnl: 0.0,
+                },
+                candle_date,
+            );
+
+            for (second, value) in [(20, 12.0), (40, 9.0), (59, 11.0 + minute as f64)] {
+                let datetime = candle_date + Duration::seconds(second);
+                price_candle.update(datetime, value, value + 0.5, 1.0, 1.0);
+                let data = AccountData {
+                    equity: value,
+                    balance: 10.0,
+                    pnl: value - 10.0,
+                };
+                account_candle.update_at(&data, datetime);
+            }
+
+            price_candles.push(price_candle);
+            account_candles.push(account_candle);
+        }
+
+        let price_heikin_ashi = HeikinAshi::from_series(
+            price_candles
+                .iter()
+                .map(|candle| (candle.date, candle.get_ohlc(PriceSide::Bid))),
+        );
+        let account_heikin_ashi = HeikinAshi::from_series(
+            account_candles
+                .iter()
+                .map(|candle| (candle.date, candle.get_ohlc(AccountValue::Equity))),
+        );
+
+        assert_eq!(
+            price_heikin_ashi.get_candles(),
+            account_heikin_ashi.get_candles()
+        );
+        assert_eq!(
+            account_heikin_ashi.get_candles()[2].ohlc,
+            Ohlc::new(10.625, 13.0, 9.0, 11.25)
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use crate::shared::ohlc::Ohlc;
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Technical indicator of candle closes. RSI and ATR use Wilder's smoothing and
+/// EMA is seeded with the SMA of the first period
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Indicator {
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+    Atr(usize),
+    /// Bands around the SMA at the multiplier of the population standard deviation
+    Bollinger {
+        period: usize,
+        multiplier: f64,
+    },
+}
+
+impl Indicator {
+    /// Checks that the period is not zero and the Bollinger multiplier is finite
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Indicator::Bollinger { multiplier, .. } if !multiplier.is_finite() => false,
+            _ => get_period(self) > 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum IndicatorValue {
+    Single(f64),
+    Bands { lower: f64, middle: f64, upper: f64 },
+}
+
+/// Indicator values of the date-ordered candle series. Values of closed candles are
+/// calculated once, the value of the forming candle is recalculated on every update
+/// from the state of the closed candles
+#[derive(Debug, Clone)]
+pub struct IndicatorSeries {
+    indicator: Indicator,
+    state: IndicatorState,
+    closed_values: Vec<(DateTime<Utc>, IndicatorValue)>,
+    last_closed_date: Option<DateTime<Utc>>,
+    forming: Option<(DateTime<Utc>, Ohlc, Option<IndicatorValue>)>,
+}
+
+impl IndicatorSeries {
+    /// Returns None if the indicator is not valid
+    pub fn new(indicator: Indicator) -> Option<Self> {
+        if !indicator.is_valid() {
+            return None;
+        }
+
+        Some(Self {
+            indicator,
+            state: IndicatorState::new(&indicator),
+            closed_values: Vec::new(),
+            last_closed_date: None,
+            forming: None,
+        })
+    }
+
+    pub fn get_indicator(&self) -> &Indicator {
+        &self.indicator
+    }
+
+    /// Recalculates the value of the forming candle. Returns false for dates of closed candles
+    pub fn update(&mut self, date: DateTime<Utc>, ohlc: Ohlc) -> bool {
+        if self
+            .last_closed_date
+            .is_some_and(|closed_date| date <= closed_date)
+        {
+            return false;
+        }
+
+        let value = self.state.peek(&self.indicator, &ohlc);
+        self.forming = Some((date, ohlc, value));
+
+        true
+    }
+
+    /// Adds the closed candle to the indicator state. Returns false for dates of closed candles
+    pub fn close(&mut self, date: DateTime<Utc>, ohlc: Ohlc) -> bool {
+        if self
+            .last_closed_date
+            .is_some_and(|closed_date| date <= closed_date)
+        {
+            return false;
+        }
+
+        if let Some(value) = self.state.push(&self.indicator, &ohlc) {
+            self.closed_values.push((date, value));
+        }
+
+        self.last_closed_date = Some(date);
+
+        match self.forming {
+            Some((forming_date, _, _)) if forming_date <= date => self.forming = None,
+            Some((forming_date, forming_ohlc, _)) => {
+                self.update(forming_date, forming_ohlc);
+            }
+            None => {}
+        }
+
+        true
+    }
+
+    /// Gets the date and the value of the forming candle, the value is None until
+    /// the indicator period is filled
+    pub fn get_forming(&self) -> Option<(DateTime<Utc>, Option<IndicatorValue>)> {
+        self.forming.map(|(date, _, value)| (date, value))
+    }
+
+    /// Gets dates and values of closed candles after the indicator period is filled
+    pub fn get_closed(&self) -> &[(DateTime<Utc>, IndicatorValue)] {
+        &self.closed_values
+    }
+
+    pub fn get_last_closed(&self) -> Option<&(DateTime<Utc>, IndicatorValue)> {
+        self.closed_values.last()
+    }
+
+    /// Removes values of closed candles before the date, the indicator state is kept
+    pub fn remove_before(&mut self, date: DateTime<Utc>) {
+        self.closed_values
+            .retain(|(value_date, _)| *value_date >= date);
+    }
+}
+
+#[derive(Debug, Clone)]
+enum IndicatorState {
+    /// Last closes of the period and their sum
+    Window { closes: VecDeque<f64>, sum: f64 },
+    /// Moving average of the period seeded with the sum of the first values
+    Smoothed {
+        prev_close: Option<f64>,
+        smoothing: Smoothing,
+    },
+    /// Wilder's averages of gains and losses of closes
+    Rsi {
+        prev_close: Option<f64>,
+        gains: Smoothing,
+        losses: Smoothing,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Smoothing {
+    count: usize,
+    seed_sum: f64,
+    average: Option<f64>,
+}
+
+impl Smoothing {
+    fn peek(&self, period: usize, value: f64, alpha: f64) -> Option<f64> {
+        match self.average {
+            Some(average) => Some(average + alpha * (value - average)),
+            None if self.count + 1 >= period => Some((self.seed_sum + value) / period as f64),
+            None => None,
+        }
+    }
+
+    fn push(&mut self, period: usize, value: f64, alpha: f64) -> Option<f64> {
+        let average = self.peek(period, value, alpha);
+        self.count += 1;
+        self.seed_sum += value;
+        self.average = average;
+
+        average
+    }
+}
+
+impl IndicatorState {
+    fn new(indicator: &Indicator) -> Self {
+        match indicator {
+            Indicator::Sma(_) | Indicator::Bollinger { .. } => IndicatorState::Window {
+                closes: VecDeque::new(),
+                sum: 0.0,
+            },
+            Indicator::Ema(_) | Indicator::Atr(_) => IndicatorState::Smoothed {
+                prev_close: None,
+                smoothing: Smoothing::default(),
+            },
+            Indicator::Rsi(_) => IndicatorState::Rsi {
+                prev_close: None,
+                gains: Smoothing::default(),
+                losses: Smoothing::default(),
+            },
+        }
+    }
+
+    /// Calculates the value with the candle without changing the state
+    fn peek(&self, indicator: &Indicator, ohlc: &Ohlc) -> Option<IndicatorValue> {
+        let period = get_period(indicator);
+
+        match self {
+            IndicatorState::Window { closes, sum } => {
+                if closes.len() + 1 < period || period == 0 {
+                    return None;
+                }
+
+                let evicted = closes.len() + 1 - period;
+                let mean =
+                    (sum - closes.iter().take(evicted).sum::<f64>() + ohlc.close) / period as f64;
+                let window = closes
+                    .iter()
+                    .skip(evicted)
+                    .chain(std::iter::once(&ohlc.close));
+
+                Some(get_window_value(indicator, mean, window))
+            }
+            IndicatorState::Smoothed {
+                prev_close,
+                smoothing,
+            } => {
+                let value = get_smoothed_input(indicator, ohlc, *prev_close);
+
+                smoothing
+                    .peek(period, value, get_alpha(indicator))
+                    .map(IndicatorValue::Single)
+            }
+            IndicatorState::Rsi {
+                prev_close,
+                gains,
+                losses,
+            } => {
+                let prev_close = (*prev_close)?;
+                let change = ohlc.close - prev_close;
+                let alpha = get_alpha(indicator);
+                let gain = gains.peek(period, change.max(0.0), alpha)?;
+                let loss = losses.peek(period, (-change).max(0.0), alpha)?;
+
+                Some(IndicatorValue::Single(get_rsi(gain, loss)))
+            }
+        }
+    }
+
+    /// Adds the candle to the state and returns the value with the candle
+    fn push(&mut self, indicator: &Indicator, ohlc: &Ohlc) -> Option<IndicatorValue> {
+        let value = self.peek(indicator, ohlc);
+        let period = get_period(indicator);
+
+        match self {
+            IndicatorState::Window { closes, sum } => {
+                closes.push_back(ohlc.close);
+                *sum += ohlc.close;
+
+                while closes.len() > period {
+                    *sum -= closes.pop_front().unwrap_or_default();
+                }
+            }
+            IndicatorState::Smoothed {
+                prev_close,
+                smoothing,
+            } => {
+                let value = get_smoothed_input(indicator, ohlc, *prev_close);
+                smoothing.push(period, value, get_alpha(indicator));
+                *prev_close = Some(ohlc.close);
+            }
+            IndicatorState::Rsi {
+                prev_close,
+                gains,
+                losses,
+            } => {
+                if let Some(prev_close) = prev_close {
+                    let change = ohlc.close - *prev_close;
+                    let alpha = get_alpha(indicator);
+                    gains.push(period, change.max(0.0), alpha);
+                    losses.push(period, (-change).max(0.0), alpha);
+                }
+
+                *prev_close = Some(ohlc.close);
+            }
+        }
+
+        value
+    }
+}
+
+fn get_period(indicator: &Indicator) -> usize {
+    match indicator {
+        Indicator::Sma(period)
+        | Indicator::Ema(period)
+        | Indicator::Rsi(period)
+        | Indicator::Atr(period)
+        | Indicator::Bollinger { period, .. } => *period,
+    }
+}
+
+fn get_alpha(indicator: &Indicator) -> f64 {
+    let period = get_period(indicator) as f64;
+
+    match indicator {
+        Indicator::Ema(_) => 2.0 / (period + 1.0),
+        _ => 1.0 / period,
+    }
+}
+
+/// Gets the close for EMA or the true range for ATR
+fn get_smoothed_input(indicator: &Indicator, ohlc: &Ohlc, prev_close: Option<f64>) -> f64 {
+    match (indicator, prev_close) {
+        (Indicator::Atr(_), Some(prev_close)) => (ohlc.high - ohlc.low)
+            .max((ohlc.high - prev_close).abs())
+            .max((ohlc.low - prev_close).abs()),
+        (Indicator::Atr(_), None) => ohlc.high - ohlc.low,
+        _ => ohlc.close,
+    }
+}
+
+fn get_window_value<'a>(
+    indicator: &Indicator,
+    mean: f64,
+    window: impl Iterator<Item = &'a f64>,
+) -> IndicatorValue {
+    let Indicator::Bollinger { period, multiplier } = indicator else {
+        return IndicatorValue::Single(mean);
+    };
+
+    let variance = window.map(|close| (close - mean).powi(2)).sum::<f64>() / *period as f64;
+    let deviation = variance.sqrt() * multiplier;
+
+    IndicatorValue::Bands {
+        lower: mean - deviation,
+        middle: mean,
+        upper: mean + deviation,
+    }
+}
+
+fn get_rsi(average_gain: f64, average_loss: f64) -> f64 {
+    if average_loss == 0.0 {
+        return if average_gain == 0.0 { 50.0 } else { 100.0 };
+    }
+
+    100.0 - 100.0 / (1.0 + average_gain / average_loss)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    const CLOSES: [f64; 12] = [
+        44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03,
+    ];
+
+    fn get_closed_values(indicator: Indicator) -> Vec<IndicatorValue> {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut series = IndicatorSeries::new(indicator).unwrap();
+
+        for (i, close) in CLOSES.iter().enumerate() {
+            let ohlc = Ohlc::new(*close, close + 0.25, close - 0.5, *close);
+            series.close(date + Duration::minutes(i as i64), ohlc);
+        }
+
+        series
+            .get_closed()
+            .iter()
+            .map(|(_, value)| *value)
+            .collect()
+    }
+
+    fn assert_single_values(values: Vec<IndicatorValue>, expected: &[f64]) {
+        assert_eq!(values.len(), expected.len());
+
+        for (value, expected) in values.iter().zip(expected) {
+            let IndicatorValue::Single(value) = value else {
+                panic!("single value expected");
+            };
+            assert!((value - expected).abs() < 1e-9, "{value} != {expected}");
+        }
+    }
+
+    #[test]
+    pub fn moving_averages_match_reference_values() {
+        assert_single_values(
+            get_closed_values(Indicator::Sma(10)),
+            &[44.779, 44.934, 45.128],
+        );
+        assert_single_values(
+            get_closed_values(Indicator::Ema(10)),
+            &[44.779, 44.981, 45.171727272727274],
+        );
+    }
+
+    #[test]
+    pub fn rsi_and_atr_match_reference_values() {
+        assert_single_values(
+            get_closed_values(Indicator::Rsi(10)),
+            &[72.0797720797721, 73.26462564413461],
+        );
+        assert_single_values(
+            get_closed_values(Indicator::Atr(10)),
+            &[0.801, 0.7959, 0.79131],
+        );
+    }
+
+    #[test]
+    pub fn bollinger_bands_match_reference_values() {
+        let values = get_closed_values(Indicator::Bollinger {
+            period: 10,
+            multiplier: 2.0,
+        });
+        let IndicatorValue::Bands {
+            lower,
+            middle,
+            upper,
+        } = values[2]
+        else {
+            panic!("bands expected");
+        };
+
+        assert_eq!(values.len(), 3);
+        assert!((middle - 45.128).abs() < 1e-9);
+        assert!((upper - middle - 1.6589104858309882).abs() < 1e-9);
+        assert!((middle - lower - 1.6589104858309882).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn forming_value_is_separate_from_closed_values() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut series = IndicatorSeries::new(Indicator::Sma(2)).unwrap();
+
+        series.update(date, Ohlc::new(1.0, 1.0, 1.0, 1.0));
+        assert_eq!(series.get_forming(), Some((date, None)));
+        series.close(date, Ohlc::new(1.0, 2.0, 1.0, 2.0));
+        assert_eq!(series.get_forming(), None);
+
+        let next_date = date + Duration::minutes(1);
+        series.update(next_date, Ohlc::new(2.0, 4.0, 2.0, 4.0));
+        series.update(next_date, Ohlc::new(2.0, 6.0, 2.0, 6.0));
+        assert_eq!(
+            series.get_forming(),
+            Some((next_date, Some(IndicatorValue::Single(4.0))))
+        );
+        assert!(series.get_closed().is_empty());
+        assert!(!series.update(date, Ohlc::new(5.0, 5.0, 5.0, 5.0)));
+
+        series.close(next_date, Ohlc::new(2.0, 8.0, 2.0, 8.0));
+        assert_eq!(
+            series.get_closed(),
+            &[(next_date, IndicatorValue::Single(5.0))]
+        );
+    }
+
+    #[test]
+    pub fn invalid_indicators() {
+        for indicator in [
+            Indicator::Sma(0),
+            Indicator::Rsi(0),
+            Indicator::Bollinger {
+                period: 0,
+                multiplier: 2.0,
+            },
+            Indicator::Bollinger {
+                period: 10,
+                multiplier: f64::NAN,
+            },
+        ] {
+            assert!(IndicatorSeries::new(indicator).is_none());
+        }
+    }
+}
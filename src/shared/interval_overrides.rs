@@ -0,0 +1,184 @@
+use crate::shared::candle_interval::CandleInterval;
+use ahash::AHashMap;
+
+/// Pattern of instruments or ref_ids which intervals are overridden
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum KeyPattern {
+    Exact(String),
+    /// Glob where `*` matches any sequence of characters and `?` matches a single character
+    Glob(String),
+}
+
+impl KeyPattern {
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            KeyPattern::Exact(pattern) => pattern == key,
+            KeyPattern::Glob(pattern) => matches_glob(pattern.as_bytes(), key.as_bytes()),
+        }
+    }
+}
+
+/// Interval sets overriding the cache intervals for matching keys. Exact overrides take
+/// precedence over globs, globs are matched in the order they were set
+#[derive(Debug, Clone, Default)]
+pub struct IntervalOverrides {
+    exact: AHashMap<String, Vec<CandleInterval>>,
+    globs: Vec<(String, Vec<CandleInterval>)>,
+}
+
+impl IntervalOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.globs.is_empty()
+    }
+
+    /// Sets intervals of the keys matching the pattern replacing the previous override
+    pub fn set(&mut self, pattern: KeyPattern, intervals: Vec<CandleInterval>) {
+        let mut intervals = intervals;
+        intervals.sort();
+        intervals.dedup();
+
+        match pattern {
+            KeyPattern::Exact(key) => {
+                self.exact.insert(key, intervals);
+            }
+            KeyPattern::Glob(glob) => {
+                match self
+                    .globs
+                    .iter_mut()
+                    .find(|(existing, _)| *existing == glob)
+                {
+                    Some((_, existing_intervals)) => *existing_intervals = intervals,
+                    None => self.globs.push((glob, intervals)),
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, pattern: &KeyPattern) -> Option<Vec<CandleInterval>> {
+        match pattern {
+            KeyPattern::Exact(key) => self.exact.remove(key),
+            KeyPattern::Glob(glob) => {
+                let position = self
+                    .globs
+                    .iter()
+                    .position(|(existing, _)| existing == glob)?;
+
+                Some(self.globs.remove(position).1)
+            }
+        }
+    }
+
+    /// Gets overridden intervals of the key
+    pub fn resolve(&self, key: &str) -> Option<&[CandleInterval]> {
+        if self.is_empty() {
+            return None;
+        }
+
+        if let Some(intervals) = self.exact.get(key) {
+            return Some(intervals);
+        }
+
+        self.globs
+            .iter()
+            .find(|(glob, _)| matches_glob(glob.as_bytes(), key.as_bytes()))
+            .map(|(_, intervals)| intervals.as_slice())
+    }
+
+    /// Gets the default intervals and all overridden intervals sorted and without duplicates
+    pub fn get_all_intervals(&self, default_intervals: &[CandleInterval]) -> Vec<CandleInterval> {
+        let mut intervals: Vec<CandleInterval> = default_intervals
+            .iter()
+            .chain(self.exact.values().flatten())
+            .chain(self.globs.iter().flat_map(|(_, intervals)| intervals))
+            .copied()
+            .collect();
+        intervals.sort();
+        intervals.dedup();
+
+        intervals
+    }
+}
+
+fn matches_glob(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut pattern_pos, mut key_pos) = (0, 0);
+    // position of the latest `*` and the key position it is matched up to
+    let mut backtrack = None;
+
+    while key_pos < key.len() {
+        match pattern.get(pattern_pos) {
+            Some(b'*') => {
+                backtrack = Some((pattern_pos, key_pos));
+                pattern_pos += 1;
+            }
+            Some(b'?') => {
+                pattern_pos += 1;
+                key_pos += 1;
+            }
+            Some(byte) if *byte == key[key_pos] => {
+                pattern_pos += 1;
+                key_pos += 1;
+            }
+            _ => match backtrack {
+                Some((star_pos, star_key_pos)) => {
+                    pattern_pos = star_pos + 1;
+                    key_pos = star_key_pos + 1;
+                    backtrack = Some((star_pos, star_key_pos + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[pattern_pos..].iter().all(|byte| *byte == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn matches_glob_1() {
+        assert!(KeyPattern::Glob("EUR*".to_string()).matches("EURUSD"));
+        assert!(KeyPattern::Glob("*USD".to_string()).matches("EURUSD"));
+        assert!(KeyPattern::Glob("E?R*D".to_string()).matches("EURUSD"));
+        assert!(KeyPattern::Glob("*".to_string()).matches(""));
+        assert!(!KeyPattern::Glob("EUR?".to_string()).matches("EURUSD"));
+        assert!(!KeyPattern::Glob("*JPY".to_string()).matches("EURUSD"));
+    }
+
+    #[test]
+    pub fn resolve_prefers_exact_overrides() {
+        let mut overrides = IntervalOverrides::new();
+        overrides.set(
+            KeyPattern::Glob("funded-*".to_string()),
+            vec![CandleInterval::Hour, CandleInterval::Minute],
+        );
+        overrides.set(
+            KeyPattern::Exact("funded-1".to_string()),
+            vec![CandleInterval::Day],
+        );
+
+        assert_eq!(
+            overrides.resolve("funded-2"),
+            Some([CandleInterval::Minute, CandleInterval::Hour].as_slice())
+        );
+        assert_eq!(
+            overrides.resolve("funded-1"),
+            Some([CandleInterval::Day].as_slice())
+        );
+        assert_eq!(overrides.resolve("demo-1"), None);
+        assert_eq!(
+            overrides.get_all_intervals(&[CandleInterval::Month]),
+            vec![
+                CandleInterval::Minute,
+                CandleInterval::Hour,
+                CandleInterval::Day,
+                CandleInterval::Month
+            ]
+        );
+    }
+}
@@ -1,4 +1,25 @@
+pub mod batch;
+pub mod budget;
 pub mod candle_data;
 pub mod candle_index;
 pub mod candle_interval;
+pub mod candle_key;
+pub mod candles_store;
+pub mod changes;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod csv;
+pub mod events;
+pub mod gap_fill;
+pub mod heikin_ashi;
+pub mod indicators;
+pub mod interval_overrides;
+pub mod ohlc;
+pub mod renko;
+pub mod replay;
+pub mod retention;
+pub mod sharded;
+pub mod snapshot;
+pub mod symbols;
 pub mod utils;
+pub mod volume_profile;
@@ -0,0 +1,28 @@
+use crate::shared::candle_data::CandleData;
+use serde_derive::{Deserialize, Serialize};
+
+/// Open, high, low and close values of a candle used by chart transformations
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Ohlc {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+impl Ohlc {
+    pub fn new(open: f64, high: f64, low: f64, close: f64) -> Self {
+        Self {
+            open,
+            high,
+            low,
+            close,
+        }
+    }
+}
+
+impl From<&CandleData> for Ohlc {
+    fn from(value: &CandleData) -> Self {
+        Self::new(value.open, value.high, value.low, value.close)
+    }
+}
@@ -0,0 +1,242 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RenkoBrick {
+    /// Date of the series value which completed the brick
+    pub date: DateTime<Utc>,
+    pub open: f64,
+    pub close: f64,
+}
+
+impl RenkoBrick {
+    pub fn is_up(&self) -> bool {
+        self.close > self.open
+    }
+}
+
+/// Renko bricks of close values of the date-ordered series. Brick prices are the first close
+/// value plus a multiple of the box size, a reversal requires two boxes. The latest value
+/// can be updated without recalculating bricks of the previous values
+#[derive(Debug, Clone)]
+pub struct Renko {
+    box_size: f64,
+    base: Option<f64>,
+    bricks: Vec<RenkoBrick>,
+    latest_date: Option<DateTime<Utc>>,
+    /// Bricks count and base before the latest value
+    previous_state: (usize, Option<f64>),
+}
+
+impl Renko {
+    /// Returns None for not finite or not positive box sizes
+    pub fn new(box_size: f64) -> Option<Self> {
+        if !box_size.is_finite() || box_size <= 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            box_size,
+            base: None,
+            bricks: Vec::new(),
+            latest_date: None,
+            previous_state: (0, None),
+        })
+    }
+
+    /// Returns None for not finite or not positive box sizes
+    pub fn from_series(
+        box_size: f64,
+        series: impl IntoIterator<Item = (DateTime<Utc>, f64)>,
+    ) -> Option<Self> {
+        let mut renko = Self::new(box_size)?;
+
+        for (date, close) in series {
+            renko.update(date, close);
+        }
+
+        Some(renko)
+    }
+
+    pub fn get_box_size(&self) -> f64 {
+        self.box_size
+    }
+
+    /// Adds bricks of the close value of the new date or replaces bricks of the latest value
+    /// of the same date. Returns false for dates before the latest date and not finite values
+    pub fn update(&mut self, date: DateTime<Utc>, close: f64) -> bool {
+        if !close.is_finite() {
+            return false;
+        }
+
+        match self.latest_date {
+            Some(latest_date) if date < latest_date => return false,
+            Some(latest_date) if date == latest_date => {
+                let (bricks_count, base) = self.previous_state;
+                self.bricks.truncate(bricks_count);
+                self.base = base;
+            }
+            _ => self.previous_state = (self.bricks.len(), self.base),
+        }
+
+        self.latest_date = Some(date);
+
+        let Some(base) = self.base else {
+            self.base = Some(close);
+            return true;
+        };
+
+        loop {
+            let (bottom, top) = match self.bricks.last() {
+                Some(brick) => {
+                    let level = |price: f64| ((price - base) / self.box_size).round() as i64;
+                    (
+                        level(brick.open.min(brick.close)),
+                        level(brick.open.max(brick.close)),
+                    )
+                }
+                None => (0, 0),
+            };
+            let price = |level: i64| base + level as f64 * self.box_size;
+
+            let (open, close) = if close >= price(top + 1) {
+                (price(top), price(top + 1))
+            } else if close <= price(bottom - 1) {
+                (price(bottom), price(bottom - 1))
+            } else {
+                break;
+            };
+
+            self.bricks.push(RenkoBrick { date, open, close });
+        }
+
+        true
+    }
+
+    pub fn get_bricks(&self) -> &[RenkoBrick] {
+        &self.bricks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::candle::{AccountData, AccountValue};
+    use crate::accounts::candles_cache::AccountCandlesCache;
+    use crate::prices::candle::PriceSide;
+    use crate::prices::candles_cache::BidAskCandlesCache;
+    use crate::shared::candle_interval::CandleInterval;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn bricks_with_reversal() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let closes = [10.0, 12.5, 11.5, 10.5, 9.9, 13.0];
+        let renko = Renko::from_series(
+            1.0,
+            closes
+                .iter()
+                .enumerate()
+                .map(|(i, close)| (date + Duration::minutes(i as i64), *close)),
+        )
+        .unwrap();
+        let bricks: Vec<(f64, f64)> = renko
+            .get_bricks()
+            .iter()
+            .map(|brick| (brick.open, brick.close))
+            .collect();
+
+        assert_eq!(
+            bricks,
+            vec![
+                (10.0, 11.0),
+                (11.0, 12.0),
+                (11.0, 10.0),
+                (11.0, 12.0),
+                (12.0, 13.0)
+            ]
+        );
+        assert_eq!(renko.get_bricks()[2].date, date + Duration::minutes(4));
+    }
+
+    #[test]
+    pub fn update_replaces_latest_bricks() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut renko = Renko::from_series(0.5, [(date, 1.0)]).unwrap();
+        let next_date = date + Duration::minutes(1);
+
+        renko.update(next_date, 2.2);
+        assert_eq!(renko.get_bricks().len(), 2);
+
+        renko.update(next_date, 1.6);
+        assert_eq!(renko.get_bricks().len(), 1);
+
+        renko.update(next_date, 0.4);
+        assert_eq!(renko.get_bricks().len(), 1);
+        assert!(!renko.get_bricks()[0].is_up());
+        assert!(!renko.update(date, 5.0));
+        assert!(!renko.update(next_date, f64::INFINITY));
+    }
+
+    #[test]
+    pub fn invalid_box_sizes() {
+        for box_size in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Renko::new(box_size).is_none());
+        }
+    }
+
+    #[test]
+    pub fn bricks_of_candle_series() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut price_cache = BidAskCandlesCache::new(vec![CandleInterval::Minute]);
+        let mut account_cache = AccountCandlesCache::new(vec![CandleInterval::Minute]);
+
+        for (minute, value) in [1.0, 3.0, 2.5, 0.0].iter().enumerate() {
+            let datetime = date + Duration::minutes(minute as i64);
+            price_cache.insert_or_update(datetime, "EURUSD", value - 0.5, value + 0.5, 1.0, 1.0);
+            let data = AccountData {
+                equity: *value,
+                balance: 1.0,
+                pnl: value - 1.0,
+            };
+            account_cache.update_or_create(datetime, "1", data);
+        }
+
+        let price_renko = Renko::from_series(
+            1.0,
+            price_cache
+                .get_range(
+                    "EURUSD",
+                    CandleInterval::Minute,
+                    date,
+                    date + Duration::minutes(3),
+                )
+                .into_iter()
+                .map(|candle| (candle.date, candle.get_ohlc(PriceSide::Mid).close)),
+        )
+        .unwrap();
+        let account_renko = Renko::from_series(
+            1.0,
+            account_cache
+                .get_range(
+                    "1",
+                    CandleInterval::Minute,
+                    date,
+                    date + Duration::minutes(3),
+                )
+                .into_iter()
+                .map(|candle| (candle.date, candle.get_ohlc(AccountValue::Equity).close)),
+        )
+        .unwrap();
+
+        assert_eq!(price_renko.get_bricks(), account_renko.get_bricks());
+        assert_eq!(
+            account_renko
+                .get_bricks()
+                .iter()
+                .map(|brick| (brick.open, brick.close))
+                .collect::<Vec<_>>(),
+            vec![(1.0, 2.0), (2.0, 3.0), (2.0, 1.0), (1.0, 0.0)]
+        );
+    }
+}
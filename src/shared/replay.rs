@@ -0,0 +1,137 @@
+use crate::shared::volume_profile::VolumeProfile;
+use chrono::{DateTime, Utc};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// Event at the position of the log is earlier than the previous one
+    Unordered {
+        position: usize,
+        date: DateTime<Utc>,
+    },
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Unordered { position, date } => write!(
+                f,
+                "replay event {position} at {date} is earlier than the previous event"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Checks that events of the replayed log are ordered by date
+#[derive(Debug, Default)]
+pub(crate) struct ReplayOrder {
+    position: usize,
+    last_date: Option<DateTime<Utc>>,
+}
+
+impl ReplayOrder {
+    pub fn check(&mut self, date: DateTime<Utc>) -> Result<(), ReplayError> {
+        if matches!(self.last_date, Some(last_date) if date < last_date) {
+            return Err(ReplayError::Unordered {
+                position: self.position,
+                date,
+            });
+        }
+
+        self.position += 1;
+        self.last_date = Some(date);
+
+        Ok(())
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.position
+    }
+}
+
+/// 64-bit FNV-1a hash which is stable between platforms and library versions
+#[derive(Debug, Clone)]
+pub(crate) struct ChecksumHasher {
+    hash: u64,
+}
+
+impl Default for ChecksumHasher {
+    fn default() -> Self {
+        Self {
+            hash: 0xcbf29ce484222325,
+        }
+    }
+}
+
+impl ChecksumHasher {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write(&(value.len() as u64).to_le_bytes());
+        self.write(value.as_bytes());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+
+    pub fn write_date(&mut self, date: DateTime<Utc>) {
+        self.write(&date.timestamp().to_le_bytes());
+        self.write(&date.timestamp_subsec_nanos().to_le_bytes());
+    }
+
+    pub fn write_volume_profile(&mut self, profile: Option<&VolumeProfile>) {
+        self.write(&[profile.is_some() as u8]);
+
+        if let Some(profile) = profile {
+            self.write_f64(profile.get_bucket_size());
+
+            for (price, volume) in profile.get_levels() {
+                self.write_f64(price);
+                self.write_f64(volume);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    pub fn checksum_hasher_is_stable() {
+        let mut hasher = ChecksumHasher::default();
+        hasher.write(b"a");
+
+        assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    pub fn replay_order_check() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut order = ReplayOrder::default();
+
+        assert!(order.check(date).is_ok());
+        assert!(order.check(date).is_ok());
+        assert_eq!(
+            order.check(date - Duration::seconds(1)),
+            Err(ReplayError::Unordered {
+                position: 2,
+                date: date - Duration::seconds(1)
+            })
+        );
+        assert_eq!(order.get_count(), 2);
+    }
+}
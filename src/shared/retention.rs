@@ -0,0 +1,177 @@
+use crate::shared::candle_interval::CandleInterval;
+use crate::shared::symbols::Symbol;
+use ahash::AHashMap;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetentionPolicy {
+    /// Keeps at most the specified number of the latest candles per key (instrument or ref_id).
+    /// The count must not be zero
+    MaxCount(usize),
+    /// Keeps candles not older than the specified duration relative to the latest candle of the key.
+    /// The duration must not be negative
+    MaxAge(Duration),
+}
+
+impl RetentionPolicy {
+    /// Checks that the max count is not zero and the max age is not negative
+    pub fn is_valid(&self) -> bool {
+        match self {
+            RetentionPolicy::MaxCount(count) => *count > 0,
+            RetentionPolicy::MaxAge(age) => *age >= Duration::zero(),
+        }
+    }
+
+    /// Calculates the oldest candle date to keep. Dates must belong to a single key.
+    /// Returns None if all dates are kept or the policy is not valid
+    pub fn get_min_date(
+        &self,
+        interval: CandleInterval,
+        dates: &BTreeSet<DateTime<Utc>>,
+    ) -> Option<DateTime<Utc>> {
+        if !self.is_valid() {
+            return None;
+        }
+
+        match self {
+            RetentionPolicy::MaxCount(count) if dates.len() > *count => {
+                dates.iter().rev().nth(*count - 1).copied()
+            }
+            RetentionPolicy::MaxCount(_) => None,
+            RetentionPolicy::MaxAge(age) => {
+                let latest_date = dates.last()?;
+                let min_date = latest_date.checked_sub_signed(*age)?;
+
+                Some(interval.get_start_date(min_date))
+            }
+        }
+    }
+}
+
+/// Ordered candle dates of keys (instruments or ref_ids) and intervals with retention
+/// policies, so expired candles of a key are found without scanning the whole cache
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RetentionIndex {
+    dates: AHashMap<(Symbol, CandleInterval), BTreeSet<DateTime<Utc>>>,
+}
+
+impl RetentionIndex {
+    pub(crate) fn insert(&mut self, key: Symbol, interval: CandleInterval, date: DateTime<Utc>) {
+        self.dates.entry((key, interval)).or_default().insert(date);
+    }
+
+    pub(crate) fn remove(&mut self, key: Symbol, interval: CandleInterval, date: DateTime<Utc>) {
+        let Some(dates) = self.dates.get_mut(&(key, interval)) else {
+            return;
+        };
+        dates.remove(&date);
+
+        if dates.is_empty() {
+            self.dates.remove(&(key, interval));
+        }
+    }
+
+    pub(crate) fn remove_interval(&mut self, interval: CandleInterval) {
+        self.dates
+            .retain(|(_key, key_interval), _dates| *key_interval != interval);
+    }
+
+    pub(crate) fn get_keys(&self) -> Vec<(Symbol, CandleInterval)> {
+        self.dates.keys().copied().collect()
+    }
+
+    /// Removes dates of the key expired by the policy. Returns removed dates in ascending order
+    pub(crate) fn take_expired(
+        &mut self,
+        key: Symbol,
+        interval: CandleInterval,
+        policy: &RetentionPolicy,
+    ) -> Vec<DateTime<Utc>> {
+        let Some(dates) = self.dates.get_mut(&(key, interval)) else {
+            return Vec::new();
+        };
+        let Some(min_date) = policy.get_min_date(interval, dates) else {
+            return Vec::new();
+        };
+        let kept_dates = dates.split_off(&min_date);
+
+        std::mem::replace(dates, kept_dates).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shared::candle_interval::CandleInterval;
+    use crate::shared::retention::{RetentionIndex, RetentionPolicy};
+    use crate::shared::symbols::Symbol;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn get_min_date_max_count() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let dates: BTreeSet<_> = (0..5).map(|i| date + Duration::minutes(i)).collect();
+        let policy = RetentionPolicy::MaxCount(2);
+
+        let min_date = policy.get_min_date(CandleInterval::Minute, &dates);
+
+        assert_eq!(min_date, Some(date + Duration::minutes(3)));
+        assert_eq!(
+            RetentionPolicy::MaxCount(5).get_min_date(CandleInterval::Minute, &dates),
+            None
+        );
+        assert_eq!(
+            RetentionPolicy::MaxCount(0).get_min_date(CandleInterval::Minute, &dates),
+            None
+        );
+    }
+
+    #[test]
+    fn get_min_date_max_age() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let dates: BTreeSet<_> = (0..5).map(|i| date + Duration::hours(i)).collect();
+        let policy = RetentionPolicy::MaxAge(Duration::minutes(90));
+
+        let min_date = policy.get_min_date(CandleInterval::Hour, &dates);
+
+        assert_eq!(min_date, Some(date + Duration::hours(2)));
+    }
+
+    #[test]
+    fn take_expired_dates_of_key() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let (key, other_key) = (Symbol::new("1"), Symbol::new("2"));
+        let mut index = RetentionIndex::default();
+
+        for i in 0..5 {
+            index.insert(key, CandleInterval::Minute, date + Duration::minutes(i));
+            index.insert(
+                other_key,
+                CandleInterval::Minute,
+                date + Duration::minutes(i),
+            );
+        }
+
+        let policy = RetentionPolicy::MaxCount(2);
+        let expired = index.take_expired(key, CandleInterval::Minute, &policy);
+
+        assert_eq!(
+            expired,
+            vec![
+                date,
+                date + Duration::minutes(1),
+                date + Duration::minutes(2)
+            ]
+        );
+        assert!(index
+            .take_expired(key, CandleInterval::Minute, &policy)
+            .is_empty());
+        assert_eq!(
+            index
+                .take_expired(other_key, CandleInterval::Minute, &policy)
+                .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use ahash::RandomState;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Splits values between locked shards by string keys (instruments or ref_ids).
+/// Shards stay usable after a thread panicked while holding their locks
+pub struct Sharded<T> {
+    shards: Vec<RwLock<T>>,
+    hasher: RandomState,
+}
+
+impl<T> Sharded<T> {
+    pub fn new(shards_count: usize, create_shard: impl Fn() -> T) -> Self {
+        let shards_count = shards_count.max(1);
+
+        Self {
+            shards: (0..shards_count)
+                .map(|_| RwLock::new(create_shard()))
+                .collect(),
+            hasher: RandomState::with_seeds(0, 0, 0, 0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    pub fn read(&self, key: &str) -> RwLockReadGuard<'_, T> {
+        self.shards[self.get_shard_no(key)]
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn write(&self, key: &str) -> RwLockWriteGuard<'_, T> {
+        self.shards[self.get_shard_no(key)]
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks all shards for reading. Shards are locked in the same order by every caller,
+    /// so the guards give a consistent view of all shards
+    pub fn read_all(&self) -> Vec<RwLockReadGuard<'_, T>> {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+
+    /// Locks shards for writing one by one and applies the action to each of them
+    pub fn for_each_mut(&self, mut action: impl FnMut(&mut T)) {
+        for shard in self.shards.iter() {
+            let mut shard = shard.write().unwrap_or_else(PoisonError::into_inner);
+            action(&mut shard);
+        }
+    }
+
+    fn get_shard_no(&self, key: &str) -> usize {
+        (self.hasher.hash_one(key) % self.shards.len() as u64) as usize
+    }
+}
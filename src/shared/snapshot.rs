@@ -0,0 +1,145 @@
+use crate::shared::candle_interval::CandleInterval;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// Version of the snapshot format written by this library. Fields added to the format
+/// must have defaults, so snapshots of older versions are still readable
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheSnapshot<C> {
+    pub version: u32,
+    #[serde(default)]
+    pub intervals: Vec<CandleInterval>,
+    #[serde(default)]
+    pub last_update_date: Option<DateTime<Utc>>,
+    #[serde(default = "Vec::new")]
+    pub candles: Vec<SnapshotCandle<C>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotCandle<C> {
+    /// Finalized candles are not updated after restore
+    #[serde(default)]
+    pub closed: bool,
+    pub candle: C,
+    /// Exact datetimes of the candle data. Candles serialize them as float seconds, which
+    /// lose sub-microsecond precision
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub datetimes: Vec<DateTime<Utc>>,
+}
+
+/// Candle with data datetimes stored exactly by snapshots
+pub trait SnapshotDatetimes {
+    fn get_datetimes(&self) -> Vec<DateTime<Utc>>;
+
+    /// Replaces data datetimes in the order of `get_datetimes`
+    fn set_datetimes(&mut self, datetimes: &[DateTime<Utc>]);
+}
+
+impl<C: SnapshotDatetimes> SnapshotCandle<C> {
+    /// Returns the candle with exact data datetimes, when the snapshot stores them
+    pub fn into_candle(self) -> (C, bool) {
+        let mut candle = self.candle;
+
+        if candle.get_datetimes().len() == self.datetimes.len() {
+            candle.set_datetimes(&self.datetimes);
+        }
+
+        (candle, self.closed)
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// Snapshot is written by a newer version of the library
+    UnsupportedVersion(u32),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "snapshot io error: {error}"),
+            SnapshotError::Format(error) => write!(f, "snapshot format error: {error}"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot version {version} is not supported, max supported version is {SNAPSHOT_VERSION}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(error: serde_json::Error) -> Self {
+        if error.is_io() {
+            SnapshotError::Io(error.into())
+        } else {
+            SnapshotError::Format(error)
+        }
+    }
+}
+
+pub fn write_snapshot<C: Serialize>(
+    writer: impl Write,
+    snapshot: &CacheSnapshot<C>,
+) -> Result<(), SnapshotError> {
+    let mut writer = writer;
+    serde_json::to_writer(&mut writer, snapshot)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn read_snapshot<C: DeserializeOwned>(
+    reader: impl Read,
+) -> Result<CacheSnapshot<C>, SnapshotError> {
+    let snapshot: CacheSnapshot<C> = serde_json::from_reader(reader)?;
+
+    if snapshot.version > SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn read_snapshot_with_missing_and_unknown_fields() {
+        let json = r#"{"version":1,"intervals":[0],"candles":[{"candle":7,"extra":"x"}]}"#;
+
+        let snapshot: CacheSnapshot<i32> = read_snapshot(json.as_bytes()).unwrap();
+
+        assert_eq!(snapshot.intervals, vec![CandleInterval::Minute]);
+        assert_eq!(snapshot.last_update_date, None);
+        assert_eq!(snapshot.candles.len(), 1);
+        assert!(!snapshot.candles[0].closed);
+        assert_eq!(snapshot.candles[0].candle, 7);
+        assert!(snapshot.candles[0].datetimes.is_empty());
+    }
+
+    #[test]
+    pub fn read_snapshot_of_newer_version() {
+        let json = format!(r#"{{"version":{}}}"#, SNAPSHOT_VERSION + 1);
+
+        let result = read_snapshot::<i32>(json.as_bytes());
+
+        assert!(matches!(result, Err(SnapshotError::UnsupportedVersion(_))));
+    }
+}
@@ -0,0 +1,284 @@
+use ahash::AHashMap;
+use serde::de::{Deserialize, Deserializer, Error, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::Deref;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+/// Process wide registry of instrument and ref_id names. Registered names are never freed,
+/// so names are registered only by stored candles and lookups of unknown names use `get`.
+/// Names of symbols are read without locks and every thread caches symbols it has resolved,
+/// so only registering a new name takes the write lock
+pub struct SymbolRegistry;
+
+const SEGMENTS_COUNT: usize = 32;
+
+struct Registry {
+    /// Names by ids in segments of doubling sizes, the segment `k` starts from the id `2^k - 1`.
+    /// Segments are never reallocated
+    segments: [OnceLock<Box<[OnceLock<&'static str>]>>; SEGMENTS_COUNT],
+    ids_by_names: RwLock<AHashMap<&'static str, u32>>,
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+thread_local! {
+    static RESOLVED_SYMBOLS: RefCell<AHashMap<&'static str, Symbol>> =
+        RefCell::new(AHashMap::new());
+}
+
+fn get_registry() -> &'static Registry {
+    REGISTRY.get_or_init(|| Registry {
+        segments: [const { OnceLock::new() }; SEGMENTS_COUNT],
+        ids_by_names: RwLock::new(AHashMap::new()),
+    })
+}
+
+/// Gets the segment number and the position in the segment of the id
+fn get_position(id: u32) -> (usize, usize) {
+    let number = id as u64 + 1;
+    let segment = (u64::BITS - 1 - number.leading_zeros()) as usize;
+
+    (segment, (number - (1 << segment)) as usize)
+}
+
+impl SymbolRegistry {
+    /// Gets the symbol of the name registering it if needed
+    pub fn register(name: &str) -> Symbol {
+        if let Some(symbol) = Self::get(name) {
+            return symbol;
+        }
+
+        let registry = get_registry();
+        let mut ids_by_names = registry
+            .ids_by_names
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(id) = ids_by_names.get(name) {
+            return Symbol(*id);
+        }
+
+        let name: &'static str = Box::leak(name.into());
+        let id = ids_by_names.len() as u32;
+        let (segment, position) = get_position(id);
+        let segment = registry.segments[segment]
+            .get_or_init(|| (0..1usize << segment).map(|_| OnceLock::new()).collect());
+        let _ = segment[position].set(name);
+        ids_by_names.insert(name, id);
+
+        Symbol(id)
+    }
+
+    /// Gets the symbol of the already registered name without registering it
+    pub fn get(name: &str) -> Option<Symbol> {
+        let symbol = RESOLVED_SYMBOLS.with(|symbols| symbols.borrow().get(name).copied());
+
+        if symbol.is_some() {
+            return symbol;
+        }
+
+        let registry = get_registry();
+        let (name, id) = registry
+            .ids_by_names
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_key_value(name)
+            .map(|(name, id)| (*name, *id))?;
+        RESOLVED_SYMBOLS.with(|symbols| symbols.borrow_mut().insert(name, Symbol(id)));
+
+        Some(Symbol(id))
+    }
+
+    pub fn len() -> usize {
+        get_registry()
+            .ids_by_names
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+/// Compact id of the name registered in the `SymbolRegistry`. Serializes as the name,
+/// compares by the id and orders by the name, so the order doesn't depend on the
+/// registration order of names
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        SymbolRegistry::register(name)
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.0
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        let (segment, position) = get_position(self.0);
+
+        get_registry().segments[segment]
+            .get()
+            .and_then(|segment| segment[position].get())
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Symbol {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.0 == other.0 {
+            return Ordering::Equal;
+        }
+
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(value: &String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(value: String) -> Self {
+        Self::new(&value)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SymbolVisitor;
+
+        impl Visitor<'_> for SymbolVisitor {
+            type Value = Symbol;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(Symbol::new(value))
+            }
+        }
+
+        deserializer.deserialize_str(SymbolVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn register_returns_same_symbol() {
+        let symbol = SymbolRegistry::register("SYMBOLS_TEST_EURUSD");
+
+        assert_eq!(Symbol::new("SYMBOLS_TEST_EURUSD"), symbol);
+        assert_eq!(SymbolRegistry::get("SYMBOLS_TEST_EURUSD"), Some(symbol));
+        assert_eq!(symbol.as_str(), "SYMBOLS_TEST_EURUSD");
+        assert_eq!(SymbolRegistry::get("SYMBOLS_TEST_UNKNOWN"), None);
+    }
+
+    #[test]
+    pub fn names_are_read_from_other_threads() {
+        let symbols: Vec<Symbol> = (0..100)
+            .map(|i| Symbol::new(&format!("SYMBOLS_TEST_THREAD_{i}")))
+            .collect();
+
+        std::thread::spawn(move || {
+            for (i, symbol) in symbols.iter().enumerate() {
+                let name = format!("SYMBOLS_TEST_THREAD_{i}");
+                assert_eq!(symbol.as_str(), name);
+                assert_eq!(SymbolRegistry::get(&name), Some(*symbol));
+            }
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    pub fn symbols_are_ordered_by_names() {
+        let b = Symbol::new("SYMBOLS_TEST_B");
+        let a = Symbol::new("SYMBOLS_TEST_A");
+
+        assert!(a < b);
+        assert_eq!(a.cmp(&Symbol::new("SYMBOLS_TEST_A")), Ordering::Equal);
+        assert_eq!(serde_json::to_string(&a).unwrap(), "\"SYMBOLS_TEST_A\"");
+        assert_eq!(
+            serde_json::from_str::<Symbol>("\"SYMBOLS_TEST_B\"").unwrap(),
+            b
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Volume histogram by price levels. Level prices are lower bounds of buckets of the bucket size
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeProfile {
+    bucket_size: f64,
+    /// Volumes by bucket numbers of level prices
+    volumes: BTreeMap<i64, f64>,
+}
+
+impl VolumeProfile {
+    /// Returns None for not finite or not positive bucket sizes
+    pub fn new(bucket_size: f64) -> Option<Self> {
+        if !bucket_size.is_finite() || bucket_size <= 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            bucket_size,
+            volumes: BTreeMap::new(),
+        })
+    }
+
+    /// Gets the profile of the same bucket size without levels
+    pub fn to_empty(&self) -> Self {
+        Self {
+            bucket_size: self.bucket_size,
+            volumes: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_bucket_size(&self) -> f64 {
+        self.bucket_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.volumes.is_empty()
+    }
+
+    pub fn add(&mut self, price: f64, volume: f64) {
+        let bucket = (price / self.bucket_size).floor() as i64;
+        *self.volumes.entry(bucket).or_insert(0.0) += volume;
+    }
+
+    /// Adds volumes of the other profile. Levels of a profile with another bucket size
+    /// are added at their bucket centers
+    pub fn merge(&mut self, other: &VolumeProfile) {
+        if other.bucket_size == self.bucket_size {
+            for (bucket, volume) in other.volumes.iter() {
+                *self.volumes.entry(*bucket).or_insert(0.0) += volume;
+            }
+
+            return;
+        }
+
+        for (bucket, volume) in other.volumes.iter() {
+            self.add((*bucket as f64 + 0.5) * other.bucket_size, *volume);
+        }
+    }
+
+    /// Gets bucket numbers and volumes ordered by bucket numbers
+    pub(crate) fn get_buckets(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        self.volumes
+            .iter()
+            .map(|(bucket, volume)| (*bucket, *volume))
+    }
+
+    pub(crate) fn add_to_bucket(&mut self, bucket: i64, volume: f64) {
+        *self.volumes.entry(bucket).or_insert(0.0) += volume;
+    }
+
+    /// Gets level prices and volumes ordered by price
+    pub fn get_levels(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.volumes
+            .iter()
+            .map(|(bucket, volume)| (self.get_price(*bucket), *volume))
+    }
+
+    pub fn get_total_volume(&self) -> f64 {
+        self.volumes.values().sum()
+    }
+
+    /// Gets the level price with the biggest volume, the lowest one for equal volumes
+    pub fn get_point_of_control(&self) -> Option<f64> {
+        self.get_point_of_control_bucket()
+            .map(|bucket| self.get_price(bucket))
+    }
+
+    /// Gets the lowest and the highest level prices of the area around the point of control
+    /// containing the share of the total volume, e.g. 0.7. The area is extended by the adjacent
+    /// level with the bigger volume
+    pub fn get_value_area(&self, share: f64) -> Option<(f64, f64)> {
+        let poc_bucket = self.get_point_of_control_bucket()?;
+        let levels: Vec<(i64, f64)> = self.volumes.iter().map(|(b, v)| (*b, *v)).collect();
+        let target_volume = self.get_total_volume() * share;
+        let poc_position = levels
+            .iter()
+            .position(|(bucket, _)| *bucket == poc_bucket)?;
+        let (mut low, mut high) = (poc_position, poc_position);
+        let mut volume = levels[poc_position].1;
+
+        while volume < target_volume {
+            let below = low.checked_sub(1).map(|position| levels[position].1);
+            let above = levels.get(high + 1).map(|(_, volume)| *volume);
+
+            match (below, above) {
+                (Some(below), Some(above)) if below > above => {
+                    low -= 1;
+                    volume += below;
+                }
+                (_, Some(above)) => {
+                    high += 1;
+                    volume += above;
+                }
+                (Some(below), None) => {
+                    low -= 1;
+                    volume += below;
+                }
+                (None, None) => break,
+            }
+        }
+
+        Some((
+            self.get_price(levels[low].0),
+            self.get_price(levels[high].0),
+        ))
+    }
+
+    fn get_point_of_control_bucket(&self) -> Option<i64> {
+        let mut result: Option<(i64, f64)> = None;
+
+        for (bucket, volume) in self.volumes.iter() {
+            if result.is_none_or(|(_, max_volume)| *volume > max_volume) {
+                result = Some((*bucket, *volume));
+            }
+        }
+
+        result.map(|(bucket, _)| bucket)
+    }
+
+    fn get_price(&self, bucket: i64) -> f64 {
+        bucket as f64 * self.bucket_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn point_of_control_and_value_area() {
+        let mut profile = VolumeProfile::new(0.5).unwrap();
+
+        for (price, volume) in [
+            (100.1, 1.0),
+            (100.6, 2.0),
+            (101.2, 6.0),
+            (101.7, 3.0),
+            (102.4, 1.0),
+            (101.3, 2.0),
+        ] {
+            profile.add(price, volume);
+        }
+
+        assert_eq!(profile.get_total_volume(), 15.0);
+        assert_eq!(profile.get_point_of_control(), Some(101.0));
+        assert_eq!(profile.get_value_area(0.7), Some((101.0, 101.5)));
+        assert_eq!(profile.get_value_area(1.0), Some((100.0, 102.0)));
+        assert_eq!(VolumeProfile::new(0.5).unwrap().get_value_area(0.7), None);
+    }
+
+    #[test]
+    pub fn merge_with_another_bucket_size() {
+        let mut fine_profile = VolumeProfile::new(0.25).unwrap();
+        fine_profile.add(100.1, 1.0);
+        fine_profile.add(100.3, 2.0);
+        fine_profile.add(100.6, 4.0);
+        let mut profile = VolumeProfile::new(0.5).unwrap();
+        profile.add(100.2, 1.0);
+
+        profile.merge(&fine_profile);
+
+        assert_eq!(
+            profile.get_levels().collect::<Vec<_>>(),
+            vec![(100.0, 4.0), (100.5, 4.0)]
+        );
+    }
+
+    #[test]
+    pub fn invalid_bucket_sizes() {
+        for bucket_size in [0.0, -0.5, f64::NAN, f64::INFINITY] {
+            assert!(VolumeProfile::new(bucket_size).is_none());
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use candles_sdk::prices::candles_cache::BidAskCandlesCache;
+use candles_sdk::prices::trade::TradeSide;
+use candles_sdk::prices::trade_candles_cache::TradeCandlesCache;
+use candles_sdk::shared::candle_interval::CandleInterval;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts allocations of the current thread so parallel tests don't affect each other
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn get_allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+#[test]
+pub fn insert_or_update_steady_state_does_not_allocate() {
+    let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+    let instruments = ["EURUSD", "GBPUSD", "USDJPY"];
+    let mut cache = BidAskCandlesCache::new(vec![
+        CandleInterval::Minute,
+        CandleInterval::Hour,
+        CandleInterval::Day,
+        CandleInterval::SevenDays,
+        CandleInterval::Month,
+    ]);
+    cache.enable_change_tracking();
+
+    for instrument in instruments {
+        cache.insert_or_update(date, instrument, 1.0, 1.1, 1.0, 1.0);
+    }
+
+    let updates_count = 100_000;
+    let allocations = get_allocations();
+
+    for i in 0..updates_count {
+        let datetime = date + Duration::milliseconds(i % 59_000);
+        let price = 1.0 + (i % 100) as f64 / 1000.0;
+        let instrument = instruments[i as usize % instruments.len()];
+        cache.insert_or_update(datetime, instrument, price, price + 0.1, 1.0, 1.0);
+    }
+
+    let allocations = get_allocations() - allocations;
+
+    assert_eq!(allocations, 0);
+    assert_eq!(cache.len(), instruments.len() * cache.get_intervals().len());
+}
+
+#[test]
+pub fn trade_insert_or_update_steady_state_does_not_allocate() {
+    let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+    let instruments = ["BTCUSD", "ETHUSD"];
+    let mut cache = TradeCandlesCache::new(vec![
+        CandleInterval::Minute,
+        CandleInterval::Hour,
+        CandleInterval::Month,
+    ]);
+
+    for instrument in instruments {
+        cache.insert_or_update(date, instrument, 100.0, 1.0, TradeSide::Buy);
+    }
+
+    let allocations = get_allocations();
+
+    for i in 0..10_000 {
+        let datetime = date + Duration::milliseconds(i % 59_000);
+        let price = 100.0 + (i % 100) as f64;
+        let instrument = instruments[i as usize % instruments.len()];
+        cache.insert_or_update(datetime, instrument, price, 1.0, TradeSide::Sell);
+    }
+
+    assert_eq!(get_allocations() - allocations, 0);
+    assert_eq!(cache.len(), instruments.len() * cache.get_intervals().len());
+}